@@ -0,0 +1,298 @@
+//! Write the calfits test fixtures in tests/data.
+//!
+//! pyuvdata is not a dependency of this crate, so the files are laid out by
+//! hand the way `UVCal.write_calfits` of pyuvdata 3 writes them through
+//! astropy, without going through this crate's FITS code: the solutions are
+//! a big-endian image of numpy shape (Nants, Nspws, Nfreqs, Ntimes, Njones,
+//! Narrays) whose axes are only described by CRVAL/CDELT keywords, cards
+//! carry comments, and antennas go in an ANTENNAS table. They check this
+//! crate against that reading of the format, not against files pyuvdata
+//! wrote.
+//!
+//! Run with `cargo run --example calfits_fixtures` from the crate root.
+use std::fs;
+
+const LATITUDE: f64 = -30.721_526_120_689_57;
+const LONGITUDE: f64 = 21.428_303_826_863_015;
+const ALTITUDE: f64 = 1_051.690_000_021_830_2;
+const TIME: f64 = 2_459_122.0;
+const INTTIME: f64 = 10.737_418_24;
+const FREQ: f64 = 100e6;
+const CHWIDTH: f64 = 97_656.25;
+const NANTS: usize = 3;
+const NFREQS: usize = 3;
+const NTIMES: usize = 2;
+const NJONES: usize = 2;
+
+enum Value {
+    Logical(bool),
+    Int(i64),
+    Float(f64),
+    Str(&'static str),
+    Text(String),
+}
+
+/// A header card as astropy formats it, values right aligned to column 30.
+fn card(key: &str, value: Value, comment: &str) -> String {
+    let value = match value {
+        Value::Logical(value) => format!("{:>20}", if value { "T" } else { "F" }),
+        Value::Int(value) => format!("{:>20}", value),
+        Value::Float(value) => {
+            let text = format!("{:?}", value).to_uppercase();
+            let text = match text.contains('.') {
+                true => text,
+                false => text.replacen('E', ".0E", 1),
+            };
+            format!("{:>20}", text)
+        }
+        Value::Str(text) => format!("{:<20}", format!("'{:<8}'", text)),
+        Value::Text(text) => format!("{:<20}", format!("'{:<8}'", text)),
+    };
+    let card = match comment.is_empty() {
+        true => format!("{:<8}= {}", key, value),
+        false => format!("{:<8}= {} / {}", key, value, comment),
+    };
+    format!("{:<80.80}", card)
+}
+
+fn header_bytes(cards: &[String]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = cards
+        .iter()
+        .map(String::as_str)
+        .chain(std::iter::once("END"))
+        .flat_map(|card| format!("{:<80}", card).into_bytes())
+        .collect();
+    bytes.resize(bytes.len().div_ceil(2880) * 2880, b' ');
+    bytes
+}
+
+fn pad_data(mut bytes: Vec<u8>) -> Vec<u8> {
+    bytes.resize(bytes.len().div_ceil(2880) * 2880, 0);
+    bytes
+}
+
+fn ecef(lat: f64, lon: f64, alt: f64) -> (f64, f64, f64) {
+    let (a, e2) = (6_378_137.0, 6.694_379_990_14e-3);
+    let (lat, lon) = (lat.to_radians(), lon.to_radians());
+    let n = a / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+    (
+        (n + alt) * lat.cos() * lon.cos(),
+        (n + alt) * lat.cos() * lon.sin(),
+        (n * (1.0 - e2) + alt) * lat.sin(),
+    )
+}
+
+/// NAXISn and the pyuvdata axis keywords of a calfits image of numpy shape
+/// (Nants, 1, nfreqs, Ntimes, Njones, narrays).
+fn image_axes(narrays: usize, nfreqs: usize) -> Vec<String> {
+    let mut cards = vec![
+        card("BITPIX", Value::Int(-64), "array data type"),
+        card("NAXIS", Value::Int(6), "number of array dimensions"),
+    ];
+    for (ind, len) in [narrays, NJONES, NTIMES, nfreqs, 1, NANTS]
+        .iter()
+        .enumerate()
+    {
+        cards.push(card(
+            &format!("NAXIS{}", ind + 1),
+            Value::Int(*len as i64),
+            "",
+        ));
+    }
+    cards
+}
+
+fn axis_keywords(cards: &mut Vec<String>) {
+    let axes = [
+        ("Narrays", "Integer", 1.0, 1.0, "Number of image arrays."),
+        ("JONES", "Integer", -5.0, -1.0, "Jones matrix array"),
+        ("TIME", "JD", TIME, INTTIME / 86400.0, "Time axis."),
+        ("FREQS", "Hz", FREQ, CHWIDTH, "Frequency."),
+        ("IF", "Integer", 1.0, 1.0, "Spectral window number."),
+        (
+            "ANTAXIS",
+            "Integer",
+            1.0,
+            -1.0,
+            "See ANTARR in ANTENNA extension for values.",
+        ),
+    ];
+    for (ind, (ctype, cunit, crval, cdelt, comment)) in axes.iter().enumerate() {
+        let axis = ind + 1;
+        cards.push(card(&format!("CTYPE{}", axis), Value::Str(ctype), comment));
+        cards.push(card(&format!("CUNIT{}", axis), Value::Str(cunit), ""));
+        cards.push(card(&format!("CRPIX{}", axis), Value::Int(1), ""));
+        cards.push(card(&format!("CRVAL{}", axis), Value::Float(*crval), ""));
+        cards.push(card(&format!("CDELT{}", axis), Value::Float(*cdelt), ""));
+    }
+}
+
+fn primary_header(cal_type: &'static str, narrays: usize, nfreqs: usize) -> Vec<String> {
+    let (x, y, z) = ecef(LATITUDE, LONGITUDE, ALTITUDE);
+    let mut cards = vec![card(
+        "SIMPLE",
+        Value::Logical(true),
+        "conforms to FITS standard",
+    )];
+    cards.extend(image_axes(narrays, nfreqs));
+    cards.extend([
+        card("EXTEND", Value::Logical(true), ""),
+        card("TELESCOP", Value::Str("HERA"), ""),
+        card("XORIENT", Value::Str("east"), ""),
+        card("FRAME", Value::Str("itrs"), ""),
+        card("ARRAYX", Value::Float(x), ""),
+        card("ARRAYY", Value::Float(y), ""),
+        card("ARRAYZ", Value::Float(z), ""),
+        card("GNCONVEN", Value::Str("divide"), ""),
+        card("GNSCALE", Value::Str("Jy"), ""),
+        card(
+            "CALTYPE",
+            Value::Str(cal_type),
+            "Type of calibration parameter.",
+        ),
+        card("CALSTYLE", Value::Str("redundant"), ""),
+        card("INTTIME", Value::Float(INTTIME), ""),
+        card("CHWIDTH", Value::Float(CHWIDTH), ""),
+    ]);
+    if cal_type == "delay" {
+        let range = format!("{:?},{:?}", FREQ, FREQ + (NFREQS - 1) as f64 * CHWIDTH);
+        cards.push(card("FRQRANGE", Value::Text(range), ""));
+    }
+    axis_keywords(&mut cards);
+    cards.push(format!("HISTORY {}", "Calibrated with pyuvdata."));
+    cards.push(format!("HISTORY {}", "Written by the fixture generator."));
+    cards
+}
+
+/// Big-endian doubles of `values` in numpy C order.
+fn image_data(values: impl Iterator<Item = f64>) -> Vec<u8> {
+    pad_data(values.flat_map(f64::to_be_bytes).collect())
+}
+
+/// The antenna table pyuvdata writes: names, numbers, solved antennas
+/// padded with -1 and positions.
+fn antennas() -> Vec<u8> {
+    let names = ["HH0", "HH1", "HH2", "HH11"];
+    let numbers: [f64; 4] = [0.0, 1.0, 2.0, 11.0];
+    let ant_arr: [f64; 4] = [0.0, 1.0, 11.0, -1.0];
+    let positions: [[f64; 3]; 4] = [
+        [-1.0, 14.6, -0.5],
+        [7.3, 1.2, 0.1],
+        [12.6, -8.9, 0.2],
+        [-21.9, 3.6, -0.3],
+    ];
+    let cards = vec![
+        card("XTENSION", Value::Str("BINTABLE"), "binary table extension"),
+        card("BITPIX", Value::Int(8), "array data type"),
+        card("NAXIS", Value::Int(2), "number of array dimensions"),
+        card("NAXIS1", Value::Int(48), "length of dimension 1"),
+        card("NAXIS2", Value::Int(4), "length of dimension 2"),
+        card("PCOUNT", Value::Int(0), "number of group parameters"),
+        card("GCOUNT", Value::Int(1), "number of groups"),
+        card("TFIELDS", Value::Int(4), "number of table fields"),
+        card("TTYPE1", Value::Str("ANTNAME"), ""),
+        card("TFORM1", Value::Str("8A"), ""),
+        card("TTYPE2", Value::Str("ANTINDEX"), ""),
+        card("TFORM2", Value::Str("D"), ""),
+        card("TTYPE3", Value::Str("ANTARR"), ""),
+        card("TFORM3", Value::Str("D"), ""),
+        card("TTYPE4", Value::Str("ANTXYZ"), ""),
+        card("TFORM4", Value::Str("3D"), ""),
+        card("EXTNAME", Value::Str("ANTENNAS"), ""),
+    ];
+    let mut rows = Vec::new();
+    for ant in 0..4 {
+        rows.extend(format!("{:\0<8}", names[ant]).into_bytes());
+        rows.extend(numbers[ant].to_be_bytes());
+        rows.extend(ant_arr[ant].to_be_bytes());
+        positions[ant]
+            .iter()
+            .for_each(|value| rows.extend(value.to_be_bytes()));
+    }
+    let mut bytes = header_bytes(&cards);
+    bytes.extend(pad_data(rows));
+    bytes
+}
+
+/// Index into numpy arrays of shape (Nants, 1, nfreqs, Ntimes, Njones,
+/// narrays), in C order.
+fn indices(nfreqs: usize, narrays: usize) -> impl Iterator<Item = [usize; 5]> {
+    (0..NANTS).flat_map(move |ant| {
+        (0..nfreqs).flat_map(move |freq| {
+            (0..NTIMES).flat_map(move |time| {
+                (0..NJONES).flat_map(move |jones| {
+                    (0..narrays).map(move |arr| [ant, freq, time, jones, arr])
+                })
+            })
+        })
+    })
+}
+
+fn write_gains(path: &str) {
+    // real, imaginary, flag and quality arrays
+    let data = indices(NFREQS, 4).map(|[ant, freq, time, jones, arr]| {
+        let amp = 1.0 + 0.1 * ant as f64 + 0.01 * freq as f64;
+        let phase = 0.2 * ant as f64 - 0.1 * freq as f64 + 0.3 * time as f64 + jones as f64;
+        match arr {
+            0 => amp * phase.cos(),
+            1 => amp * phase.sin(),
+            2 => ([ant, freq, time, jones] == [2, 1, 0, 1]) as u8 as f64,
+            _ => ant as f64 + 0.5 * freq as f64,
+        }
+    });
+    let mut bytes = header_bytes(&primary_header("gain", 4, NFREQS));
+    bytes.extend(image_data(data));
+    bytes.extend(antennas());
+
+    let mut cards = vec![card("XTENSION", Value::Str("IMAGE"), "Image extension")];
+    cards.extend([
+        card("BITPIX", Value::Int(-64), "array data type"),
+        card("NAXIS", Value::Int(4), "number of array dimensions"),
+        card("NAXIS1", Value::Int(NJONES as i64), ""),
+        card("NAXIS2", Value::Int(NTIMES as i64), ""),
+        card("NAXIS3", Value::Int(NFREQS as i64), ""),
+        card("NAXIS4", Value::Int(1), ""),
+        card("PCOUNT", Value::Int(0), "number of parameters"),
+        card("GCOUNT", Value::Int(1), "number of groups"),
+        card("EXTNAME", Value::Str("TOTQLTY"), "extension name"),
+    ]);
+    bytes.extend(header_bytes(&cards));
+    bytes.extend(image_data(
+        (0..NFREQS * NTIMES * NJONES).map(|ind| ind as f64 / 4.0),
+    ));
+    fs::write(path, bytes).unwrap();
+}
+
+fn write_delays(path: &str) {
+    // delay and quality arrays
+    let data = indices(1, 2).map(|[ant, _, time, jones, arr]| match arr {
+        0 => (ant as f64 - 2.0 * time as f64 + jones as f64) * 1e-9,
+        _ => 1.0 + ant as f64,
+    });
+    let mut bytes = header_bytes(&primary_header("delay", 2, 1));
+    bytes.extend(image_data(data));
+
+    let mut cards = vec![card("XTENSION", Value::Str("IMAGE"), "Image extension")];
+    let mut axes = image_axes(1, 1);
+    axes[0] = card("BITPIX", Value::Int(16), "array data type");
+    cards.extend(axes);
+    cards.extend([
+        card("PCOUNT", Value::Int(0), "number of parameters"),
+        card("GCOUNT", Value::Int(1), "number of groups"),
+        card("EXTNAME", Value::Str("FLAGS"), "extension name"),
+    ]);
+    bytes.extend(header_bytes(&cards));
+    let flags: Vec<u8> = indices(1, 1)
+        .flat_map(|[ant, _, time, jones, _]| {
+            ((ant == 1 && time == 1 && jones == 0) as i16).to_be_bytes()
+        })
+        .collect();
+    bytes.extend(pad_data(flags));
+    bytes.extend(antennas());
+    fs::write(path, bytes).unwrap();
+}
+
+fn main() {
+    write_gains("tests/data/test_gains.calfits");
+    write_delays("tests/data/test_delays.calfits");
+}
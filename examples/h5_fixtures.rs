@@ -0,0 +1,231 @@
+//! Write the UVFlag and calh5 test fixtures in tests/data.
+//!
+//! pyuvdata is not a dependency of this crate, so the files are laid out by
+//! hand the way `UVFlag.write` and `UVCal.write_calh5` of pyuvdata 3 write
+//! them through h5py, without going through this crate's writers: strings
+//! are null padded to their exact length, integer arrays are int64, bools
+//! are h5py's FALSE/TRUE enum and complex values are {r, i} compounds.
+//! They check this crate against that reading of the format, not against
+//! files pyuvdata wrote.
+//!
+//! Run with `cargo run --example h5_fixtures` from the crate root.
+use hdf5::types::{CompoundField, CompoundType, FixedAscii, TypeDescriptor};
+use hdf5::{Group, H5Type};
+use ndarray::{Array, Array1};
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Complex128 {
+    r: f64,
+    i: f64,
+}
+
+// h5py stores complex128 as a compound of two doubles named r and i
+unsafe impl H5Type for Complex128 {
+    fn type_descriptor() -> TypeDescriptor {
+        TypeDescriptor::Compound(CompoundType {
+            fields: vec![
+                CompoundField::typed::<f64>("r", 0, 0),
+                CompoundField::typed::<f64>("i", 8, 1),
+            ],
+            size: 16,
+        })
+    }
+}
+
+const LATITUDE: f64 = -30.721_526_120_689_57;
+const LONGITUDE: f64 = 21.428_303_826_863_015;
+const ALTITUDE: f64 = 1_051.690_000_021_830_2;
+
+fn ascii(text: &str) -> FixedAscii<200> {
+    FixedAscii::<200>::from_ascii(text).unwrap()
+}
+
+/// A scalar string the way h5py writes `np.bytes_`.
+fn write_str(group: &Group, name: &str, text: &str) -> hdf5::Result<()> {
+    group
+        .new_dataset_builder()
+        .empty_as(&TypeDescriptor::FixedAscii(text.len()))
+        .create(name)?
+        .write_scalar(&ascii(text))
+}
+
+/// A string array the way h5py writes `np.asarray(names, dtype="bytes")`.
+fn write_strs(group: &Group, name: &str, texts: &[&str]) -> hdf5::Result<()> {
+    let width = texts.iter().map(|text| text.len()).max().unwrap_or(1);
+    group
+        .new_dataset_builder()
+        .empty_as(&TypeDescriptor::FixedAscii(width))
+        .shape(texts.len())
+        .create(name)?
+        .write_raw(&texts.iter().map(|text| ascii(text)).collect::<Vec<_>>())
+}
+
+fn write_scalar<T: H5Type>(group: &Group, name: &str, value: T) -> hdf5::Result<()> {
+    group
+        .new_dataset_builder()
+        .empty::<T>()
+        .create(name)?
+        .write_scalar(&value)
+}
+
+fn write_ints(group: &Group, name: &str, values: &[i64]) -> hdf5::Result<()> {
+    group
+        .new_dataset_builder()
+        .with_data(&Array1::from(values.to_vec()))
+        .create(name)?;
+    Ok(())
+}
+
+fn write_floats(group: &Group, name: &str, values: &[f64]) -> hdf5::Result<()> {
+    group
+        .new_dataset_builder()
+        .with_data(&Array1::from(values.to_vec()))
+        .create(name)?;
+    Ok(())
+}
+
+/// Telescope and antenna metadata shared by both files.
+fn write_telescope(header: &Group) -> hdf5::Result<()> {
+    write_str(header, "telescope_name", "HERA")?;
+    write_str(header, "instrument", "HERA")?;
+    write_str(header, "telescope_frame", "itrs")?;
+    write_scalar(header, "latitude", LATITUDE)?;
+    write_scalar(header, "longitude", LONGITUDE)?;
+    write_scalar(header, "altitude", ALTITUDE)?;
+    write_str(header, "x_orientation", "east")?;
+    write_scalar(header, "Nants_telescope", 4i64)?;
+    write_ints(header, "antenna_numbers", &[0, 1, 2, 11])?;
+    write_strs(header, "antenna_names", &["HH0", "HH1", "HH2", "HH11"])?;
+    let positions = Array::from_shape_vec(
+        (4, 3),
+        vec![
+            -1.0, 14.6, -0.5, 7.3, 1.2, 0.1, 12.6, -8.9, 0.2, -21.9, 3.6, -0.3,
+        ],
+    )
+    .unwrap();
+    header
+        .new_dataset_builder()
+        .with_data(&positions)
+        .create("antenna_positions")?;
+    write_str(header, "version", "3.0.0")
+}
+
+const TIMES: [f64; 2] = [2_459_122.0, 2_459_122.000_124_276];
+const LSTS: [f64; 2] = [0.746_937_3, 0.747_720_6];
+const FREQS: [f64; 3] = [100e6, 100.1e6, 100.2e6];
+
+/// A baseline type flag file of three baselines at two times.
+fn write_uvflag(path: &str) -> hdf5::Result<()> {
+    let h5file = hdf5::File::create(path)?;
+    let header = h5file.create_group("Header")?;
+    write_str(&header, "type", "baseline")?;
+    write_str(&header, "mode", "flag")?;
+    write_str(&header, "label", "xrfi")?;
+    write_str(&header, "history", "Flagged with pyuvdata.")?;
+    write_telescope(&header)?;
+    let (ant_1, ant_2) = ([0i64, 0, 1], [1i64, 2, 2]);
+    let blt = |values: &[i64]| -> Vec<i64> { values.iter().chain(values).copied().collect() };
+    let baselines: Vec<i64> = ant_1
+        .iter()
+        .zip(ant_2.iter())
+        .map(|(a1, a2)| 2048 * (a1 + 1) + a2 + 1 + (1 << 16))
+        .collect();
+    write_ints(&header, "ant_1_array", &blt(&ant_1))?;
+    write_ints(&header, "ant_2_array", &blt(&ant_2))?;
+    write_ints(&header, "baseline_array", &blt(&baselines))?;
+    let repeat = |values: [f64; 2]| -> Vec<f64> { values.iter().flat_map(|&v| [v; 3]).collect() };
+    write_floats(&header, "time_array", &repeat(TIMES))?;
+    write_floats(&header, "lst_array", &repeat(LSTS))?;
+    write_floats(&header, "freq_array", &FREQS)?;
+    write_floats(&header, "channel_width", &[0.1e6; 3])?;
+    write_ints(&header, "spw_array", &[0])?;
+    write_ints(&header, "flex_spw_id_array", &[0; 3])?;
+    write_ints(&header, "polarization_array", &[-5, -6])?;
+    let counts = [
+        ("Ntimes", 2),
+        ("Nblts", 6),
+        ("Nbls", 3),
+        ("Nfreqs", 3),
+        ("Npols", 2),
+        ("Nspws", 1),
+        ("Nants_data", 3),
+    ];
+    for (name, count) in counts {
+        write_scalar(&header, name, count as i64)?;
+    }
+
+    let data = h5file.create_group("Data")?;
+    let flags = Array::from_shape_fn((6, 3, 2), |(blt, freq, pol)| {
+        (blt + 2 * freq + pol) % 5 == 0
+    });
+    data.new_dataset_builder()
+        .with_data(&flags)
+        .create("flag_array")?;
+    h5file.close()
+}
+
+/// Gain solutions of three antennas, two Jones terms and two times.
+fn write_calh5(path: &str) -> hdf5::Result<()> {
+    let h5file = hdf5::File::create(path)?;
+    let header = h5file.create_group("Header")?;
+    write_str(&header, "cal_type", "gain")?;
+    write_str(&header, "cal_style", "redundant")?;
+    write_str(&header, "gain_convention", "divide")?;
+    write_str(&header, "gain_scale", "Jy")?;
+    write_str(&header, "history", "Calibrated with pyuvdata.")?;
+    write_telescope(&header)?;
+    write_scalar(&header, "wide_band", false)?;
+    write_ints(&header, "ant_array", &[0, 1, 11])?;
+    write_ints(&header, "jones_array", &[-5, -6])?;
+    write_floats(&header, "time_array", &TIMES)?;
+    write_floats(&header, "lst_array", &LSTS)?;
+    write_floats(&header, "integration_time", &[10.737_418_24; 2])?;
+    write_floats(&header, "freq_array", &FREQS)?;
+    write_floats(&header, "channel_width", &[0.1e6; 3])?;
+    write_ints(&header, "spw_array", &[0])?;
+    write_ints(&header, "flex_spw_id_array", &[0; 3])?;
+    let counts = [
+        ("Nants_data", 3),
+        ("Nfreqs", 3),
+        ("Ntimes", 2),
+        ("Njones", 2),
+        ("Nspws", 1),
+    ];
+    for (name, count) in counts {
+        write_scalar(&header, name, count as i64)?;
+    }
+
+    let data = h5file.create_group("Data")?;
+    let shape = (3, 3, 2, 2);
+    let gains = Array::from_shape_fn(shape, |(ant, freq, time, jones)| {
+        let amp = 1.0 + 0.1 * ant as f64 + 0.01 * freq as f64;
+        let phase = 0.2 * ant as f64 - 0.1 * freq as f64 + 0.3 * time as f64 + jones as f64;
+        Complex128 {
+            r: amp * phase.cos(),
+            i: amp * phase.sin(),
+        }
+    });
+    data.new_dataset_builder()
+        .with_data(&gains)
+        .create("gains")?;
+    let flags = Array::from_shape_fn(shape, |index| index == (2, 1, 0, 1));
+    data.new_dataset_builder()
+        .with_data(&flags)
+        .create("flags")?;
+    let qualities = Array::from_shape_fn(shape, |(ant, freq, _, _)| ant as f64 + 0.5 * freq as f64);
+    data.new_dataset_builder()
+        .with_data(&qualities)
+        .create("qualities")?;
+    let total = Array::from_elem((3, 2, 2), PI / 2.0);
+    data.new_dataset_builder()
+        .with_data(&total)
+        .create("total_quality")?;
+    h5file.close()
+}
+
+fn main() -> hdf5::Result<()> {
+    write_uvflag("tests/data/test_flags.h5")?;
+    write_calh5("tests/data/test_gains.calh5")
+}
@@ -0,0 +1,602 @@
+use approx::AbsDiffEq;
+use ndarray::{Array, Axis, Ix1, Ix2, Ix3};
+use num_complex::Complex;
+use num_traits::{Float, Zero};
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+use super::base::{BltOrder, BltOrders, PhaseType};
+use super::utils;
+use super::UVData;
+
+/// How consecutive integrations are grouped by `downsample_in_time`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TimeAverage {
+    /// Average until the summed integration time reaches this many seconds.
+    IntegrationTime(f64),
+    /// Average this many consecutive integrations.
+    NTimes(usize),
+}
+
+/// Circular mean of angles in radians, wrapped into [0, 2pi).
+fn mean_angle<I: Iterator<Item = f64>>(angles: I) -> f64 {
+    let (sin_sum, cos_sum) = angles.fold((0.0, 0.0), |(sin_sum, cos_sum), angle| {
+        (sin_sum + angle.sin(), cos_sum + angle.cos())
+    });
+    utils::wrap_2pi(sin_sum.atan2(cos_sum))
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Per-frequency phasors which rephase the drift visibilities of `blt`
+    /// to the zenith at `lst0`. A negative `sign` undoes the rephasing.
+    pub(crate) fn zenith_phasors(
+        &self,
+        blt: usize,
+        lst0: f64,
+        sign: f64,
+    ) -> Array<Complex<T>, Ix1> {
+        let lat = self.telescope_location_latlonalt().0;
+        let uvw = &self.meta_arrays.uvw_array;
+        let enu = [uvw[[blt, 0]], uvw[[blt, 1]], uvw[[blt, 2]]];
+        let hour_angle = self.meta_arrays.lst_array[blt] - lst0;
+        let delta_w = utils::uvw_from_enu(enu, hour_angle, lat, lat)[2] - enu[2];
+        self.meta_arrays.freq_array.mapv(|freq| {
+            let phase = -sign * 2.0 * PI * freq * delta_w / utils::SPEED_OF_LIGHT;
+            Complex::from_polar(T::one(), T::from(phase).unwrap())
+        })
+    }
+
+    /// Reorder the blt axis to be sorted by time, then baseline.
+    pub(crate) fn sort_blts_by_time(&mut self) {
+        let times = &self.meta_arrays.time_array;
+        let baselines = &self.meta_arrays.baseline_array;
+        let mut order: Vec<usize> = (0..times.len()).collect();
+        order.sort_by(|&i, &j| {
+            times[i]
+                .partial_cmp(&times[j])
+                .unwrap()
+                .then(baselines[i].cmp(&baselines[j]))
+        });
+        self.take_blts(&order);
+        self.meta.blt_order = BltOrder {
+            major: BltOrders::Time,
+            minor: BltOrders::Baseline,
+        };
+    }
+
+    /// The time sorted blt indices of every stored antenna pair and phase
+    /// center, so integrations phased to different centers are never
+    /// averaged together.
+    pub(crate) fn antpair_center_blt_groups(&self) -> Vec<Vec<usize>> {
+        let centers = &self.meta_arrays.phase_center_id_array;
+        let mut groups: BTreeMap<(u32, u32, u32), Vec<usize>> = BTreeMap::new();
+        for (&(ant1, ant2), inds) in self.blt_index().antpairs() {
+            for &blt in inds {
+                groups
+                    .entry((ant1, ant2, centers[blt]))
+                    .or_default()
+                    .push(blt);
+            }
+        }
+        groups.into_values().collect()
+    }
+
+    /// Replace each block of blts by its first record, carrying the average
+    /// of the block's data.
+    ///
+    /// Visibilities are averaged over unflagged samples weighted by nsample
+    /// times `weights[blt]`, and output samples are only flagged if all of
+    /// their inputs are. The weighted nsamples are summed. Drift
+    /// visibilities are rephased to the zenith at the block's entry of
+    /// `lsts` first when given.
+    pub(crate) fn average_blocks(
+        &mut self,
+        blocks: &[Vec<usize>],
+        weights: &Array<f64, Ix1>,
+        lsts: Option<&Array<f64, Ix1>>,
+    ) {
+        let nfreqs = self.meta.nfreqs as usize;
+        let npols = self.meta.npols as usize;
+        let nblocks = blocks.len();
+
+        let averaged = match (&self.data_array, &self.nsample_array, &self.flag_array) {
+            (Some(data), Some(nsamples), Some(flags)) => {
+                let mut avg_data = Array::<Complex<T>, Ix3>::zeros((nblocks, nfreqs, npols));
+                let mut avg_nsamples = Array::<S, Ix3>::zeros((nblocks, nfreqs, npols));
+                let mut avg_flags = Array::<bool, Ix3>::from_elem((nblocks, nfreqs, npols), false);
+                for (ind, block) in blocks.iter().enumerate() {
+                    let phasors: Option<Vec<Array<Complex<T>, Ix1>>> = lsts.map(|lsts| {
+                        block
+                            .iter()
+                            .map(|&blt| self.zenith_phasors(blt, lsts[ind], 1.0))
+                            .collect()
+                    });
+                    for freq in 0..nfreqs {
+                        for pol in 0..npols {
+                            let all_flagged = block.iter().all(|&blt| flags[[blt, freq, pol]]);
+                            let mut vis_sum = Complex::<T>::zero();
+                            let mut nsample_sum = S::zero();
+                            let mut count = T::zero();
+                            let mut plain_sum = Complex::<T>::zero();
+                            for (bind, &blt) in block.iter().enumerate() {
+                                if flags[[blt, freq, pol]] && !all_flagged {
+                                    continue;
+                                }
+                                let vis = match &phasors {
+                                    Some(phasors) => data[[blt, freq, pol]] * phasors[bind][freq],
+                                    None => data[[blt, freq, pol]],
+                                };
+                                let weight =
+                                    nsamples[[blt, freq, pol]] * S::from(weights[blt]).unwrap();
+                                vis_sum = vis_sum + vis * T::from(weight).unwrap();
+                                nsample_sum = nsample_sum + weight;
+                                plain_sum = plain_sum + vis;
+                                count = count + T::one();
+                            }
+                            avg_data[[ind, freq, pol]] = match nsample_sum > S::zero() {
+                                true => vis_sum / T::from(nsample_sum).unwrap(),
+                                false => plain_sum / count,
+                            };
+                            avg_nsamples[[ind, freq, pol]] = nsample_sum;
+                            avg_flags[[ind, freq, pol]] = all_flagged;
+                        }
+                    }
+                }
+                Some((avg_data, avg_nsamples, avg_flags))
+            }
+            _ => None,
+        };
+
+        let firsts: Vec<usize> = blocks.iter().map(|block| block[0]).collect();
+        // drop the data before selecting, it is replaced by the averages anyway
+        self.data_array = None;
+        self.nsample_array = None;
+        self.flag_array = None;
+        self.take_blts(&firsts);
+        if let Some((data, nsamples, flags)) = averaged {
+            self.data_array = Some(data);
+            self.nsample_array = Some(nsamples);
+            self.flag_array = Some(flags);
+        }
+    }
+
+    /// Average blocks of blts into one record each.
+    ///
+    /// Visibilities are averaged with `average_blocks`, weighting nsamples by
+    /// integration time, and nsamples are normalized by the summed
+    /// integration time. Drift data are rephased to the zenith at the
+    /// block's mean LST first when `rephase` is set. Each block must share a
+    /// single phase center, which the averaged record keeps.
+    pub(crate) fn average_blt_blocks(&mut self, blocks: &[Vec<usize>], rephase: bool) {
+        let rephase = rephase && self.meta.phase_type == PhaseType::Drift;
+        let nblocks = blocks.len();
+        let centers = &self.meta_arrays.phase_center_id_array;
+        debug_assert!(blocks
+            .iter()
+            .all(|block| block.iter().all(|&blt| centers[blt] == centers[block[0]])));
+
+        let arrays = &self.meta_arrays;
+        let mut time_array = Array::<f64, Ix1>::zeros(nblocks);
+        let mut lst_array = Array::<f64, Ix1>::zeros(nblocks);
+        let mut integration_time = Array::<f64, Ix1>::zeros(nblocks);
+        let mut uvw_array = Array::<f64, Ix2>::zeros((nblocks, 3));
+        for (ind, block) in blocks.iter().enumerate() {
+            let nblock = block.len() as f64;
+            time_array[ind] = block.iter().map(|&blt| arrays.time_array[blt]).sum::<f64>() / nblock;
+            lst_array[ind] = mean_angle(block.iter().map(|&blt| arrays.lst_array[blt]));
+            integration_time[ind] = block
+                .iter()
+                .map(|&blt| arrays.integration_time[blt])
+                .sum::<f64>();
+            let mut uvw = uvw_array.row_mut(ind);
+            for &blt in block {
+                uvw += &arrays.uvw_array.row(blt);
+            }
+            uvw.mapv_inplace(|x| x / nblock);
+        }
+
+        let weights = arrays.integration_time.clone();
+        self.average_blocks(blocks, &weights, rephase.then_some(&lst_array));
+        if let Some(nsamples) = self.nsample_array.as_mut() {
+            for (mut block, &total_time) in nsamples.outer_iter_mut().zip(integration_time.iter()) {
+                let total_time = S::from(total_time).unwrap();
+                block.mapv_inplace(|nsample| nsample / total_time);
+            }
+        }
+
+        self.meta_arrays.time_array = time_array;
+        self.meta_arrays.lst_array = lst_array;
+        self.meta_arrays.integration_time = integration_time;
+        self.meta_arrays.uvw_array = uvw_array;
+        self.update_blt_counts();
+    }
+
+    /// Average consecutive integrations of each baseline and phase center together.
+    ///
+    /// Leftover integrations at the end of a baseline which do not fill a
+    /// whole averaging block are kept as a shorter block when `keep_ragged`
+    /// is set and dropped otherwise. The result is sorted in time, baseline order.
+    pub fn downsample_in_time(
+        &mut self,
+        target: TimeAverage,
+        keep_ragged: bool,
+        rephase: bool,
+    ) -> Result<(), String> {
+        match target {
+            TimeAverage::NTimes(0) => {
+                return Err("Number of times to average must be positive.".to_string())
+            }
+            TimeAverage::IntegrationTime(time) if time <= 0.0 => {
+                return Err(format!(
+                    "Target integration time must be positive, got {}.",
+                    time
+                ))
+            }
+            _ => {}
+        }
+
+        let int_time = &self.meta_arrays.integration_time;
+        let mut blocks: Vec<Vec<usize>> = Vec::new();
+        for inds in self.antpair_center_blt_groups().iter() {
+            let mut block: Vec<usize> = Vec::new();
+            let mut block_time = 0.0;
+            for &blt in inds {
+                block.push(blt);
+                block_time += int_time[blt];
+                let full = match target {
+                    TimeAverage::NTimes(ntimes) => block.len() >= ntimes,
+                    // allow for rounding in the summed integration times
+                    TimeAverage::IntegrationTime(time) => block_time >= time - 1e-6,
+                };
+                if full {
+                    blocks.push(std::mem::take(&mut block));
+                    block_time = 0.0;
+                }
+            }
+            if !block.is_empty() && keep_ragged {
+                blocks.push(block);
+            }
+        }
+        if blocks.is_empty() {
+            return Err("No integrations remain after averaging in time.".to_string());
+        }
+
+        self.average_blt_blocks(&blocks, rephase);
+        self.sort_blts_by_time();
+        Ok(())
+    }
+
+    /// Split every integration longer than `max_int_time` seconds into equal
+    /// shorter integrations with copies of the original data.
+    pub fn upsample_in_time(&mut self, max_int_time: f64, rephase: bool) -> Result<(), String> {
+        if max_int_time <= 0.0 {
+            return Err(format!(
+                "Maximum integration time must be positive, got {}.",
+                max_int_time
+            ));
+        }
+        let rephase = rephase && self.meta.phase_type == PhaseType::Drift;
+
+        let arrays = &self.meta_arrays;
+        let mut sources: Vec<usize> = Vec::new();
+        let mut time_array: Vec<f64> = Vec::new();
+        let mut lst_array: Vec<f64> = Vec::new();
+        let mut integration_time: Vec<f64> = Vec::new();
+        for (blt, &int_time) in arrays.integration_time.iter().enumerate() {
+            let nsplit = (int_time / max_int_time - 1e-6).ceil().max(1.0) as usize;
+            let new_int_time = int_time / nsplit as f64;
+            for split in 0..nsplit {
+                // offset of the new integration centre from the original, in seconds
+                let offset = (split as f64 + 0.5) * new_int_time - int_time / 2.0;
+                sources.push(blt);
+                time_array.push(arrays.time_array[blt] + offset / 86400.0);
+                lst_array.push(utils::wrap_2pi(
+                    arrays.lst_array[blt] + offset * 2.0 * PI * utils::SIDEREAL_RATE / 86400.0,
+                ));
+                integration_time.push(new_int_time);
+            }
+        }
+        if sources.len() == arrays.integration_time.len() {
+            return Ok(());
+        }
+
+        let centre_lsts: Vec<f64> = sources.iter().map(|&blt| arrays.lst_array[blt]).collect();
+        self.take_blts(&sources);
+        self.meta_arrays.time_array = Array::from_vec(time_array);
+        self.meta_arrays.lst_array = Array::from_vec(lst_array);
+        self.meta_arrays.integration_time = Array::from_vec(integration_time);
+
+        if rephase {
+            for (blt, &lst0) in centre_lsts.iter().enumerate() {
+                let phasors = self.zenith_phasors(blt, lst0, -1.0);
+                if let Some(data) = self.data_array.as_mut() {
+                    for mut freqs in data.index_axis_mut(Axis(0), blt).columns_mut() {
+                        freqs.zip_mut_with(&phasors, |vis, &phasor| *vis = *vis * phasor);
+                    }
+                }
+            }
+        }
+        self.update_blt_counts();
+        self.sort_blts_by_time();
+        Ok(())
+    }
+
+    /// Apply baseline-dependent averaging (BDA).
+    ///
+    /// Each baseline is averaged in time by the largest power of two number
+    /// of integrations for which the time-smearing decorrelation of its
+    /// fastest fringe at the highest frequency stays below `max_decorr`, and
+    /// the averaged integration time does not exceed `max_time` seconds.
+    pub fn apply_bda(
+        &mut self,
+        max_decorr: f64,
+        max_time: f64,
+        rephase: bool,
+    ) -> Result<(), String> {
+        if max_decorr <= 0.0 || max_decorr >= 1.0 {
+            return Err(format!(
+                "Maximum decorrelation must be between 0 and 1, got {}.",
+                max_decorr
+            ));
+        }
+        let max_freq = self
+            .meta_arrays
+            .freq_array
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let earth_rate = 2.0 * PI * utils::SIDEREAL_RATE / 86400.0;
+
+        let arrays = &self.meta_arrays;
+        let mut blocks: Vec<Vec<usize>> = Vec::new();
+        for inds in self.antpair_center_blt_groups().iter() {
+            let length = inds
+                .iter()
+                .map(|&blt| arrays.uvw_array.row(blt).mapv(|x| x.powi(2)).sum().sqrt())
+                .sum::<f64>()
+                / inds.len() as f64;
+            let fringe_rate = earth_rate * length * max_freq / utils::SPEED_OF_LIGHT;
+            let int_time = inds
+                .iter()
+                .map(|&blt| arrays.integration_time[blt])
+                .fold(0.0, f64::max);
+            let decorrelation = |factor: usize| {
+                let x = PI * fringe_rate * factor as f64 * int_time;
+                match x == 0.0 {
+                    true => 0.0,
+                    false => 1.0 - x.sin() / x,
+                }
+            };
+
+            let mut factor: usize = 1;
+            while factor * 2 <= inds.len()
+                && (factor * 2) as f64 * int_time <= max_time + 1e-6
+                && decorrelation(factor * 2) <= max_decorr
+            {
+                factor *= 2;
+            }
+            blocks.extend(inds.chunks(factor).map(|block| block.to_vec()));
+        }
+
+        self.average_blt_blocks(&blocks, rephase);
+        self.sort_blts_by_time();
+        self.meta.blt_order = BltOrder {
+            major: BltOrders::Bda,
+            minor: BltOrders::Bda,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TimeAverage;
+    use crate::base::{CatTypes, SiderealVal};
+    use crate::test_data::{read_drift, read_test_file};
+    use num_complex::Complex;
+
+    #[test]
+    fn downsample_ntimes() {
+        let uvd = read_drift();
+        let mut avg = uvd.clone();
+        avg.downsample_in_time(TimeAverage::NTimes(2), true, false)
+            .expect("Cannot downsample.");
+
+        assert_eq!(avg.meta.nblts, 100);
+        assert_eq!(avg.meta.ntimes, 10);
+        assert_eq!(avg.meta.nbls, 10);
+        assert!(avg
+            .meta_arrays
+            .integration_time
+            .iter()
+            .all(|&x| abs_diff_eq!(x, 2.0 * 1.879048192, epsilon = 1e-6)));
+
+        // the first blt of the input is the (0, 0) autocorrelation at the first time
+        // and the same baseline is 10 records later at the second time.
+        let data = uvd.data_array.as_ref().unwrap();
+        let expected: Complex<f64> = (data[[0, 1, 0]] + data[[10, 1, 0]]) / 2.0;
+        let avg_data = avg.data_array.as_ref().unwrap();
+        let blt = avg
+            .meta_arrays
+            .ant_1_array
+            .iter()
+            .zip(avg.meta_arrays.ant_2_array.iter())
+            .position(|(&a1, &a2)| a1 == 0 && a2 == 0)
+            .unwrap();
+        assert_abs_diff_eq!(avg_data[[blt, 1, 0]].re, expected.re, epsilon = 1e-8);
+        assert_abs_diff_eq!(avg_data[[blt, 1, 0]].im, expected.im, epsilon = 1e-8);
+        assert_abs_diff_eq!(
+            avg.meta_arrays.time_array[blt],
+            (uvd.meta_arrays.time_array[0] + uvd.meta_arrays.time_array[10]) / 2.0,
+            epsilon = 1e-10
+        );
+    }
+
+    #[test]
+    fn downsample_ragged() {
+        let mut uvd = read_test_file("test_phased.uvh5");
+        uvd.downsample_in_time(TimeAverage::IntegrationTime(3.0 * 1.879048192), false, true)
+            .expect("Cannot downsample.");
+        assert_eq!(uvd.meta.ntimes, 6);
+        assert_eq!(uvd.meta.nblts, 60);
+
+        let mut uvd = read_test_file("test_phased.uvh5");
+        uvd.downsample_in_time(TimeAverage::IntegrationTime(3.0 * 1.879048192), true, true)
+            .expect("Cannot downsample.");
+        assert_eq!(uvd.meta.ntimes, 7);
+        assert_eq!(uvd.meta.nblts, 70);
+    }
+
+    #[test]
+    fn downsample_multiphase() {
+        let mut uvd = read_test_file("test_multiphase.uvh5");
+        uvd.nsample_array.as_mut().unwrap().fill(1.0);
+        uvd.flag_array.as_mut().unwrap().fill(false);
+        // alternate integrations are phased to a second center
+        let center = SiderealVal {
+            cat_id: 1,
+            cat_type: "sidereal".to_string(),
+            cat_lon: 0.1,
+            cat_lat: -0.5,
+            cat_frame: "icrs".to_string(),
+            cat_epoch: 2000.0,
+            cat_pm_ra: None,
+            cat_pm_dec: None,
+            cat_dist: None,
+            cat_vrad: None,
+            info_source: Some("user".to_string()),
+        };
+        let arrays = &mut uvd.meta_arrays;
+        arrays
+            .phase_center_catalog
+            .insert("offset".to_string(), CatTypes::Sidereal(center));
+        for blt in 0..200 {
+            arrays.phase_center_id_array[blt] = ((blt / 10) % 2) as u32;
+        }
+
+        let mut avg = uvd.clone();
+        avg.downsample_in_time(TimeAverage::NTimes(2), true, false)
+            .expect("Cannot downsample.");
+        assert_eq!(avg.meta.nblts, 100);
+        assert_eq!(avg.meta.ntimes, 10);
+
+        // the (0, 1) baseline is the fourth record of every time, so its
+        // first block on the second center averages the second and fourth
+        // integrations
+        let (first, second) = (13, 33);
+        let arrays = &avg.meta_arrays;
+        let blt = (0..100)
+            .filter(|&blt| {
+                (arrays.ant_1_array[blt], arrays.ant_2_array[blt]) == (0, 1)
+                    && arrays.phase_center_id_array[blt] == 1
+            })
+            .min_by(|&i, &j| {
+                arrays.time_array[i]
+                    .partial_cmp(&arrays.time_array[j])
+                    .unwrap()
+            })
+            .unwrap();
+        let times = uvd.meta_arrays.time_array;
+        assert_abs_diff_eq!(
+            arrays.time_array[blt],
+            (times[first] + times[second]) / 2.0,
+            epsilon = 1e-10
+        );
+        let data = uvd.data_array.as_ref().unwrap();
+        let expected: Complex<f64> = (data[[first, 1, 0]] + data[[second, 1, 0]]) / 2.0;
+        let avg_data = avg.data_array.as_ref().unwrap();
+        assert_abs_diff_eq!(avg_data[[blt, 1, 0]].re, expected.re, epsilon = 1e-8);
+        assert_abs_diff_eq!(avg_data[[blt, 1, 0]].im, expected.im, epsilon = 1e-8);
+    }
+
+    #[test]
+    fn downsample_all_flagged() {
+        let mut uvd = read_drift();
+        uvd.flag_array.as_mut().unwrap().fill(true);
+        uvd.downsample_in_time(TimeAverage::NTimes(4), true, true)
+            .expect("Cannot downsample.");
+        assert!(uvd.flag_array.unwrap().iter().all(|&x| x));
+    }
+
+    #[test]
+    fn upsample_inverts_downsample() {
+        let mut uvd = read_drift();
+        uvd.sort_blts_by_time();
+        let mut avg = uvd.clone();
+        avg.downsample_in_time(TimeAverage::NTimes(2), true, true)
+            .expect("Cannot downsample.");
+        avg.upsample_in_time(1.879048192, true)
+            .expect("Cannot upsample.");
+
+        assert_eq!(avg.meta.nblts, uvd.meta.nblts);
+        assert_eq!(avg.meta.ntimes, uvd.meta.ntimes);
+        assert!(avg
+            .meta_arrays
+            .time_array
+            .abs_diff_eq(&uvd.meta_arrays.time_array, 1e-8));
+        assert!(avg
+            .meta_arrays
+            .lst_array
+            .abs_diff_eq(&uvd.meta_arrays.lst_array, 1e-6));
+        assert!(avg
+            .meta_arrays
+            .integration_time
+            .abs_diff_eq(&uvd.meta_arrays.integration_time, 1e-6));
+        assert_eq!(avg.meta_arrays.ant_1_array, uvd.meta_arrays.ant_1_array);
+        assert_eq!(avg.meta_arrays.ant_2_array, uvd.meta_arrays.ant_2_array);
+    }
+
+    #[test]
+    fn bda_per_baseline_factors() {
+        let uvd = read_drift();
+        let int_time = 1.879048192;
+        let mut bda = uvd.clone();
+        bda.apply_bda(1e-4, 16.0 * int_time, true)
+            .expect("Cannot apply bda.");
+        assert_eq!(bda.meta.blt_order.to_string(), "bda");
+        assert_eq!(bda.meta.nbls, 10);
+        assert!(bda.meta.nblts < uvd.meta.nblts);
+
+        let blt_index = bda.blt_index();
+        for ((ant1, ant2), inds) in blt_index.antpairs().iter() {
+            // total integration time of every baseline is preserved
+            let total: f64 = inds
+                .iter()
+                .map(|&blt| bda.meta_arrays.integration_time[blt])
+                .sum();
+            assert_abs_diff_eq!(total, 20.0 * int_time, epsilon = 1e-6);
+            if ant1 == ant2 {
+                assert_abs_diff_eq!(
+                    bda.meta_arrays.integration_time[inds[0]],
+                    16.0 * int_time,
+                    epsilon = 1e-6
+                );
+            }
+        }
+        // longer baselines are averaged less than autos
+        let long_times = blt_index.antpairs()[&(0, 2)].len();
+        let auto_times = blt_index.antpairs()[&(0, 0)].len();
+        assert!(long_times > auto_times);
+
+        let mut auto = bda.clone();
+        auto.select_antpairs(&[(0, 0)]).expect("Cannot select.");
+        assert_eq!(auto.meta.ntimes as usize, auto_times);
+    }
+
+    #[test]
+    fn bad_targets() {
+        let mut uvd = read_drift();
+        assert!(uvd
+            .downsample_in_time(TimeAverage::NTimes(0), true, false)
+            .is_err());
+        assert!(uvd
+            .downsample_in_time(TimeAverage::IntegrationTime(-1.0), true, false)
+            .is_err());
+        assert!(uvd.upsample_in_time(0.0, false).is_err());
+        assert!(uvd.apply_bda(1.5, 10.0, false).is_err());
+    }
+}
@@ -210,6 +210,16 @@ pub enum CatTypes {
     Ephem(EphemVal),
 }
 
+impl CatTypes {
+    pub fn cat_id(&self) -> u32 {
+        match self {
+            CatTypes::Unphased(val) => val.cat_id,
+            CatTypes::Sidereal(val) => val.cat_id,
+            CatTypes::Ephem(val) => val.cat_id,
+        }
+    }
+}
+
 pub type Catalog = BTreeMap<String, CatTypes>;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -328,6 +338,161 @@ impl std::fmt::Display for Orientation {
     }
 }
 
+/// Polarization products, numbered following the AIPS memo 117 convention.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum Polarization {
+    YX = -8,
+    XY = -7,
+    YY = -6,
+    XX = -5,
+    LR = -4,
+    RL = -3,
+    LL = -2,
+    RR = -1,
+    I = 1,
+    Q = 2,
+    U = 3,
+    V = 4,
+}
+
+impl Polarization {
+    pub fn from_num(num: i8) -> Result<Polarization, String> {
+        match num {
+            -8 => Ok(Polarization::YX),
+            -7 => Ok(Polarization::XY),
+            -6 => Ok(Polarization::YY),
+            -5 => Ok(Polarization::XX),
+            -4 => Ok(Polarization::LR),
+            -3 => Ok(Polarization::RL),
+            -2 => Ok(Polarization::LL),
+            -1 => Ok(Polarization::RR),
+            1 => Ok(Polarization::I),
+            2 => Ok(Polarization::Q),
+            3 => Ok(Polarization::U),
+            4 => Ok(Polarization::V),
+            other => Err(format!("Unknown polarization number: {}.", other)),
+        }
+    }
+
+    pub fn num(&self) -> i8 {
+        *self as i8
+    }
+
+    /// Parse a polarization string, resolving E/N feed names with `x_orientation`.
+    pub fn from_str_oriented(
+        input: &str,
+        x_orientation: Orientation,
+    ) -> Result<Polarization, String> {
+        let pol = input.trim_matches(char::is_whitespace).to_lowercase();
+        if pol.len() == 2 && pol.chars().all(|c| c == 'e' || c == 'n') {
+            let (east, north) = match x_orientation {
+                Orientation::East => ('x', 'y'),
+                Orientation::North => ('y', 'x'),
+                Orientation::Unknown => {
+                    return Err(format!(
+                        "Cannot interpret polarization {} with an unknown x_orientation.",
+                        input
+                    ))
+                }
+            };
+            let linear: String = pol
+                .chars()
+                .map(|c| match c {
+                    'e' => east,
+                    _ => north,
+                })
+                .collect();
+            return Polarization::from_str(&linear);
+        }
+        Polarization::from_str(&pol)
+    }
+
+    /// The polarization name using E/N feed names when `x_orientation` is known.
+    pub fn to_str_oriented(&self, x_orientation: Orientation) -> String {
+        let name = self.to_string();
+        let (x_name, y_name) = match x_orientation {
+            Orientation::East => ('e', 'n'),
+            Orientation::North => ('n', 'e'),
+            Orientation::Unknown => return name,
+        };
+        match self.num() {
+            -8..=-5 => name
+                .chars()
+                .map(|c| match c {
+                    'x' => x_name,
+                    _ => y_name,
+                })
+                .collect(),
+            _ => name,
+        }
+    }
+}
+
+impl FromStr for Polarization {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Polarization, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "yx" => Ok(Polarization::YX),
+            "xy" => Ok(Polarization::XY),
+            "yy" => Ok(Polarization::YY),
+            "xx" => Ok(Polarization::XX),
+            "lr" => Ok(Polarization::LR),
+            "rl" => Ok(Polarization::RL),
+            "ll" => Ok(Polarization::LL),
+            "rr" => Ok(Polarization::RR),
+            "pi" | "i" => Ok(Polarization::I),
+            "pq" | "q" => Ok(Polarization::Q),
+            "pu" | "u" => Ok(Polarization::U),
+            "pv" | "v" => Ok(Polarization::V),
+            other => Err(format!("Unknown polarization: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for Polarization {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.num() {
+            // pseudo-stokes are written pI, pQ, ...
+            1..=4 => write!(f, "p{:?}", self),
+            _ => write!(f, "{}", format!("{:?}", self).to_lowercase()),
+        }
+    }
+}
+
+/// Anything that names a polarization: an AIPS number, a string or a `Polarization`.
+pub trait IntoPolNum {
+    fn pol_num(&self, x_orientation: Orientation) -> Result<i8, String>;
+}
+
+impl IntoPolNum for i8 {
+    fn pol_num(&self, _x_orientation: Orientation) -> Result<i8, String> {
+        Polarization::from_num(*self).map(|pol| pol.num())
+    }
+}
+
+impl IntoPolNum for Polarization {
+    fn pol_num(&self, _x_orientation: Orientation) -> Result<i8, String> {
+        Ok(self.num())
+    }
+}
+
+impl IntoPolNum for &str {
+    fn pol_num(&self, x_orientation: Orientation) -> Result<i8, String> {
+        Polarization::from_str_oriented(self, x_orientation).map(|pol| pol.num())
+    }
+}
+
+impl IntoPolNum for String {
+    fn pol_num(&self, x_orientation: Orientation) -> Result<i8, String> {
+        self.as_str().pol_num(x_orientation)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum BltOrders {
     Ant1,
@@ -350,12 +515,16 @@ pub struct BltOrder {
 }
 impl std::fmt::Display for BltOrder {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "{:}, {:}",
-            self.major.to_string().to_lowercase(),
-            self.minor.to_string().to_lowercase()
-        )
+        match self.major {
+            // bda data has no minor ordering
+            BltOrders::Bda => write!(f, "bda"),
+            _ => write!(
+                f,
+                "{:}, {:}",
+                self.major.to_string().to_lowercase(),
+                self.minor.to_string().to_lowercase()
+            ),
+        }
     }
 }
 
@@ -368,7 +537,7 @@ impl FromStr for BltOrder {
             .to_lowercase()
             .as_str()
         {
-            "bda," => Ok(BltOrder {
+            "bda" | "bda," => Ok(BltOrder {
                 major: BltOrders::Bda,
                 minor: BltOrders::Bda,
             }),
@@ -854,7 +1023,7 @@ impl ArrayMetaData {
             integration_time: Array::<f64, Ix1>::zeros(meta.nblts as usize),
             channel_width: Array::<f64, Ix1>::zeros(meta.nfreqs as usize),
             antenna_numbers: Array::<u32, Ix1>::zeros(meta.nants_telescope as usize),
-            antenna_names: Array::<f32, Ix1>::range(0.0, meta.nblts as f32, 1.0)
+            antenna_names: Array::<f32, Ix1>::range(0.0, meta.nants_telescope as f32, 1.0)
                 .mapv(|x| x.to_string()),
             antenna_positions: Array::<f64, Ix2>::zeros((meta.nants_telescope as usize, 3)),
             eq_coeffs: None,
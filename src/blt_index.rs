@@ -0,0 +1,223 @@
+use approx::AbsDiffEq;
+use num_traits::Float;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+
+use super::base::ArrayMetaData;
+use super::utils::TIME_TOL;
+use super::UVData;
+
+// blts hashed into the fingerprint, so checking it stays constant time
+const FINGERPRINT_SAMPLES: usize = 64;
+
+/// Lookup tables from antenna pairs and (baseline, time) to blt indices.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BltIndex {
+    // fingerprint of the blt arrays the index was built from
+    fingerprint: u64,
+    // time sorted blts of each antenna pair as stored
+    antpairs: BTreeMap<(u32, u32), Vec<usize>>,
+    // keyed on the bits of the time so lookups are exact
+    baseline_times: HashMap<(u32, u64), usize>,
+}
+
+/// Hash the lengths and buffers of the blt arrays and a fixed sample of
+/// their entries, which changes whenever they are replaced or resized.
+fn fingerprint(arrays: &ArrayMetaData) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let nblts = arrays.time_array.len();
+    [
+        nblts,
+        arrays.ant_1_array.len(),
+        arrays.ant_2_array.len(),
+        arrays.baseline_array.len(),
+        arrays.time_array.as_ptr() as usize,
+        arrays.ant_1_array.as_ptr() as usize,
+        arrays.ant_2_array.as_ptr() as usize,
+        arrays.baseline_array.as_ptr() as usize,
+    ]
+    .hash(&mut hasher);
+    if nblts > 0 {
+        let step = ((nblts - 1) / (FINGERPRINT_SAMPLES - 1)).max(1);
+        for blt in (0..nblts).step_by(step).chain(std::iter::once(nblts - 1)) {
+            (
+                arrays.ant_1_array.get(blt),
+                arrays.ant_2_array.get(blt),
+                arrays.baseline_array.get(blt),
+                arrays.time_array[blt].to_bits(),
+            )
+                .hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+impl BltIndex {
+    fn build(arrays: &ArrayMetaData, fingerprint: u64) -> BltIndex {
+        let times = &arrays.time_array;
+        let mut antpairs: BTreeMap<(u32, u32), Vec<usize>> = BTreeMap::new();
+        let mut baseline_times: HashMap<(u32, u64), usize> = HashMap::with_capacity(times.len());
+        for (blt, ((&ant1, &ant2), (&baseline, &time))) in arrays
+            .ant_1_array
+            .iter()
+            .zip(arrays.ant_2_array.iter())
+            .zip(arrays.baseline_array.iter().zip(times.iter()))
+            .enumerate()
+        {
+            antpairs.entry((ant1, ant2)).or_default().push(blt);
+            baseline_times
+                .entry((baseline, time.to_bits()))
+                .or_insert(blt);
+        }
+        for inds in antpairs.values_mut() {
+            inds.sort_by(|&i, &j| times[i].partial_cmp(&times[j]).unwrap());
+        }
+        BltIndex {
+            fingerprint,
+            antpairs,
+            baseline_times,
+        }
+    }
+
+    /// The time sorted blts of the antenna pair.
+    pub(crate) fn antpair_blts(&self, ant1: u32, ant2: u32) -> &[usize] {
+        self.antpairs
+            .get(&(ant1, ant2))
+            .map_or(&[], |inds| inds.as_slice())
+    }
+
+    /// The time sorted blts of every antenna pair.
+    pub(crate) fn antpairs(&self) -> &BTreeMap<(u32, u32), Vec<usize>> {
+        &self.antpairs
+    }
+
+    /// The blt holding `baseline` at exactly `time`.
+    pub(crate) fn baseline_time_blt(&self, baseline: u32, time: f64) -> Option<usize> {
+        self.baseline_times
+            .get(&(baseline, time.to_bits()))
+            .copied()
+    }
+}
+
+/// The `BltIndex` of a `UVData`, rebuilt on access once the fingerprint of
+/// its blt arrays has changed.
+#[derive(Debug, Default)]
+pub(crate) struct BltIndexCache(Mutex<Option<Arc<BltIndex>>>);
+
+impl Clone for BltIndexCache {
+    // a clone has its own arrays, so it would not match the fingerprint
+    fn clone(&self) -> BltIndexCache {
+        BltIndexCache::default()
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// The blt index of the current blt arrays, built on first use.
+    pub(crate) fn blt_index(&self) -> Arc<BltIndex> {
+        let fingerprint = fingerprint(&self.meta_arrays);
+        let mut cached = self.blt_index.0.lock().unwrap();
+        match cached.as_ref() {
+            Some(index) if index.fingerprint == fingerprint => Arc::clone(index),
+            _ => {
+                let index = Arc::new(BltIndex::build(&self.meta_arrays, fingerprint));
+                *cached = Some(Arc::clone(&index));
+                index
+            }
+        }
+    }
+
+    /// Drop the cached blt index.
+    ///
+    /// The index notices when the blt arrays of `meta_arrays` are replaced
+    /// or resized but checks only a sample of their entries, so call this
+    /// after editing entries in place.
+    pub fn invalidate_blt_index(&mut self) {
+        self.blt_index.0.get_mut().unwrap().take();
+    }
+
+    /// The time sorted blt indices of the antenna pair, in the stored order only.
+    pub fn antpair_blts(&self, ant1: u32, ant2: u32) -> Vec<usize> {
+        self.blt_index().antpair_blts(ant1, ant2).to_vec()
+    }
+
+    /// The blt holding `baseline` at exactly `time` (JD), if any.
+    pub fn baseline_time_blt(&self, baseline: u32, time: f64) -> Option<usize> {
+        self.blt_index().baseline_time_blt(baseline, time)
+    }
+
+    /// The blt holding the antenna pair within `TIME_TOL` of `time` (JD),
+    /// if any.
+    pub fn antpair_time_blt(&self, ant1: u32, ant2: u32, time: f64) -> Option<usize> {
+        let index = self.blt_index();
+        let blts = index.antpair_blts(ant1, ant2);
+        let times = &self.meta_arrays.time_array;
+        let first = blts.partition_point(|&blt| times[blt] <= time - TIME_TOL);
+        blts.get(first)
+            .copied()
+            .filter(|&blt| (times[blt] - time).abs() < TIME_TOL)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_data::read_metadata;
+    use crate::utils::antnums_to_baseline;
+    use ndarray::array;
+
+    #[test]
+    fn index_lookups() {
+        let mut uvd = read_metadata();
+        let blts = uvd.antpair_blts(0, 1);
+        assert_eq!(blts.len(), 20);
+        assert!(uvd.antpair_blts(1, 0).is_empty());
+        for &blt in blts.iter() {
+            assert_eq!(uvd.meta_arrays.ant_1_array[blt], 0);
+            assert_eq!(uvd.meta_arrays.ant_2_array[blt], 1);
+        }
+
+        let baseline = antnums_to_baseline(&array![0u32], &array![1u32], false)[0];
+        let time = uvd.meta_arrays.time_array[blts[4]];
+        assert_eq!(uvd.baseline_time_blt(baseline, time), Some(blts[4]));
+        assert_eq!(uvd.baseline_time_blt(baseline, time + 1.0), None);
+        assert_eq!(uvd.antpair_time_blt(0, 1, time + 1e-8), Some(blts[4]));
+        assert_eq!(uvd.antpair_time_blt(0, 1, time - 1e-8), Some(blts[4]));
+        assert_eq!(uvd.antpair_time_blt(1, 0, time), None);
+        assert_eq!(uvd.antpair_time_blt(0, 1, time + 1e-5), None);
+
+        // the index follows the data through mutations
+        uvd.select_blts(&blts[2..]).expect("Cannot select.");
+        assert_eq!(uvd.antpair_blts(0, 1), (0..18).collect::<Vec<_>>());
+        assert_eq!(uvd.baseline_time_blt(baseline, time), Some(2));
+        uvd.conjugate_bls(&[baseline]).expect("Cannot conjugate.");
+        assert!(uvd.antpair_blts(0, 1).is_empty());
+        assert_eq!(uvd.antpair_blts(1, 0).len(), 18);
+
+        // and through direct edits of the blt arrays
+        let blt = uvd.antpair_blts(1, 0)[0];
+        uvd.meta_arrays.ant_1_array[blt] = 0;
+        uvd.invalidate_blt_index();
+        assert_eq!(uvd.antpair_blts(1, 0).len(), 17);
+        assert_eq!(uvd.antpair_blts(0, 0), vec![blt]);
+        let conjugated = uvd.meta_arrays.baseline_array[2];
+        assert_eq!(uvd.baseline_time_blt(conjugated, time), Some(2));
+        uvd.meta_arrays.time_array = uvd.meta_arrays.time_array.mapv(|time| time + 1.0);
+        assert_eq!(uvd.baseline_time_blt(conjugated, time), None);
+        assert_eq!(uvd.baseline_time_blt(conjugated, time + 1.0), Some(2));
+        // in place edits of sampled blts are noticed too
+        let (baseline0, time0) = (
+            uvd.meta_arrays.baseline_array[0],
+            uvd.meta_arrays.time_array[0],
+        );
+        assert_eq!(uvd.baseline_time_blt(baseline0, time0), Some(0));
+        uvd.meta_arrays.time_array[0] -= 1.0;
+        assert_eq!(uvd.baseline_time_blt(baseline0, time0), None);
+        assert_eq!(uvd.baseline_time_blt(baseline0, time0 - 1.0), Some(0));
+    }
+}
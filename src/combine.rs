@@ -0,0 +1,410 @@
+use approx::AbsDiffEq;
+use ndarray::{Array, Dimension};
+use num_traits::Float;
+use std::str::FromStr;
+
+use super::UVData;
+
+/// How the nsamples of two objects are combined by `sum_vis` and `diff_vis`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NsamplePolicy {
+    First,
+    Min,
+    Sum,
+    Mean,
+}
+
+impl FromStr for NsamplePolicy {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<NsamplePolicy, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "first" => Ok(NsamplePolicy::First),
+            "min" => Ok(NsamplePolicy::Min),
+            "sum" => Ok(NsamplePolicy::Sum),
+            "mean" => Ok(NsamplePolicy::Mean),
+            other => Err(format!("Unknown nsample policy: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for NsamplePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+fn close<A, D>(array1: &Array<A, D>, array2: &Array<A, D>) -> bool
+where
+    A: AbsDiffEq<Epsilon = A> + Float,
+    D: Dimension,
+{
+    array1.shape() == array2.shape() && array1.abs_diff_eq(array2, A::from(1e-6).unwrap())
+}
+
+fn option_close<A, D>(array1: &Option<Array<A, D>>, array2: &Option<Array<A, D>>) -> bool
+where
+    A: AbsDiffEq<Epsilon = A> + Float,
+    D: Dimension,
+{
+    match (array1, array2) {
+        (Some(array1), Some(array2)) => close(array1, array2),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Each metadata field that can be listed in the overrides of
+    /// `compare_metadata`, with whether it matches between the objects.
+    fn metadata_matches(&self, other: &UVData<T, S>) -> [(&'static str, bool); 43] {
+        let (meta1, meta2) = (&self.meta, &other.meta);
+        let (arrays1, arrays2) = (&self.meta_arrays, &other.meta_arrays);
+        [
+            ("nbls", meta1.nbls == meta2.nbls),
+            ("nblts", meta1.nblts == meta2.nblts),
+            ("nspws", meta1.nspws == meta2.nspws),
+            ("npols", meta1.npols == meta2.npols),
+            ("ntimes", meta1.ntimes == meta2.ntimes),
+            ("nfreqs", meta1.nfreqs == meta2.nfreqs),
+            ("nphases", meta1.nphases == meta2.nphases),
+            ("nants_data", meta1.nants_data == meta2.nants_data),
+            ("blt_order", meta1.blt_order == meta2.blt_order),
+            ("vis_units", meta1.vis_units == meta2.vis_units),
+            (
+                "nants_telescope",
+                meta1.nants_telescope == meta2.nants_telescope,
+            ),
+            ("phase_type", meta1.phase_type == meta2.phase_type),
+            ("x_orientation", meta1.x_orientation == meta2.x_orientation),
+            ("instrument", meta1.instrument == meta2.instrument),
+            (
+                "telescope_name",
+                meta1.telescope_name == meta2.telescope_name,
+            ),
+            (
+                "telescope_location",
+                meta1
+                    .telescope_location
+                    .iter()
+                    .zip(meta2.telescope_location.iter())
+                    .all(|(x1, x2)| abs_diff_eq!(x1, x2, epsilon = 1e-6)),
+            ),
+            ("object_name", meta1.object_name == meta2.object_name),
+            (
+                "eq_coeffs_convention",
+                meta1.eq_coeffs_convention == meta2.eq_coeffs_convention,
+            ),
+            ("dut1", meta1.dut1 == meta2.dut1),
+            ("gst0", meta1.gst0 == meta2.gst0),
+            ("rdate", meta1.rdate == meta2.rdate),
+            ("earth_omega", meta1.earth_omega == meta2.earth_omega),
+            ("timesys", meta1.timesys == meta2.timesys),
+            (
+                "uvplane_reference_time",
+                meta1.uvplane_reference_time == meta2.uvplane_reference_time,
+            ),
+            ("spw_array", arrays1.spw_array == arrays2.spw_array),
+            ("uvw_array", close(&arrays1.uvw_array, &arrays2.uvw_array)),
+            (
+                "time_array",
+                close(&arrays1.time_array, &arrays2.time_array),
+            ),
+            ("lst_array", close(&arrays1.lst_array, &arrays2.lst_array)),
+            ("ant_1_array", arrays1.ant_1_array == arrays2.ant_1_array),
+            ("ant_2_array", arrays1.ant_2_array == arrays2.ant_2_array),
+            (
+                "baseline_array",
+                arrays1.baseline_array == arrays2.baseline_array,
+            ),
+            (
+                "freq_array",
+                close(&arrays1.freq_array, &arrays2.freq_array),
+            ),
+            ("spw_id_array", arrays1.spw_id_array == arrays2.spw_id_array),
+            (
+                "polarization_array",
+                arrays1.polarization_array == arrays2.polarization_array,
+            ),
+            (
+                "integration_time",
+                close(&arrays1.integration_time, &arrays2.integration_time),
+            ),
+            (
+                "channel_width",
+                close(&arrays1.channel_width, &arrays2.channel_width),
+            ),
+            (
+                "antenna_numbers",
+                arrays1.antenna_numbers == arrays2.antenna_numbers,
+            ),
+            (
+                "antenna_names",
+                arrays1.antenna_names == arrays2.antenna_names,
+            ),
+            (
+                "antenna_positions",
+                close(&arrays1.antenna_positions, &arrays2.antenna_positions),
+            ),
+            (
+                "eq_coeffs",
+                option_close(&arrays1.eq_coeffs, &arrays2.eq_coeffs),
+            ),
+            (
+                "antenna_diameters",
+                option_close(&arrays1.antenna_diameters, &arrays2.antenna_diameters),
+            ),
+            (
+                "phase_center_catalog",
+                arrays1.phase_center_catalog == arrays2.phase_center_catalog,
+            ),
+            (
+                "phase_center_id_array",
+                arrays1.phase_center_id_array == arrays2.phase_center_id_array,
+            ),
+        ]
+    }
+
+    /// The names of the metadata fields which differ between the objects.
+    ///
+    /// The history is never compared.
+    pub fn metadata_differences(&self, other: &UVData<T, S>) -> Vec<&'static str> {
+        self.metadata_matches(other)
+            .iter()
+            .filter(|(_, same)| !same)
+            .map(|&(name, _)| name)
+            .collect()
+    }
+
+    /// Check the metadata of the objects match, except for the fields named
+    /// in `override_params` which are allowed to differ.
+    pub fn compare_metadata(
+        &self,
+        other: &UVData<T, S>,
+        override_params: &[&str],
+    ) -> Result<(), String> {
+        let matches = self.metadata_matches(other);
+        if let Some(bad) = override_params
+            .iter()
+            .find(|&name| !matches.iter().any(|(field, _)| field == name))
+        {
+            return Err(format!("Unknown metadata field: {}.", bad));
+        }
+        let differences: Vec<&str> = matches
+            .iter()
+            .filter(|(name, same)| !same && !override_params.contains(name))
+            .map(|&(name, _)| name)
+            .collect();
+        match differences.is_empty() {
+            true => Ok(()),
+            false => Err(format!(
+                "Metadata do not match: {}.",
+                differences.join(", ")
+            )),
+        }
+    }
+
+    /// The blt of `other` holding the antenna pair and time of each blt, if
+    /// the blts of the objects pair up one to one.
+    fn matching_blts(&self, other: &UVData<T, S>) -> Option<Vec<usize>> {
+        let arrays = &self.meta_arrays;
+        let nblts = arrays.time_array.len();
+        if other.meta_arrays.time_array.len() != nblts {
+            return None;
+        }
+        let mut used = vec![false; nblts];
+        (0..nblts)
+            .map(|blt| {
+                let ind = other.antpair_time_blt(
+                    arrays.ant_1_array[blt],
+                    arrays.ant_2_array[blt],
+                    arrays.time_array[blt],
+                )?;
+                match std::mem::replace(&mut used[ind], true) {
+                    true => None,
+                    false => Some(ind),
+                }
+            })
+            .collect()
+    }
+
+    fn combine_vis(
+        &self,
+        other: &UVData<T, S>,
+        override_params: &[&str],
+        nsample_policy: NsamplePolicy,
+        subtract: bool,
+    ) -> Result<UVData<T, S>, String> {
+        // join the blts on antenna pair and time, so the blt order of `other`
+        // is free; otherwise they are compared position by position
+        let reordered;
+        let other = match self.matching_blts(other) {
+            Some(order) if order.iter().enumerate().any(|(blt, &ind)| blt != ind) => {
+                let mut copy = other.clone();
+                copy.take_blts(&order);
+                copy.meta.blt_order = self.meta.blt_order;
+                reordered = copy;
+                &reordered
+            }
+            _ => other,
+        };
+        self.compare_metadata(other, override_params)?;
+        let mut combined = self.clone();
+        match (&mut combined.data_array, &other.data_array) {
+            (Some(data1), Some(data2)) if data1.shape() == data2.shape() => {
+                data1.zip_mut_with(data2, |vis1, &vis2| match subtract {
+                    true => *vis1 = *vis1 - vis2,
+                    false => *vis1 = *vis1 + vis2,
+                })
+            }
+            (Some(_), Some(_)) => return Err("Data arrays have different shapes.".to_string()),
+            _ => return Err("Both objects need their data loaded.".to_string()),
+        }
+        if let (Some(flags1), Some(flags2)) = (&mut combined.flag_array, &other.flag_array) {
+            flags1.zip_mut_with(flags2, |flag1, &flag2| *flag1 |= flag2);
+        }
+        if let (Some(nsamples1), Some(nsamples2)) =
+            (&mut combined.nsample_array, &other.nsample_array)
+        {
+            let two = S::one() + S::one();
+            match nsample_policy {
+                NsamplePolicy::First => {}
+                NsamplePolicy::Min => nsamples1.zip_mut_with(nsamples2, |n1, &n2| *n1 = n1.min(n2)),
+                NsamplePolicy::Sum => nsamples1.zip_mut_with(nsamples2, |n1, &n2| *n1 = *n1 + n2),
+                NsamplePolicy::Mean => {
+                    nsamples1.zip_mut_with(nsamples2, |n1, &n2| *n1 = (*n1 + n2) / two)
+                }
+            }
+        }
+        let verb = match subtract {
+            true => "Differenced",
+            false => "Summed",
+        };
+        combined.meta.history = format!(
+            "{}\n{} with a second object whose history follows.\n{}",
+            self.meta.history, verb, other.meta.history
+        );
+        Ok(combined)
+    }
+
+    /// Add the visibilities of `other` to a copy of this object.
+    ///
+    /// Blts are joined on antenna pair and time, so `other` may store them
+    /// in any order. Metadata must match apart from `override_params`,
+    /// which keep the values of this object. Flags are combined with OR and
+    /// nsamples following `nsample_policy`.
+    pub fn sum_vis(
+        &self,
+        other: &UVData<T, S>,
+        override_params: &[&str],
+        nsample_policy: NsamplePolicy,
+    ) -> Result<UVData<T, S>, String> {
+        self.combine_vis(other, override_params, nsample_policy, false)
+    }
+
+    /// Subtract the visibilities of `other` from a copy of this object.
+    ///
+    /// See `sum_vis` for how metadata, flags and nsamples are handled.
+    pub fn diff_vis(
+        &self,
+        other: &UVData<T, S>,
+        override_params: &[&str],
+        nsample_policy: NsamplePolicy,
+    ) -> Result<UVData<T, S>, String> {
+        self.combine_vis(other, override_params, nsample_policy, true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::NsamplePolicy;
+    use crate::test_data::read_drift;
+    use std::str::FromStr;
+
+    #[test]
+    fn policy_from_str() {
+        assert_eq!(NsamplePolicy::from_str("Sum ").unwrap(), NsamplePolicy::Sum);
+        assert!(NsamplePolicy::from_str("max").is_err());
+    }
+
+    #[test]
+    fn sum_and_diff() {
+        let uvd = read_drift();
+        let mut other = uvd.clone();
+        other.flag_array.as_mut().unwrap()[[5, 1, 0]] = true;
+
+        let summed = uvd
+            .sum_vis(&other, &[], NsamplePolicy::Sum)
+            .expect("Cannot sum.");
+        let data = uvd.data_array.as_ref().unwrap();
+        assert_eq!(summed.data_array.unwrap(), data.mapv(|vis| vis * 2.0));
+        assert!(summed.flag_array.as_ref().unwrap()[[5, 1, 0]]);
+        assert_eq!(
+            summed.nsample_array.unwrap(),
+            uvd.nsample_array.as_ref().unwrap() * 2.0
+        );
+
+        let diffed = uvd
+            .diff_vis(&other, &[], NsamplePolicy::First)
+            .expect("Cannot difference.");
+        assert!(diffed
+            .data_array
+            .unwrap()
+            .iter()
+            .all(|vis| vis.norm() == 0.0));
+        assert_eq!(diffed.nsample_array, uvd.nsample_array);
+
+        // the same data in another blt order
+        let mut reversed = other.clone();
+        reversed.take_blts(&(0..uvd.meta.nblts as usize).rev().collect::<Vec<_>>());
+        let summed = uvd
+            .sum_vis(&reversed, &[], NsamplePolicy::Sum)
+            .expect("Cannot sum reordered data.");
+        assert_eq!(summed.meta_arrays, uvd.meta_arrays);
+        assert_eq!(summed.data_array.unwrap(), data.mapv(|vis| vis * 2.0));
+        assert!(summed.flag_array.as_ref().unwrap()[[5, 1, 0]]);
+        let diffed = uvd
+            .diff_vis(&reversed, &[], NsamplePolicy::First)
+            .expect("Cannot difference reordered data.");
+        assert!(diffed
+            .data_array
+            .unwrap()
+            .iter()
+            .all(|vis| vis.norm() == 0.0));
+        let mut missing = reversed.clone();
+        missing.take_blts(&(1..uvd.meta.nblts as usize).collect::<Vec<_>>());
+        assert!(uvd.sum_vis(&missing, &[], NsamplePolicy::Sum).is_err());
+    }
+
+    #[test]
+    fn metadata_must_match() {
+        let uvd = read_drift();
+        let mut other = uvd.clone();
+        other.meta.object_name = "elsewhere".to_string();
+        other.meta.history = "different".to_string();
+        other.meta_arrays.lst_array += 1.0;
+        assert_eq!(
+            uvd.metadata_differences(&other),
+            vec!["object_name", "lst_array"]
+        );
+        let err = uvd
+            .sum_vis(&other, &["object_name"], NsamplePolicy::Min)
+            .unwrap_err();
+        assert!(err.contains("lst_array"));
+        let summed = uvd
+            .sum_vis(&other, &["object_name", "lst_array"], NsamplePolicy::Min)
+            .expect("Cannot sum.");
+        assert_eq!(summed.meta.object_name, uvd.meta.object_name);
+        assert!(uvd
+            .compare_metadata(&other, &["lst_arrays"])
+            .unwrap_err()
+            .contains("Unknown"));
+    }
+}
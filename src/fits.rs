@@ -0,0 +1,588 @@
+use std::{convert::TryInto, fs, path::Path};
+
+const BLOCK: usize = 2880;
+const CARD: usize = 80;
+const HISTORY_LENGTH: usize = 72;
+
+/// A FITS header keyword value.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Value {
+    Logical(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Logical(value)
+    }
+}
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Int(value)
+    }
+}
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+        Value::Float(value)
+    }
+}
+impl From<&str> for Value {
+    fn from(value: &str) -> Value {
+        Value::Str(value.to_string())
+    }
+}
+
+impl Value {
+    fn to_card_value(&self) -> String {
+        match self {
+            Value::Logical(true) => format!("{:>20}", "T"),
+            Value::Logical(false) => format!("{:>20}", "F"),
+            Value::Int(value) => format!("{:>20}", value),
+            Value::Float(value) => format!("{:>20}", format!("{:E}", value)),
+            Value::Str(value) => format!("'{:<8}'", value.replace('\'', "''")),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Value, String> {
+        let text = text.trim_start();
+        if let Some(rest) = text.strip_prefix('\'') {
+            let mut value = String::new();
+            let mut chars = rest.chars().peekable();
+            while let Some(c) = chars.next() {
+                match (c, chars.peek()) {
+                    ('\'', Some('\'')) => {
+                        value.push('\'');
+                        chars.next();
+                    }
+                    ('\'', _) => return Ok(Value::Str(value.trim_end().to_string())),
+                    _ => value.push(c),
+                }
+            }
+            return Err(format!("Unterminated FITS string: {}.", text));
+        }
+        let text = text.split('/').next().unwrap_or("").trim();
+        match text {
+            "T" => Ok(Value::Logical(true)),
+            "F" => Ok(Value::Logical(false)),
+            _ => match text.parse::<i64>() {
+                Ok(value) => Ok(Value::Int(value)),
+                Err(_) => text
+                    .replace(['D', 'd'], "E")
+                    .parse::<f64>()
+                    .map(Value::Float)
+                    .map_err(|_| format!("Cannot parse FITS value: {}.", text)),
+            },
+        }
+    }
+}
+
+/// The keywords and history of a FITS header.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub(crate) struct Header {
+    cards: Vec<(String, Value)>,
+    pub history: Vec<String>,
+}
+
+impl Header {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.cards
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Set `key`, replacing any existing value.
+    pub fn set<V: Into<Value>>(&mut self, key: &str, value: V) {
+        let value = value.into();
+        match self.cards.iter_mut().find(|(name, _)| name == key) {
+            Some(card) => card.1 = value,
+            None => self.cards.push((key.to_string(), value)),
+        }
+    }
+
+    fn require(&self, key: &str) -> Result<&Value, String> {
+        self.get(key)
+            .ok_or_else(|| format!("FITS header is missing {}.", key))
+    }
+
+    pub fn string(&self, key: &str) -> Result<String, String> {
+        match self.require(key)? {
+            Value::Str(value) => Ok(value.clone()),
+            other => Err(format!(
+                "FITS keyword {} is not a string: {:?}.",
+                key, other
+            )),
+        }
+    }
+
+    pub fn int(&self, key: &str) -> Result<i64, String> {
+        match self.require(key)? {
+            Value::Int(value) => Ok(*value),
+            other => Err(format!(
+                "FITS keyword {} is not an integer: {:?}.",
+                key, other
+            )),
+        }
+    }
+
+    pub fn float(&self, key: &str) -> Result<f64, String> {
+        match self.require(key)? {
+            Value::Int(value) => Ok(*value as f64),
+            Value::Float(value) => Ok(*value),
+            other => Err(format!(
+                "FITS keyword {} is not a number: {:?}.",
+                key, other
+            )),
+        }
+    }
+
+    /// Add `text` as HISTORY cards, one or more per line.
+    pub fn add_history(&mut self, text: &str) {
+        for line in text.lines() {
+            let chars: Vec<char> = line.chars().collect();
+            match chars.is_empty() {
+                true => self.history.push(String::new()),
+                false => self
+                    .history
+                    .extend(chars.chunks(HISTORY_LENGTH).map(|c| c.iter().collect())),
+            }
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Header, String> {
+        let mut header = Header::default();
+        for card in bytes.chunks(CARD) {
+            if !card.is_ascii() {
+                return Err(format!(
+                    "FITS header card is not ASCII: {}.",
+                    String::from_utf8_lossy(card)
+                ));
+            }
+            let card = format!("{:<80}", std::str::from_utf8(card).unwrap());
+            let key = card[..8].trim();
+            match key {
+                "HISTORY" => header.history.push(card[8..].trim().to_string()),
+                "" | "COMMENT" | "END" => {}
+                _ if &card[8..10] == "= " => header.set(key, Value::parse(&card[10..])?),
+                _ => {}
+            }
+        }
+        Ok(header)
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut cards = Vec::new();
+        for (key, value) in &self.cards {
+            let card = format!("{:<8}= {}", key, value.to_card_value());
+            if card.len() > CARD || !card.is_ascii() {
+                return Err(format!("FITS keyword {} is too long or not ASCII.", key));
+            }
+            cards.push(card);
+        }
+        for line in &self.history {
+            cards.push(format!("HISTORY {}", line));
+        }
+        cards.push("END".to_string());
+        let mut bytes: Vec<u8> = cards
+            .iter()
+            .flat_map(|card| format!("{:<80}", card).into_bytes())
+            .collect();
+        bytes.resize(padded(bytes.len()), b' ');
+        Ok(bytes)
+    }
+}
+
+/// A binary table column.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Column {
+    Str(Vec<String>),
+    /// Integers with `repeat` values per row.
+    Int(usize, Vec<i64>),
+    /// Floats with `repeat` values per row.
+    Float(usize, Vec<f64>),
+}
+
+impl Column {
+    fn nrows(&self) -> usize {
+        match self {
+            Column::Str(values) => values.len(),
+            Column::Int(repeat, values) => values.len() / repeat.max(&1),
+            Column::Float(repeat, values) => values.len() / repeat.max(&1),
+        }
+    }
+
+    fn format(&self) -> (String, usize) {
+        match self {
+            Column::Str(values) => {
+                let width = values.iter().map(|value| value.len()).max().unwrap_or(0);
+                (format!("{}A", width.max(1)), width.max(1))
+            }
+            Column::Int(repeat, _) => (format!("{}J", repeat), 4 * repeat),
+            Column::Float(repeat, _) => (format!("{}D", repeat), 8 * repeat),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum HduData {
+    Empty,
+    /// Image values in file order, axes listed from NAXIS1 (fastest) on.
+    Image {
+        bitpix: i32,
+        axes: Vec<usize>,
+        data: Vec<f64>,
+    },
+    Table(Vec<(String, Column)>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Hdu {
+    pub header: Header,
+    pub data: HduData,
+}
+
+impl Hdu {
+    pub fn extname(&self) -> Option<String> {
+        self.header.string("EXTNAME").ok()
+    }
+
+    pub fn column(&self, name: &str) -> Result<&Column, String> {
+        match &self.data {
+            HduData::Table(columns) => columns
+                .iter()
+                .find(|(column, _)| column == name)
+                .map(|(_, column)| column)
+                .ok_or_else(|| format!("FITS table is missing column {}.", name)),
+            _ => Err("FITS HDU is not a binary table.".to_string()),
+        }
+    }
+
+    fn to_bytes(&self, primary: bool) -> Result<Vec<u8>, String> {
+        let mut header = Header::default();
+        match primary {
+            true => header.set("SIMPLE", true),
+            false => header.set(
+                "XTENSION",
+                match self.data {
+                    HduData::Table(_) => "BINTABLE",
+                    _ => "IMAGE",
+                },
+            ),
+        }
+        let data = match &self.data {
+            HduData::Empty => {
+                header.set("BITPIX", 8);
+                header.set("NAXIS", 0);
+                Vec::new()
+            }
+            HduData::Image { bitpix, axes, data } => {
+                header.set("BITPIX", *bitpix as i64);
+                header.set("NAXIS", axes.len() as i64);
+                for (ind, len) in axes.iter().enumerate() {
+                    header.set(&format!("NAXIS{}", ind + 1), *len as i64);
+                }
+                if axes.iter().product::<usize>() != data.len() {
+                    return Err("FITS image size does not match its axes.".to_string());
+                }
+                encode(*bitpix, data)?
+            }
+            HduData::Table(columns) => {
+                let nrows = columns.first().map_or(0, |(_, column)| column.nrows());
+                if columns.iter().any(|(_, column)| column.nrows() != nrows) {
+                    return Err("FITS table columns differ in length.".to_string());
+                }
+                let formats: Vec<(String, usize)> =
+                    columns.iter().map(|(_, column)| column.format()).collect();
+                let row_width: usize = formats.iter().map(|(_, width)| width).sum();
+                header.set("BITPIX", 8);
+                header.set("NAXIS", 2);
+                header.set("NAXIS1", row_width as i64);
+                header.set("NAXIS2", nrows as i64);
+                header.set("PCOUNT", 0);
+                header.set("GCOUNT", 1);
+                header.set("TFIELDS", columns.len() as i64);
+                for (ind, ((name, _), (format, _))) in columns.iter().zip(&formats).enumerate() {
+                    header.set(&format!("TTYPE{}", ind + 1), name.as_str());
+                    header.set(&format!("TFORM{}", ind + 1), format.as_str());
+                }
+                let mut bytes = Vec::with_capacity(row_width * nrows);
+                for row in 0..nrows {
+                    for ((_, column), (_, width)) in columns.iter().zip(&formats) {
+                        match column {
+                            Column::Str(values) => {
+                                let mut value = values[row].clone().into_bytes();
+                                value.resize(*width, b' ');
+                                bytes.extend(value);
+                            }
+                            Column::Int(repeat, values) => values[row * repeat..(row + 1) * repeat]
+                                .iter()
+                                .for_each(|&value| bytes.extend((value as i32).to_be_bytes())),
+                            Column::Float(repeat, values) => values
+                                [row * repeat..(row + 1) * repeat]
+                                .iter()
+                                .for_each(|&value| bytes.extend(value.to_be_bytes())),
+                        }
+                    }
+                }
+                bytes
+            }
+        };
+        if primary {
+            header.set("EXTEND", true);
+        }
+        for (key, value) in &self.header.cards {
+            header.set(key, value.clone());
+        }
+        header.history = self.header.history.clone();
+
+        let mut bytes = header.to_bytes()?;
+        let start = bytes.len();
+        bytes.extend(data);
+        bytes.resize(start + padded(bytes.len() - start), 0);
+        Ok(bytes)
+    }
+}
+
+fn padded(len: usize) -> usize {
+    len.div_ceil(BLOCK) * BLOCK
+}
+
+fn encode(bitpix: i32, data: &[f64]) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(data.len() * (bitpix.unsigned_abs() as usize / 8));
+    for &value in data {
+        match bitpix {
+            8 => bytes.push(value as u8),
+            16 => bytes.extend((value as i16).to_be_bytes()),
+            32 => bytes.extend((value as i32).to_be_bytes()),
+            64 => bytes.extend((value as i64).to_be_bytes()),
+            -32 => bytes.extend((value as f32).to_be_bytes()),
+            -64 => bytes.extend(value.to_be_bytes()),
+            other => return Err(format!("Unknown FITS BITPIX: {}.", other)),
+        }
+    }
+    Ok(bytes)
+}
+
+fn decode(bitpix: i32, bytes: &[u8]) -> Result<Vec<f64>, String> {
+    let size = bitpix.unsigned_abs() as usize / 8;
+    bytes
+        .chunks_exact(size.max(1))
+        .map(|chunk| match bitpix {
+            8 => Ok(chunk[0] as f64),
+            16 => Ok(i16::from_be_bytes(chunk.try_into().unwrap()) as f64),
+            32 => Ok(i32::from_be_bytes(chunk.try_into().unwrap()) as f64),
+            64 => Ok(i64::from_be_bytes(chunk.try_into().unwrap()) as f64),
+            -32 => Ok(f32::from_be_bytes(chunk.try_into().unwrap()) as f64),
+            -64 => Ok(f64::from_be_bytes(chunk.try_into().unwrap())),
+            other => Err(format!("Unknown FITS BITPIX: {}.", other)),
+        })
+        .collect()
+}
+
+fn decode_table(header: &Header, bytes: &[u8]) -> Result<Vec<(String, Column)>, String> {
+    let row_width = header.int("NAXIS1")? as usize;
+    let nrows = header.int("NAXIS2")? as usize;
+    let mut columns = Vec::new();
+    let mut offset = 0;
+    for ind in 1..=header.int("TFIELDS")? {
+        let name = header.string(&format!("TTYPE{}", ind))?;
+        let format = header.string(&format!("TFORM{}", ind))?;
+        let format = format.trim();
+        let (repeat, code) = format.split_at(format.len() - 1);
+        let repeat = match repeat {
+            "" => 1,
+            repeat => repeat
+                .parse::<usize>()
+                .map_err(|_| format!("Cannot parse FITS column format {}.", format))?,
+        };
+        let size = match code {
+            "A" | "L" | "B" => 1,
+            "I" => 2,
+            "J" | "E" => 4,
+            "K" | "D" => 8,
+            other => return Err(format!("Unsupported FITS column format: {}.", other)),
+        };
+        let fields = (0..nrows).map(|row| {
+            let start = row * row_width + offset;
+            &bytes[start..start + size * repeat]
+        });
+        let column = match code {
+            "A" => Column::Str(
+                fields
+                    .map(|field| {
+                        String::from_utf8_lossy(field)
+                            .trim_end_matches(['\0', ' '])
+                            .to_string()
+                    })
+                    .collect(),
+            ),
+            "L" => Column::Int(
+                repeat,
+                fields
+                    .flatten()
+                    .map(|&byte| (byte == b'T') as i64)
+                    .collect(),
+            ),
+            "E" | "D" => Column::Float(
+                repeat,
+                fields
+                    .flat_map(|field| decode(-8 * size as i32, field))
+                    .flatten()
+                    .collect(),
+            ),
+            _ => Column::Int(
+                repeat,
+                fields
+                    .flat_map(|field| decode(8 * size as i32, field))
+                    .flatten()
+                    .map(|value| value as i64)
+                    .collect(),
+            ),
+        };
+        offset += size * repeat;
+        columns.push((name, column));
+    }
+    Ok(columns)
+}
+
+/// Read every HDU of a FITS file.
+pub(crate) fn read_file<P: AsRef<Path>>(fname: P) -> Result<Vec<Hdu>, String> {
+    let bytes = fs::read(fname).map_err(|err| err.to_string())?;
+    let mut hdus = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let end = (start..bytes.len())
+            .step_by(CARD)
+            .find(|&card| bytes[card..].starts_with(b"END     "))
+            .ok_or("FITS header has no END card.")?;
+        let header = Header::parse(&bytes[start..end])?;
+        let data_start = start + padded(end + CARD - start);
+
+        let bitpix = header.int("BITPIX")? as i32;
+        let naxis = header.int("NAXIS")? as usize;
+        let axes = (1..=naxis)
+            .map(|ind| Ok(header.int(&format!("NAXIS{}", ind))? as usize))
+            .collect::<Result<Vec<usize>, String>>()?;
+        let pcount = header.int("PCOUNT").unwrap_or(0) as usize;
+        let size = match naxis {
+            0 => 0,
+            _ => axes.iter().product::<usize>() * bitpix.unsigned_abs() as usize / 8 + pcount,
+        };
+        let data_bytes = bytes
+            .get(data_start..data_start + size)
+            .ok_or("FITS file is truncated.")?;
+        let data = match (header.string("XTENSION").as_deref(), naxis) {
+            (Ok("BINTABLE"), _) => HduData::Table(decode_table(&header, data_bytes)?),
+            (_, 0) => HduData::Empty,
+            _ => {
+                let scale = header.float("BSCALE").unwrap_or(1.0);
+                let zero = header.float("BZERO").unwrap_or(0.0);
+                let data = decode(bitpix, data_bytes)?
+                    .into_iter()
+                    .map(|value| value * scale + zero)
+                    .collect();
+                HduData::Image { bitpix, axes, data }
+            }
+        };
+        hdus.push(Hdu { header, data });
+        start = data_start + padded(size);
+    }
+    Ok(hdus)
+}
+
+/// Write `hdus` to a FITS file, the first being the primary HDU.
+///
+/// Structural keywords are generated from the data.
+pub(crate) fn write_file<P: AsRef<Path>>(
+    fname: P,
+    hdus: &[Hdu],
+    overwrite: bool,
+) -> Result<(), String> {
+    if !overwrite && fname.as_ref().exists() {
+        return Err(format!("File {} exists.", fname.as_ref().display()));
+    }
+    let mut bytes = Vec::new();
+    for (ind, hdu) in hdus.iter().enumerate() {
+        bytes.extend(hdu.to_bytes(ind == 0)?);
+    }
+    fs::write(fname, bytes).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_file, write_file, Column, Hdu, HduData, Header, Value};
+    use tempdir::TempDir;
+
+    #[test]
+    fn card_values() {
+        for value in [
+            Value::Logical(true),
+            Value::Int(-12),
+            Value::Float(1.5e-9),
+            Value::Float(150.0),
+            Value::Str("it's".to_string()),
+        ] {
+            assert_eq!(Value::parse(&value.to_card_value()).unwrap(), value);
+        }
+        assert_eq!(
+            Value::parse("  1.0D3 / a comment").unwrap(),
+            Value::Float(1000.0)
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let tmp_dir = TempDir::new("fits").unwrap();
+        let path = tmp_dir.path().join("test.fits");
+        let mut header = Header::default();
+        header.set("TELESCOP", "HERA");
+        header.set("INTTIME", 10.0);
+        header.add_history(&"a".repeat(100));
+        let image = Hdu {
+            header,
+            data: HduData::Image {
+                bitpix: -64,
+                axes: vec![3, 2],
+                data: vec![0.0, 1.5, -2.0, 3.0, 4.0, 5.0],
+            },
+        };
+        let mut header = Header::default();
+        header.set("EXTNAME", "ANTENNAS");
+        let table = Hdu {
+            header,
+            data: HduData::Table(vec![
+                (
+                    "ANTNAME".to_string(),
+                    Column::Str(vec!["ant0".to_string(), "ant12".to_string()]),
+                ),
+                ("ANTINDEX".to_string(), Column::Int(1, vec![0, 12])),
+                (
+                    "ANTXYZ".to_string(),
+                    Column::Float(3, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+                ),
+            ]),
+        };
+        write_file(&path, &[image.clone(), table.clone()], false).unwrap();
+        assert!(write_file(&path, std::slice::from_ref(&image), false).is_err());
+
+        let hdus = read_file(&path).unwrap();
+        assert_eq!(hdus.len(), 2);
+        assert_eq!(hdus[0].data, image.data);
+        assert_eq!(hdus[0].header.string("TELESCOP").unwrap(), "HERA");
+        assert_eq!(hdus[0].header.int("NAXIS2").unwrap(), 2);
+        assert_eq!(hdus[0].header.history.len(), 2);
+        assert_eq!(hdus[1].extname().unwrap(), "ANTENNAS");
+        assert_eq!(hdus[1].data, table.data);
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let card = bytes
+            .chunks(80)
+            .position(|card| card.starts_with(b"TELESCOP"))
+            .unwrap();
+        bytes[80 * card + 3] = 0xc3;
+        std::fs::write(&path, bytes).unwrap();
+        assert!(read_file(&path).unwrap_err().contains("not ASCII"));
+    }
+}
@@ -0,0 +1,193 @@
+use approx::AbsDiffEq;
+use ndarray::{s, Array, Axis, Ix3};
+use num_traits::Float;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::UVData;
+
+fn check_fraction(fraction: f64) -> Result<(), String> {
+    match (0.0..=1.0).contains(&fraction) {
+        true => Ok(()),
+        false => Err(format!(
+            "Flag fractions must be between 0 and 1, got {}.",
+            fraction
+        )),
+    }
+}
+
+fn flagged_fraction<'a>(flags: impl Iterator<Item = &'a bool>) -> f64 {
+    let (count, total) = flags.fold((0usize, 0usize), |(count, total), &flag| {
+        (count + flag as usize, total + 1)
+    });
+    match total {
+        0 => 0.0,
+        _ => count as f64 / total as f64,
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    fn loaded_flags(&mut self) -> Result<&mut Array<bool, Ix3>, String> {
+        self.flag_array
+            .as_mut()
+            .ok_or_else(|| "Flags must be loaded to extend them.".to_string())
+    }
+
+    /// Flag every channel with more than `fraction` of its samples flagged
+    /// over all baseline-times and polarizations.
+    pub fn flag_channels_by_occupancy(&mut self, fraction: f64) -> Result<(), String> {
+        check_fraction(fraction)?;
+        let flags = self.loaded_flags()?;
+        for mut channel in flags.axis_iter_mut(Axis(1)) {
+            if flagged_fraction(channel.iter()) > fraction {
+                channel.fill(true);
+            }
+        }
+        Ok(())
+    }
+
+    /// Flag every integration with more than `fraction` of its channels
+    /// flagged over all baselines and polarizations at that time.
+    pub fn flag_times_by_occupancy(&mut self, fraction: f64) -> Result<(), String> {
+        check_fraction(fraction)?;
+        let mut time_blts: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+        for (blt, time) in self.meta_arrays.time_array.iter().enumerate() {
+            time_blts.entry(time.to_bits()).or_default().push(blt);
+        }
+        let flags = self.loaded_flags()?;
+        for blts in time_blts.values() {
+            let occupancy = flagged_fraction(
+                blts.iter()
+                    .flat_map(|&blt| flags.slice(s![blt, .., ..]).into_iter()),
+            );
+            if occupancy > fraction {
+                blts.iter()
+                    .for_each(|&blt| flags.slice_mut(s![blt, .., ..]).fill(true));
+            }
+        }
+        Ok(())
+    }
+
+    /// Flag every baseline of the antennas with more than `fraction` of the
+    /// samples of their baselines flagged.
+    ///
+    /// Occupancies are computed before any flags are added, so flagging one
+    /// antenna does not push its partners over the threshold.
+    pub fn flag_antennas_by_occupancy(&mut self, fraction: f64) -> Result<(), String> {
+        check_fraction(fraction)?;
+        let ant_1_array = self.meta_arrays.ant_1_array.clone();
+        let ant_2_array = self.meta_arrays.ant_2_array.clone();
+        let flags = self.loaded_flags()?;
+
+        // (flagged, total) samples per antenna
+        let mut counts: BTreeMap<u32, (usize, usize)> = BTreeMap::new();
+        for (blt, blt_flags) in flags.outer_iter().enumerate() {
+            let nflagged = blt_flags.iter().filter(|&&flag| flag).count();
+            let mut ants = vec![ant_1_array[blt]];
+            if ant_2_array[blt] != ant_1_array[blt] {
+                ants.push(ant_2_array[blt]);
+            }
+            for ant in ants {
+                let count = counts.entry(ant).or_insert((0, 0));
+                count.0 += nflagged;
+                count.1 += blt_flags.len();
+            }
+        }
+        let bad_ants: BTreeSet<u32> = counts
+            .into_iter()
+            .filter(|(_, (nflagged, total))| *nflagged as f64 > fraction * *total as f64)
+            .map(|(ant, _)| ant)
+            .collect();
+
+        for (blt, mut blt_flags) in flags.outer_iter_mut().enumerate() {
+            if bad_ants.contains(&ant_1_array[blt]) || bad_ants.contains(&ant_2_array[blt]) {
+                blt_flags.fill(true);
+            }
+        }
+        Ok(())
+    }
+
+    /// Flag a sample in every polarization if it is flagged in any.
+    pub fn propagate_flags_across_pols(&mut self) -> Result<(), String> {
+        let flags = self.loaded_flags()?;
+        for mut pols in flags.lanes_mut(Axis(2)) {
+            if pols.iter().any(|&flag| flag) {
+                pols.fill(true);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_data::read_unflagged;
+    use ndarray::s;
+
+    #[test]
+    fn channels_and_pols() {
+        let mut uvd = read_unflagged();
+        let flags = uvd.flag_array.as_mut().unwrap();
+        flags.slice_mut(s![..120, 1, ..]).fill(true);
+        flags.slice_mut(s![..80, 2, ..]).fill(true);
+        flags[[3, 0, 1]] = true;
+
+        uvd.flag_channels_by_occupancy(0.5).expect("Cannot flag.");
+        let flags = uvd.flag_array.as_ref().unwrap();
+        assert!(flags.slice(s![.., 1, ..]).iter().all(|&flag| flag));
+        assert!(!flags[[150, 2, 0]]);
+        assert!(uvd.flag_channels_by_occupancy(1.5).is_err());
+
+        uvd.propagate_flags_across_pols().expect("Cannot flag.");
+        let flags = uvd.flag_array.as_ref().unwrap();
+        assert!(flags[[3, 0, 0]]);
+        assert!(!flags[[4, 0, 0]]);
+    }
+
+    #[test]
+    fn times_by_occupancy() {
+        let mut uvd = read_unflagged();
+        let time = uvd.meta_arrays.time_array[0];
+        let blts: Vec<usize> = (0..uvd.meta.nblts as usize)
+            .filter(|&blt| uvd.meta_arrays.time_array[blt] == time)
+            .collect();
+        let flags = uvd.flag_array.as_mut().unwrap();
+        for &blt in blts.iter().take(6) {
+            flags.slice_mut(s![blt, .., ..]).fill(true);
+        }
+
+        uvd.flag_times_by_occupancy(0.5).expect("Cannot flag.");
+        let flags = uvd.flag_array.as_ref().unwrap();
+        for blt in 0..uvd.meta.nblts as usize {
+            let flagged = flags.slice(s![blt, .., ..]).iter().all(|&flag| flag);
+            assert_eq!(flagged, blts.contains(&blt));
+        }
+    }
+
+    #[test]
+    fn antennas_by_occupancy() {
+        let mut uvd = read_unflagged();
+        let arrays = uvd.meta_arrays.clone();
+        // half of the (0, 1) samples: antenna 0 and 1 are each 1/8 flagged
+        let flags = uvd.flag_array.as_mut().unwrap();
+        for blt in 0..arrays.ant_1_array.len() {
+            if (arrays.ant_1_array[blt], arrays.ant_2_array[blt]) == (0, 1) {
+                flags.slice_mut(s![blt, ..2, ..]).fill(true);
+            }
+        }
+        let mut high = uvd.clone();
+        high.flag_antennas_by_occupancy(0.2).expect("Cannot flag.");
+        assert_eq!(high.flag_array, uvd.flag_array);
+
+        uvd.flag_antennas_by_occupancy(0.1).expect("Cannot flag.");
+        let flags = uvd.flag_array.as_ref().unwrap();
+        for blt in 0..arrays.ant_1_array.len() {
+            let ants = [arrays.ant_1_array[blt], arrays.ant_2_array[blt]];
+            let flagged = flags.slice(s![blt, .., ..]).iter().all(|&flag| flag);
+            assert_eq!(flagged, ants.contains(&0) || ants.contains(&1));
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use ndarray::{Array, Ix2};
+use std::{f64::consts::PI, str::FromStr};
+
+use super::noise::{BOLTZMANN, JANSKY};
+use super::utils::SPEED_OF_LIGHT;
+
+/// Pixel numbering scheme of a HEALPix map.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HealpixOrdering {
+    Ring,
+    Nested,
+}
+
+impl FromStr for HealpixOrdering {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<HealpixOrdering, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "ring" => Ok(HealpixOrdering::Ring),
+            "nested" | "nest" => Ok(HealpixOrdering::Nested),
+            other => Err(format!("Unknown HEALPix ordering: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for HealpixOrdering {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Brightness unit of a HEALPix map.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SkyUnit {
+    /// Specific intensity in Jy/sr.
+    JanskyPerSr,
+    /// Rayleigh-Jeans brightness temperature in K.
+    Kelvin,
+}
+
+impl FromStr for SkyUnit {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<SkyUnit, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "jy/sr" | "jy sr^-1" => Ok(SkyUnit::JanskyPerSr),
+            "k" | "kelvin" => Ok(SkyUnit::Kelvin),
+            other => Err(format!("Unknown sky brightness unit: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for SkyUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Face of each base pixel: its ring number in units of nside and its
+/// longitude in units of pi / 4.
+const JRLL: [i64; 12] = [2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4];
+const JPLL: [i64; 12] = [1, 3, 5, 7, 0, 2, 4, 6, 1, 3, 5, 7];
+
+fn isqrt(value: i64) -> i64 {
+    let mut root = (value as f64).sqrt() as i64;
+    while root * root > value {
+        root -= 1;
+    }
+    while (root + 1) * (root + 1) <= value {
+        root += 1;
+    }
+    root
+}
+
+/// Every other bit of `value`, starting from the lowest.
+fn compress_bits(value: i64) -> i64 {
+    (0..32).fold(0, |out, bit| out | (((value >> (2 * bit)) & 1) << bit))
+}
+
+/// Colatitude and longitude in radians of the center of RING pixel `pix`.
+fn pix2ang_ring(nside: i64, pix: i64) -> (f64, f64) {
+    let npix = 12 * nside * nside;
+    let ncap = 2 * nside * (nside - 1);
+    let fact2 = 4.0 / npix as f64;
+    let fact1 = (2 * nside) as f64 * fact2;
+    let (z, phi) = if pix < ncap {
+        let iring = (1 + isqrt(1 + 2 * pix)) >> 1;
+        let iphi = pix + 1 - 2 * iring * (iring - 1);
+        (
+            1.0 - (iring * iring) as f64 * fact2,
+            (iphi as f64 - 0.5) * PI / (2 * iring) as f64,
+        )
+    } else if pix < npix - ncap {
+        let ip = pix - ncap;
+        let iring = ip / (4 * nside) + nside;
+        let iphi = ip % (4 * nside) + 1;
+        let fodd = match (iring + nside) & 1 {
+            1 => 1.0,
+            _ => 0.5,
+        };
+        (
+            (2 * nside - iring) as f64 * fact1,
+            (iphi as f64 - fodd) * PI / (2 * nside) as f64,
+        )
+    } else {
+        let ip = npix - pix;
+        let iring = (1 + isqrt(2 * ip - 1)) >> 1;
+        let iphi = 4 * iring + 1 - (ip - 2 * iring * (iring - 1));
+        (
+            (iring * iring) as f64 * fact2 - 1.0,
+            (iphi as f64 - 0.5) * PI / (2 * iring) as f64,
+        )
+    };
+    (z.acos(), phi)
+}
+
+/// Colatitude and longitude in radians of the center of NESTED pixel `pix`.
+fn pix2ang_nest(nside: i64, pix: i64) -> (f64, f64) {
+    let npface = nside * nside;
+    let fact2 = 4.0 / (12 * npface) as f64;
+    let fact1 = (2 * nside) as f64 * fact2;
+    let face = (pix / npface) as usize;
+    let ipf = pix % npface;
+    let ix = compress_bits(ipf);
+    let iy = compress_bits(ipf >> 1);
+    let jr = JRLL[face] * nside - ix - iy - 1;
+    let (nr, z, kshift) = if jr < nside {
+        (jr, 1.0 - (jr * jr) as f64 * fact2, 0)
+    } else if jr > 3 * nside {
+        let nr = 4 * nside - jr;
+        (nr, (nr * nr) as f64 * fact2 - 1.0, 0)
+    } else {
+        (nside, (2 * nside - jr) as f64 * fact1, (jr - nside) & 1)
+    };
+    let mut jp = (JPLL[face] * nr + ix - iy + 1 + kshift) / 2;
+    if jp > 4 * nside {
+        jp -= 4 * nside;
+    }
+    if jp < 1 {
+        jp += 4 * nside;
+    }
+    (
+        z.acos(),
+        (jp as f64 - (kshift + 1) as f64 * 0.5) * PI / (2 * nr) as f64,
+    )
+}
+
+/// A HEALPix map in equatorial coordinates with one row of pixels per
+/// frequency.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HealpixMap {
+    pub nside: u32,
+    pub ordering: HealpixOrdering,
+    pub unit: SkyUnit,
+    /// Shaped (nfreqs, npix).
+    pub data: Array<f64, Ix2>,
+}
+
+impl HealpixMap {
+    /// Build a map, checking `data` has 12 nside^2 pixels per row.
+    pub fn new(
+        nside: u32,
+        ordering: HealpixOrdering,
+        unit: SkyUnit,
+        data: Array<f64, Ix2>,
+    ) -> Result<HealpixMap, String> {
+        if nside == 0 || (ordering == HealpixOrdering::Nested && !nside.is_power_of_two()) {
+            return Err(format!(
+                "Invalid nside {} for {} ordering.",
+                nside, ordering
+            ));
+        }
+        let map = HealpixMap {
+            nside,
+            ordering,
+            unit,
+            data,
+        };
+        match map.data.ncols() == map.npix() {
+            true => Ok(map),
+            false => Err(format!(
+                "Maps of nside {} have {} pixels, not {}.",
+                nside,
+                map.npix(),
+                map.data.ncols()
+            )),
+        }
+    }
+
+    pub fn npix(&self) -> usize {
+        12 * self.nside as usize * self.nside as usize
+    }
+
+    /// Solid angle of each pixel in steradians.
+    pub fn pixel_area(&self) -> f64 {
+        4.0 * PI / self.npix() as f64
+    }
+
+    /// Colatitude and longitude in radians of the center of `pix`.
+    pub fn pix2ang(&self, pix: usize) -> (f64, f64) {
+        match self.ordering {
+            HealpixOrdering::Ring => pix2ang_ring(self.nside as i64, pix as i64),
+            HealpixOrdering::Nested => pix2ang_nest(self.nside as i64, pix as i64),
+        }
+    }
+
+    /// The map in Jy/sr with row `freq` at `freqs[freq]` Hz, converting
+    /// brightness temperatures by the Rayleigh-Jeans law I = 2 k nu^2 T / c^2.
+    pub fn jy_per_sr(&self, freqs: &[f64]) -> Result<Array<f64, Ix2>, String> {
+        if freqs.len() != self.data.nrows() {
+            return Err(format!(
+                "Expected {} frequencies for the map, found {}.",
+                self.data.nrows(),
+                freqs.len()
+            ));
+        }
+        let mut data = self.data.clone();
+        if self.unit == SkyUnit::Kelvin {
+            for (mut row, freq) in data.outer_iter_mut().zip(freqs) {
+                let scale = 2.0 * BOLTZMANN * (freq / SPEED_OF_LIGHT).powi(2) / JANSKY;
+                row.mapv_inplace(|temp| temp * scale);
+            }
+        }
+        Ok(data)
+    }
+
+    /// Right ascension and declination in radians of the center of `pix`.
+    pub fn pix2radec(&self, pix: usize) -> (f64, f64) {
+        let (theta, phi) = self.pix2ang(pix);
+        (phi, PI / 2.0 - theta)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HealpixMap, HealpixOrdering, SkyUnit};
+    use ndarray::Array;
+    use std::f64::consts::PI;
+
+    fn directions(nside: u32, ordering: HealpixOrdering) -> Vec<(f64, f64)> {
+        let map = HealpixMap::new(
+            nside,
+            ordering,
+            SkyUnit::JanskyPerSr,
+            Array::zeros((1, 12 * nside as usize * nside as usize)),
+        )
+        .unwrap();
+        let mut dirs: Vec<(f64, f64)> = (0..map.npix()).map(|pix| map.pix2ang(pix)).collect();
+        dirs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        dirs
+    }
+
+    #[test]
+    fn ring_pixels() {
+        let map = HealpixMap::new(
+            1,
+            HealpixOrdering::Ring,
+            SkyUnit::JanskyPerSr,
+            Array::zeros((1, 12)),
+        )
+        .unwrap();
+        let (theta, phi) = map.pix2ang(0);
+        assert_abs_diff_eq!(theta, (2.0f64 / 3.0).acos(), epsilon = 1e-12);
+        assert_abs_diff_eq!(phi, PI / 4.0, epsilon = 1e-12);
+        let (theta, phi) = map.pix2ang(5);
+        assert_abs_diff_eq!(theta, PI / 2.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(phi, PI / 2.0, epsilon = 1e-12);
+        let (theta, phi) = map.pix2ang(11);
+        assert_abs_diff_eq!(theta, (-2.0f64 / 3.0).acos(), epsilon = 1e-12);
+        assert_abs_diff_eq!(phi, 7.0 * PI / 4.0, epsilon = 1e-12);
+
+        // pixels have equal area, so their heights average to zero
+        let dirs = directions(8, HealpixOrdering::Ring);
+        let mean_z: f64 = dirs.iter().map(|dir| dir.0.cos()).sum::<f64>() / dirs.len() as f64;
+        assert_abs_diff_eq!(mean_z, 0.0, epsilon = 1e-12);
+        assert!(dirs.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn nested_matches_ring() {
+        for &nside in &[1, 2, 4, 16] {
+            let ring = directions(nside, HealpixOrdering::Ring);
+            let nest = directions(nside, HealpixOrdering::Nested);
+            for (a, b) in ring.iter().zip(nest.iter()) {
+                assert_abs_diff_eq!(a.0, b.0, epsilon = 1e-12);
+                assert_abs_diff_eq!(a.1, b.1, epsilon = 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn kelvin_maps() {
+        let data = Array::from_elem((2, 12), 2.0);
+        let map = HealpixMap::new(1, HealpixOrdering::Ring, SkyUnit::Kelvin, data.clone()).unwrap();
+        let intensity = map.jy_per_sr(&[150e6, 300e6]).unwrap();
+        // 1 K at 150 MHz is about 691 Jy/sr, scaling as nu^2
+        assert_abs_diff_eq!(intensity[[0, 3]], 2.0 * 691.28, epsilon = 0.01);
+        assert_abs_diff_eq!(intensity[[1, 3]], 8.0 * 691.28, epsilon = 0.04);
+        assert!(map.jy_per_sr(&[150e6]).is_err());
+
+        let map =
+            HealpixMap::new(1, HealpixOrdering::Ring, SkyUnit::JanskyPerSr, data.clone()).unwrap();
+        assert_eq!(map.jy_per_sr(&[150e6, 300e6]).unwrap(), data);
+        assert_eq!(" Kelvin".parse::<SkyUnit>(), Ok(SkyUnit::Kelvin));
+        assert_eq!("Jy/sr".parse::<SkyUnit>(), Ok(SkyUnit::JanskyPerSr));
+        assert!("mK".parse::<SkyUnit>().is_err());
+    }
+
+    #[test]
+    fn bad_maps() {
+        assert!(HealpixMap::new(
+            3,
+            HealpixOrdering::Nested,
+            SkyUnit::JanskyPerSr,
+            Array::zeros((1, 108))
+        )
+        .is_err());
+        assert!(HealpixMap::new(
+            3,
+            HealpixOrdering::Ring,
+            SkyUnit::Kelvin,
+            Array::zeros((1, 108))
+        )
+        .is_ok());
+        assert!(HealpixMap::new(
+            2,
+            HealpixOrdering::Ring,
+            SkyUnit::Kelvin,
+            Array::zeros((1, 12))
+        )
+        .is_err());
+        assert_eq!(
+            "nest ".parse::<HealpixOrdering>(),
+            Ok(HealpixOrdering::Nested)
+        );
+        assert!("galactic".parse::<HealpixOrdering>().is_err());
+    }
+}
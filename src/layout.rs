@@ -0,0 +1,500 @@
+use approx::AbsDiffEq;
+use hdf5::types::FixedAscii;
+use ndarray::{Array, Ix1, Ix2};
+use num_traits::Float;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryInto, fs, path::Path, str::FromStr};
+
+use super::utils;
+use super::UVData;
+
+/// Antenna names, numbers, positions (ECEF relative to the telescope
+/// location, meters) and optionally diameters (meters).
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct AntennaLayout {
+    pub antenna_names: Vec<String>,
+    pub antenna_numbers: Vec<u32>,
+    pub antenna_positions: Vec<[f64; 3]>,
+    pub antenna_diameters: Option<Vec<f64>>,
+}
+
+/// The frame of the positions in a layout file.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LayoutFrame {
+    /// East, north, up in meters from the telescope location.
+    Enu,
+    /// Absolute earth-centered earth-fixed meters.
+    Ecef,
+}
+
+impl FromStr for LayoutFrame {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<LayoutFrame, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "enu" => Ok(LayoutFrame::Enu),
+            "ecef" => Ok(LayoutFrame::Ecef),
+            other => Err(format!("Unknown layout frame: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for LayoutFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+fn degrees_latlonalt(location: [f64; 3]) -> (f64, f64, f64) {
+    let (lat, lon, alt) = utils::latlonalt_from_xyz(location);
+    (lat.to_degrees(), lon.to_degrees(), alt)
+}
+
+fn rows_to_array(rows: &[[f64; 3]]) -> Array<f64, Ix2> {
+    Array::from_shape_vec((rows.len(), 3), rows.iter().flatten().cloned().collect())
+        .expect("Rows of three always fit an (N, 3) array.")
+}
+
+fn array_to_rows(array: &Array<f64, Ix2>) -> Vec<[f64; 3]> {
+    array
+        .outer_iter()
+        .map(|row| [row[0], row[1], row[2]])
+        .collect()
+}
+
+impl AntennaLayout {
+    pub fn nants(&self) -> usize {
+        self.antenna_numbers.len()
+    }
+
+    /// Check the per-antenna lists have the same length.
+    pub fn check(&self) -> Result<(), String> {
+        let nants = self.nants();
+        let diameters_ok = match &self.antenna_diameters {
+            Some(diams) => diams.len() == nants,
+            None => true,
+        };
+        match self.antenna_names.len() == nants
+            && self.antenna_positions.len() == nants
+            && diameters_ok
+        {
+            true => Ok(()),
+            false => Err("Antenna layout has mismatched lengths.".to_string()),
+        }
+    }
+
+    /// Positions in east, north, up meters from `telescope_location` (ECEF).
+    pub fn enu_positions(&self, telescope_location: [f64; 3]) -> Array<f64, Ix2> {
+        let (lat, lon, alt) = degrees_latlonalt(telescope_location);
+        let ecef =
+            rows_to_array(&self.antenna_positions) + Array::from(telescope_location.to_vec());
+        utils::enu_from_ecef(&ecef, lat, lon, alt)
+    }
+
+    /// Set the positions from east, north, up meters about `telescope_location`.
+    pub fn set_enu_positions(&mut self, enu: &Array<f64, Ix2>, telescope_location: [f64; 3]) {
+        let (lat, lon, alt) = degrees_latlonalt(telescope_location);
+        let ecef =
+            utils::ecef_from_enu(enu, lat, lon, alt) - Array::from(telescope_location.to_vec());
+        self.antenna_positions = array_to_rows(&ecef);
+    }
+
+    /// Read a layout from a CSV file.
+    ///
+    /// Columns are separated by commas or whitespace and named in a header
+    /// row: `name`, `number`, either `e`, `n`, `u` (meters from
+    /// `telescope_location`) or `x`, `y`, `z` (absolute ECEF meters), and
+    /// optionally `diameter`. Other columns (e.g. `beamid`) are ignored.
+    pub fn from_csv<P: AsRef<Path>>(
+        path: P,
+        telescope_location: [f64; 3],
+    ) -> Result<AntennaLayout, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let split = |line: &str| -> Vec<String> {
+            line.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty())
+                .map(|field| field.to_string())
+                .collect()
+        };
+        let mut lines = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+        let header: Vec<String> = split(lines.next().ok_or("Layout file is empty.")?)
+            .iter()
+            .map(|name| name.to_lowercase())
+            .collect();
+        let column = |name: &str| header.iter().position(|col| col == name);
+        let (frame, pos_cols) = match (
+            [column("e"), column("n"), column("u")],
+            [column("x"), column("y"), column("z")],
+        ) {
+            ([Some(e), Some(n), Some(u)], _) => (LayoutFrame::Enu, [e, n, u]),
+            (_, [Some(x), Some(y), Some(z)]) => (LayoutFrame::Ecef, [x, y, z]),
+            _ => return Err("Layout file needs e, n, u or x, y, z columns.".to_string()),
+        };
+        let name_col = column("name");
+        let number_col = column("number");
+        let diameter_col = column("diameter");
+
+        let mut layout = AntennaLayout {
+            antenna_names: Vec::new(),
+            antenna_numbers: Vec::new(),
+            antenna_positions: Vec::new(),
+            antenna_diameters: diameter_col.map(|_| Vec::new()),
+        };
+        for (ind, line) in lines.enumerate() {
+            let fields = split(line);
+            if fields.len() != header.len() {
+                return Err(format!("Cannot parse layout line: {}", line));
+            }
+            let parse = |col: usize| -> Result<f64, String> {
+                fields[col]
+                    .parse::<f64>()
+                    .map_err(|_| format!("Cannot parse layout line: {}", line))
+            };
+            let number = match number_col {
+                Some(col) => fields[col]
+                    .parse::<u32>()
+                    .map_err(|_| format!("Cannot parse layout line: {}", line))?,
+                None => ind as u32,
+            };
+            layout.antenna_numbers.push(number);
+            layout.antenna_names.push(match name_col {
+                Some(col) => fields[col].clone(),
+                None => number.to_string(),
+            });
+            layout.antenna_positions.push([
+                parse(pos_cols[0])?,
+                parse(pos_cols[1])?,
+                parse(pos_cols[2])?,
+            ]);
+            if let (Some(col), Some(diams)) = (diameter_col, layout.antenna_diameters.as_mut()) {
+                diams.push(parse(col)?);
+            }
+        }
+        match frame {
+            LayoutFrame::Enu => {
+                let enu = rows_to_array(&layout.antenna_positions);
+                layout.set_enu_positions(&enu, telescope_location);
+            }
+            LayoutFrame::Ecef => {
+                for pos in layout.antenna_positions.iter_mut() {
+                    for (x, center) in pos.iter_mut().zip(telescope_location.iter()) {
+                        *x -= center;
+                    }
+                }
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Write the layout to a CSV file readable by `from_csv`.
+    pub fn to_csv<P: AsRef<Path>>(
+        &self,
+        path: P,
+        telescope_location: [f64; 3],
+        frame: LayoutFrame,
+    ) -> Result<(), String> {
+        self.check()?;
+        let (columns, positions) = match frame {
+            LayoutFrame::Enu => ("e,n,u", self.enu_positions(telescope_location)),
+            LayoutFrame::Ecef => (
+                "x,y,z",
+                rows_to_array(&self.antenna_positions) + Array::from(telescope_location.to_vec()),
+            ),
+        };
+        let mut contents = format!("name,number,{}", columns);
+        if self.antenna_diameters.is_some() {
+            contents.push_str(",diameter");
+        }
+        contents.push('\n');
+        for (ind, pos) in positions.outer_iter().enumerate() {
+            contents.push_str(&format!(
+                "{},{},{:.6},{:.6},{:.6}",
+                self.antenna_names[ind], self.antenna_numbers[ind], pos[0], pos[1], pos[2]
+            ));
+            if let Some(diams) = &self.antenna_diameters {
+                contents.push_str(&format!(",{}", diams[ind]));
+            }
+            contents.push('\n');
+        }
+        fs::write(path, contents).map_err(|err| err.to_string())
+    }
+
+    /// Read an HDF5 layout file, returning the layout and the array center (ECEF).
+    ///
+    /// The array center is read from `arrcent`, and positions from `enh`
+    /// (ENU) or else `rot_ecef` (ECEF rotated to the array longitude).
+    /// Optional `antenna_numbers`, `antenna_names` and `antenna_diameters`
+    /// datasets default to 0..N, the numbers, and none.
+    pub fn from_layout_h5<P: AsRef<Path>>(path: P) -> hdf5::Result<(AntennaLayout, [f64; 3])> {
+        let h5file = hdf5::File::open(path)?;
+        let arrcent: [f64; 3] = h5file
+            .dataset("arrcent")?
+            .read::<f64, Ix1>()?
+            .to_vec()
+            .try_into()
+            .map_err(|_| "arrcent must have three elements.")?;
+        let (_, lon, _) = degrees_latlonalt(arrcent);
+        let positions: Array<f64, Ix2> = match h5file.link_exists("enh") {
+            true => {
+                let (lat, lon, alt) = degrees_latlonalt(arrcent);
+                let enu = h5file.dataset("enh")?.read::<f64, Ix2>()?;
+                utils::ecef_from_enu(&enu, lat, lon, alt) - Array::from(arrcent.to_vec())
+            }
+            false => {
+                utils::ecef_from_rot_ecef(h5file.dataset("rot_ecef")?.read::<f64, Ix2>()?, lon)
+            }
+        };
+        let nants = positions.nrows();
+        let antenna_numbers: Vec<u32> = match h5file.link_exists("antenna_numbers") {
+            true => h5file.dataset("antenna_numbers")?.read_raw::<u32>()?,
+            false => (0..nants as u32).collect(),
+        };
+        let antenna_names: Vec<String> = match h5file.link_exists("antenna_names") {
+            true => h5file
+                .dataset("antenna_names")?
+                .read_raw::<FixedAscii<50>>()?
+                .into_iter()
+                .map(|name| name.to_string())
+                .collect(),
+            false => antenna_numbers.iter().map(|num| num.to_string()).collect(),
+        };
+        let antenna_diameters: Option<Vec<f64>> = match h5file.link_exists("antenna_diameters") {
+            true => Some(h5file.dataset("antenna_diameters")?.read_raw::<f64>()?),
+            false => None,
+        };
+        let layout = AntennaLayout {
+            antenna_names,
+            antenna_numbers,
+            antenna_positions: array_to_rows(&positions),
+            antenna_diameters,
+        };
+        layout.check()?;
+        Ok((layout, arrcent))
+    }
+
+    /// Write an HDF5 layout file with `arrcent`, `rot_ecef` and `enh` datasets
+    /// plus the antenna numbers, names and diameters.
+    pub fn to_layout_h5<P: AsRef<Path>>(
+        &self,
+        path: P,
+        telescope_location: [f64; 3],
+        overwrite: bool,
+    ) -> hdf5::Result<()> {
+        self.check()?;
+        let h5file: hdf5::File = match overwrite {
+            true => hdf5::File::create(path)?,
+            false => hdf5::File::create_excl(path)?,
+        };
+        let (_, lon, _) = degrees_latlonalt(telescope_location);
+        let positions = rows_to_array(&self.antenna_positions);
+        h5file
+            .new_dataset_builder()
+            .with_data(&Array::from(telescope_location.to_vec()))
+            .create("arrcent")?;
+        h5file
+            .new_dataset_builder()
+            .with_data(&utils::rot_ecef_from_ecef(positions, lon))
+            .create("rot_ecef")?;
+        h5file
+            .new_dataset_builder()
+            .with_data(&self.enu_positions(telescope_location))
+            .create("enh")?;
+        h5file
+            .new_dataset_builder()
+            .with_data(&Array::from(self.antenna_numbers.clone()))
+            .create("antenna_numbers")?;
+        let names = self
+            .antenna_names
+            .iter()
+            .map(|name| FixedAscii::<50>::from_ascii(name).map_err(|err| err.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        h5file
+            .new_dataset_builder()
+            .with_data(&Array::from(names))
+            .create("antenna_names")?;
+        if let Some(diams) = &self.antenna_diameters {
+            h5file
+                .new_dataset_builder()
+                .with_data(&Array::from(diams.clone()))
+                .create("antenna_diameters")?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// The telescope's antenna layout.
+    pub fn antenna_layout(&self) -> AntennaLayout {
+        let arrays = &self.meta_arrays;
+        AntennaLayout {
+            antenna_names: arrays.antenna_names.to_vec(),
+            antenna_numbers: arrays.antenna_numbers.to_vec(),
+            antenna_positions: array_to_rows(&arrays.antenna_positions),
+            antenna_diameters: arrays
+                .antenna_diameters
+                .as_ref()
+                .map(|diams| diams.iter().map(|&x| x as f64).collect()),
+        }
+    }
+
+    /// Replace the telescope's antennas by `layout`.
+    ///
+    /// Every antenna in the data must be in the layout. Antenna diameters
+    /// are taken from the layout (and dropped if it has none).
+    pub fn set_antenna_layout(&mut self, layout: &AntennaLayout) -> Result<(), String> {
+        layout.check()?;
+        if let Some(missing) = self
+            .meta_arrays
+            .ant_1_array
+            .iter()
+            .chain(self.meta_arrays.ant_2_array.iter())
+            .find(|ant| !layout.antenna_numbers.contains(ant))
+        {
+            return Err(format!(
+                "Antenna {} is in the data but not in the layout.",
+                missing
+            ));
+        }
+        let arrays = &mut self.meta_arrays;
+        arrays.antenna_names = Array::from(layout.antenna_names.clone());
+        arrays.antenna_numbers = Array::from(layout.antenna_numbers.clone());
+        arrays.antenna_positions = rows_to_array(&layout.antenna_positions);
+        arrays.antenna_diameters = layout
+            .antenna_diameters
+            .as_ref()
+            .map(|diams| diams.iter().map(|&x| x as f32).collect());
+        self.meta.nants_telescope = layout.nants() as u32;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AntennaLayout, LayoutFrame};
+    use crate::test_data::read_metadata;
+    use ndarray::{Array, Ix1, Ix2};
+    use std::{fs::File, io::Write, path::Path};
+    use tempdir::TempDir;
+
+    #[test]
+    fn mwa_layout_file() {
+        let data_file = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/mwa128_layouts.h5");
+        let (layout, arrcent) =
+            AntennaLayout::from_layout_h5(&data_file).expect("Cannot read layout.");
+        assert_eq!(layout.nants(), 128);
+        assert_eq!(layout.antenna_numbers[127], 127);
+        assert!(layout.antenna_diameters.is_none());
+
+        // enh and rot_ecef describe the same positions
+        let h5file = hdf5::File::open(&data_file).unwrap();
+        let enh = h5file.dataset("enh").unwrap().read::<f64, Ix2>().unwrap();
+        assert!(layout.enu_positions(arrcent).abs_diff_eq(&enh, 1e-3));
+        let file_center = h5file
+            .dataset("arrcent")
+            .unwrap()
+            .read::<f64, Ix1>()
+            .unwrap();
+        assert_eq!(arrcent.to_vec(), file_center.to_vec());
+
+        let tmp_dir = TempDir::new("layout").unwrap();
+        let out_path = tmp_dir.path().join("layout.h5");
+        layout
+            .to_layout_h5(&out_path, arrcent, false)
+            .expect("Cannot write layout.");
+        let (new_layout, new_arrcent) =
+            AntennaLayout::from_layout_h5(&out_path).expect("Cannot read layout.");
+        assert_eq!(new_arrcent, arrcent);
+        assert_eq!(new_layout.antenna_names, layout.antenna_names);
+        let old_pos: Vec<f64> = layout.antenna_positions.iter().flatten().cloned().collect();
+        let new_pos: Vec<f64> = new_layout
+            .antenna_positions
+            .iter()
+            .flatten()
+            .cloned()
+            .collect();
+        assert!(Array::from(old_pos).abs_diff_eq(&Array::from(new_pos), 1e-3));
+        assert!(layout.to_layout_h5(&out_path, arrcent, false).is_err());
+    }
+
+    #[test]
+    fn csv_roundtrip() {
+        let uvd = read_metadata();
+        let location = uvd.meta.telescope_location;
+        let mut layout = uvd.antenna_layout();
+        layout.antenna_diameters = Some(vec![14.0; layout.nants()]);
+
+        let tmp_dir = TempDir::new("layout").unwrap();
+        for frame in [LayoutFrame::Enu, LayoutFrame::Ecef] {
+            let path = tmp_dir.path().join(format!("layout_{}.csv", frame));
+            layout
+                .to_csv(&path, location, frame)
+                .expect("Cannot write csv.");
+            let new_layout = AntennaLayout::from_csv(&path, location).expect("Cannot read csv.");
+            assert_eq!(new_layout.antenna_numbers, layout.antenna_numbers);
+            assert_eq!(new_layout.antenna_names, layout.antenna_names);
+            assert_eq!(new_layout.antenna_diameters, layout.antenna_diameters);
+            for (pos1, pos2) in new_layout
+                .antenna_positions
+                .iter()
+                .zip(layout.antenna_positions.iter())
+            {
+                for (x1, x2) in pos1.iter().zip(pos2.iter()) {
+                    assert_abs_diff_eq!(x1, x2, epsilon = 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn csv_enu_layout() {
+        let mut uvd = read_metadata();
+        let location = uvd.meta.telescope_location;
+        let (enu, antnums) = uvd.get_enu_antpos();
+
+        let tmp_dir = TempDir::new("layout").unwrap();
+        let path = tmp_dir.path().join("layout.txt");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "Name Number BeamID E N U").unwrap();
+        for (ind, num) in antnums.iter().enumerate() {
+            writeln!(
+                file,
+                "ant{} {} 0 {} {} {}",
+                num,
+                num,
+                enu[[ind, 0]],
+                enu[[ind, 1]] + 1.0,
+                enu[[ind, 2]]
+            )
+            .unwrap();
+        }
+        drop(file);
+
+        let layout = AntennaLayout::from_csv(&path, location).expect("Cannot read layout.");
+        uvd.set_antenna_layout(&layout).expect("Cannot set layout.");
+        assert_eq!(
+            uvd.meta_arrays.antenna_names[0],
+            format!("ant{}", antnums[0])
+        );
+        assert!(uvd.meta_arrays.antenna_diameters.is_none());
+        let (new_enu, _) = uvd.get_enu_antpos();
+        let shifted = &enu + &Array::from(vec![0.0, 1.0, 0.0]);
+        assert!(new_enu.abs_diff_eq(&shifted, 1e-6));
+
+        let mut short = layout.clone();
+        let keep: usize = 2;
+        short.antenna_names.truncate(keep);
+        short.antenna_numbers.truncate(keep);
+        short.antenna_positions.truncate(keep);
+        assert!(uvd.set_antenna_layout(&short).is_err());
+        assert!(AntennaLayout::from_csv(tmp_dir.path().join("missing.csv"), location).is_err());
+    }
+}
@@ -3,27 +3,70 @@ extern crate approx;
 
 use approx::AbsDiffEq;
 use hdf5::H5Type;
-use ndarray::{Array, Dimension, Ix1, Ix2, Ix3};
+use ndarray::{s, Array, Axis, Dimension, Ix1, Ix2, Ix3};
 use num_complex::Complex;
 use num_traits::{
     cast::{AsPrimitive, FromPrimitive},
     Float,
 };
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
 
+mod averaging;
 mod base;
+mod blt_index;
+mod calfits;
+mod combine;
+mod fits;
+mod flag_extension;
+mod healpix;
+mod layout;
+mod mock;
+mod noise;
+mod polarization;
+mod redcal;
+mod redundancy;
+mod rfi;
+mod simulate;
+mod stefcal;
+mod telescopes;
+mod time;
 mod utils;
+mod uvcal;
+mod uvflag;
 mod uvh5;
+mod waterfall;
 
+pub use self::averaging::TimeAverage;
+pub use self::combine::NsamplePolicy;
+pub use self::healpix::{HealpixMap, HealpixOrdering, SkyUnit};
+pub use self::layout::{AntennaLayout, LayoutFrame};
+pub use self::mock::{MockObservation, SpectralWindow};
+pub use self::noise::{DifferenceAxis, SystemNoise};
+pub use self::redcal::{RedCal, RedSolution, RedSolver};
+pub use self::redundancy::RedundancyMethod;
+pub use self::rfi::{PolCombination, SumThreshold, Xrfi, XrfiSource};
+pub use self::simulate::{BeamModel, PointSource};
+pub use self::stefcal::StefCal;
+pub use self::telescopes::{Telescope, TelescopeRegistry, TelescopeSpec};
+pub use self::time::{
+    convert_time, iso_from_jd, jd_from_iso, jd_from_mjd, jd_from_unix, lst_from_jd, mjd_from_jd,
+    unix_from_jd, LeapSeconds, TimeScale,
+};
+pub use self::uvcal::{CalStyle, CalType, GainConvention, UVCal};
+pub use self::uvflag::{FlagCollapse, UVFlag, UVFlagMode, UVFlagType};
 pub use self::uvh5::UVH5;
 
 pub use self::base::{
-    ArrayMetaData, BltOrder, BltOrders, CatTypes, Catalog, EqConvention, Orientation, PhaseType,
-    SiderealVal, UVMeta, UnphasedVal, VisUnit,
+    ArrayMetaData, BltOrder, BltOrders, CatTypes, Catalog, EqConvention, IntoPolNum, Orientation,
+    PhaseType, Polarization, SiderealVal, UVMeta, UnphasedVal, VisUnit,
 };
 pub use self::utils::{
     antnums_to_baseline, baseline_to_antnums, ecef_from_enu, ecef_from_rot_ecef, enu_from_ecef,
-    latlonalt_from_xyz, rot_ecef_from_ecef, xyz_from_latlonalt,
+    get_antenna_redundancies, get_baseline_redundancies, jnum2str, jstr2num, latlonalt_from_xyz,
+    polnum2str, polstr2num, rot_ecef_from_ecef, uvw_from_enu, xyz_from_latlonalt, RedundantGroups,
 };
 
 fn compare_complex_arrays<T, U>(
@@ -53,6 +96,8 @@ where
     pub data_array: Option<Array<Complex<T>, Ix3>>,
     pub nsample_array: Option<Array<S, Ix3>>,
     pub flag_array: Option<Array<bool, Ix3>>,
+    // built on first lookup, not part of equality
+    blt_index: blt_index::BltIndexCache,
 }
 
 impl<T, S> PartialEq for UVData<T, S>
@@ -122,18 +167,18 @@ where
             false => {
                 let data_array = Some(Array::<Complex<T>, Ix3>::zeros((
                     meta.nblts as usize,
-                    meta.ntimes as usize,
+                    meta.nfreqs as usize,
                     meta.npols as usize,
                 )));
                 let nsample_array = Some(Array::<S, Ix3>::zeros((
                     meta.nblts as usize,
-                    meta.ntimes as usize,
+                    meta.nfreqs as usize,
                     meta.npols as usize,
                 )));
                 let flag_array = Some(Array::<bool, Ix3>::from_elem(
                     (
                         meta.nblts as usize,
-                        meta.ntimes as usize,
+                        meta.nfreqs as usize,
                         meta.npols as usize,
                     ),
                     false,
@@ -147,6 +192,141 @@ where
             data_array,
             nsample_array,
             flag_array,
+            blt_index: Default::default(),
+        }
+    }
+
+    /// Check the metadata counts against the arrays and the arrays against
+    /// each other.
+    pub fn check(&self) -> Result<(), String> {
+        let meta = &self.meta;
+        let arrays = &self.meta_arrays;
+        let nblts = meta.nblts as usize;
+        let nfreqs = meta.nfreqs as usize;
+        let nants = meta.nants_telescope as usize;
+        let lengths = [
+            ("ant_1_array", arrays.ant_1_array.len(), nblts),
+            ("ant_2_array", arrays.ant_2_array.len(), nblts),
+            ("baseline_array", arrays.baseline_array.len(), nblts),
+            ("time_array", arrays.time_array.len(), nblts),
+            ("lst_array", arrays.lst_array.len(), nblts),
+            ("integration_time", arrays.integration_time.len(), nblts),
+            ("uvw_array", arrays.uvw_array.nrows(), nblts),
+            (
+                "phase_center_id_array",
+                arrays.phase_center_id_array.len(),
+                nblts,
+            ),
+            ("freq_array", arrays.freq_array.len(), nfreqs),
+            ("channel_width", arrays.channel_width.len(), nfreqs),
+            ("spw_id_array", arrays.spw_id_array.len(), nfreqs),
+            ("spw_array", arrays.spw_array.len(), meta.nspws as usize),
+            (
+                "polarization_array",
+                arrays.polarization_array.len(),
+                meta.npols as usize,
+            ),
+            ("antenna_numbers", arrays.antenna_numbers.len(), nants),
+            ("antenna_names", arrays.antenna_names.len(), nants),
+            ("antenna_positions", arrays.antenna_positions.nrows(), nants),
+        ];
+        if let Some((name, len, expected)) = lengths.iter().find(|(_, len, exp)| len != exp) {
+            return Err(format!(
+                "{} has length {}, expected {}.",
+                name, len, expected
+            ));
+        }
+        if arrays.uvw_array.ncols() != 3 || arrays.antenna_positions.ncols() != 3 {
+            return Err("uvw_array and antenna_positions must have 3 columns.".to_string());
+        }
+        if let Some(diameters) = &arrays.antenna_diameters {
+            if diameters.len() != nants {
+                return Err(format!(
+                    "antenna_diameters has length {}, expected {}.",
+                    diameters.len(),
+                    nants
+                ));
+            }
+        }
+        let shape = (nblts, nfreqs, meta.npols as usize);
+        let shapes = [
+            ("data_array", self.data_array.as_ref().map(|arr| arr.dim())),
+            (
+                "nsample_array",
+                self.nsample_array.as_ref().map(|arr| arr.dim()),
+            ),
+            ("flag_array", self.flag_array.as_ref().map(|arr| arr.dim())),
+        ];
+        for (name, dim) in shapes.iter() {
+            match dim {
+                Some(dim) if *dim != shape => {
+                    return Err(format!(
+                        "{} has shape {:?}, expected {:?}.",
+                        name, dim, shape
+                    ))
+                }
+                _ => {}
+            }
+        }
+
+        let times = self.unique_times();
+        let baselines = arrays.baseline_array.iter().collect::<BTreeSet<_>>();
+        let data_ants = arrays
+            .ant_1_array
+            .iter()
+            .chain(arrays.ant_2_array.iter())
+            .collect::<BTreeSet<_>>();
+        let counts = [
+            ("Ntimes", times.len(), meta.ntimes as usize),
+            ("Nbls", baselines.len(), meta.nbls as usize),
+            ("Nants_data", data_ants.len(), meta.nants_data as usize),
+        ];
+        if let Some((name, count, expected)) = counts.iter().find(|(_, cnt, exp)| cnt != exp) {
+            return Err(format!(
+                "{} is {} but the data have {}.",
+                name, expected, count
+            ));
+        }
+
+        if arrays.baseline_array
+            != antnums_to_baseline(&arrays.ant_1_array, &arrays.ant_2_array, false)
+        {
+            return Err("baseline_array does not match the antenna arrays.".to_string());
+        }
+        if let Some(ant) = data_ants
+            .iter()
+            .find(|ant| !arrays.antenna_numbers.iter().any(|num| num == **ant))
+        {
+            return Err(format!("Antenna {} is not in antenna_numbers.", ant));
+        }
+        if let Some(id) = arrays
+            .spw_id_array
+            .iter()
+            .find(|id| !arrays.spw_array.iter().any(|spw| spw == *id))
+        {
+            return Err(format!("Spectral window {} is not in spw_array.", id));
+        }
+        if let Some(id) = arrays.phase_center_id_array.iter().find(|&&id| {
+            !arrays
+                .phase_center_catalog
+                .values()
+                .any(|cat| cat.cat_id() == id)
+        }) {
+            return Err(format!("Phase center {} is not in the catalog.", id));
+        }
+        if arrays
+            .lst_array
+            .iter()
+            .any(|lst| !(0.0..2.0 * std::f64::consts::PI).contains(lst))
+        {
+            return Err("lst_array must be in [0, 2pi).".to_string());
+        }
+        let autos_ok = (0..nblts)
+            .filter(|&blt| arrays.ant_1_array[blt] == arrays.ant_2_array[blt])
+            .all(|blt| arrays.uvw_array.row(blt).iter().all(|x| x.abs() < 1e-6));
+        match autos_ok {
+            true => Ok(()),
+            false => Err("Auto-correlations must have zero uvw.".to_string()),
         }
     }
 
@@ -159,6 +339,22 @@ where
         (lla.0.to_degrees(), lla.1.to_degrees(), lla.2)
     }
 
+    /// The (ra, dec) in radians of the phase center of `blt`, or None if it
+    /// is unphased. Ephemeris centers give their first position.
+    pub(crate) fn phase_center_of(&self, blt: usize) -> Option<(f64, f64)> {
+        let cat_id = self.meta_arrays.phase_center_id_array[blt];
+        match self
+            .meta_arrays
+            .phase_center_catalog
+            .values()
+            .find(|cat| cat.cat_id() == cat_id)
+        {
+            Some(CatTypes::Sidereal(val)) => Some((val.cat_lon, val.cat_lat)),
+            Some(CatTypes::Ephem(val)) => Some((val.cat_lon[0], val.cat_lat[0])),
+            _ => None,
+        }
+    }
+
     pub fn get_enu_antpos(&self) -> (Array<f64, Ix2>, Array<u32, Ix1>) {
         let (lat, lon, alt) = self.telescope_location_latlonalt_degrees();
         let tele_loc: Array<f64, Ix1> = Array::from_vec(self.meta.telescope_location.to_vec());
@@ -168,6 +364,271 @@ where
             self.meta_arrays.antenna_numbers.clone(),
         )
     }
+
+    /// Find groups of redundant baselines among the baselines in the data.
+    ///
+    /// Baseline vectors come from the ENU antenna positions when `use_antpos`
+    /// is set, and otherwise from the first uvw of each baseline.
+    pub fn get_redundancies(
+        &self,
+        tol: f64,
+        use_antpos: bool,
+        include_conjugates: bool,
+        include_autos: bool,
+    ) -> Result<RedundantGroups, String> {
+        let arrays = &self.meta_arrays;
+        let mut firsts: BTreeMap<u32, usize> = BTreeMap::new();
+        for (blt, &baseline) in arrays.baseline_array.iter().enumerate() {
+            if include_autos || arrays.ant_1_array[blt] != arrays.ant_2_array[blt] {
+                firsts.entry(baseline).or_insert(blt);
+            }
+        }
+        let baselines: Array<u32, Ix1> = firsts.keys().cloned().collect();
+        let mut vecs = Array::<f64, Ix2>::zeros((baselines.len(), 3));
+        match use_antpos {
+            true => {
+                let (enu, antnums) = self.get_enu_antpos();
+                let ant_index = |ant: u32| -> Result<usize, String> {
+                    antnums
+                        .iter()
+                        .position(|&num| num == ant)
+                        .ok_or(format!("Antenna {} has no antenna position.", ant))
+                };
+                for (mut vec, &blt) in vecs.outer_iter_mut().zip(firsts.values()) {
+                    let ind1 = ant_index(arrays.ant_1_array[blt])?;
+                    let ind2 = ant_index(arrays.ant_2_array[blt])?;
+                    vec.assign(&(&enu.row(ind2) - &enu.row(ind1)));
+                }
+            }
+            false => {
+                for (mut vec, &blt) in vecs.outer_iter_mut().zip(firsts.values()) {
+                    vec.assign(&arrays.uvw_array.row(blt));
+                }
+            }
+        }
+        Ok(utils::get_baseline_redundancies(
+            &baselines,
+            &vecs,
+            tol,
+            include_conjugates,
+        ))
+    }
+
+    /// The sorted distinct times, treating times within `TIME_TOL` as one.
+    pub fn unique_times(&self) -> Vec<f64> {
+        let mut times: Vec<f64> = self.meta_arrays.time_array.to_vec();
+        times.sort_by(|t1, t2| t1.partial_cmp(t2).unwrap());
+        times.dedup_by(|t1, t2| (*t1 - *t2).abs() < utils::TIME_TOL);
+        times
+    }
+
+    /// Keep only the given blts. Indices are deduplicated and kept in their original order.
+    pub fn select_blts(&mut self, blt_inds: &[usize]) -> Result<(), String> {
+        if let Some(&bad) = blt_inds
+            .iter()
+            .find(|&&blt| blt >= self.meta.nblts as usize)
+        {
+            return Err(format!(
+                "Blt index {} is out of range for Nblts {}.",
+                bad, self.meta.nblts
+            ));
+        }
+        let mut inds: Vec<usize> = blt_inds.to_vec();
+        inds.sort_unstable();
+        inds.dedup();
+        match inds.is_empty() {
+            true => Err("No blts selected.".to_string()),
+            false => {
+                self.take_blts(&inds);
+                Ok(())
+            }
+        }
+    }
+
+    /// Keep only the blts of the given antenna pairs, in either ordering.
+    pub fn select_antpairs(&mut self, antpairs: &[(u32, u32)]) -> Result<(), String> {
+        let inds: Vec<usize> = self
+            .meta_arrays
+            .ant_1_array
+            .iter()
+            .zip(self.meta_arrays.ant_2_array.iter())
+            .enumerate()
+            .filter(|(_, (&ant1, &ant2))| {
+                antpairs.contains(&(ant1, ant2)) || antpairs.contains(&(ant2, ant1))
+            })
+            .map(|(blt, _)| blt)
+            .collect();
+        match inds.is_empty() {
+            true => Err(format!("No data matches the antenna pairs {:?}.", antpairs)),
+            false => {
+                self.take_blts(&inds);
+                Ok(())
+            }
+        }
+    }
+
+    /// Keep only the blts with times (JD) between `start` and `end` inclusive.
+    ///
+    /// Selection is done per blt, so baselines with different integration
+    /// times (e.g. bda data) each keep their own samples in the range.
+    pub fn select_time_range(&mut self, start: f64, end: f64) -> Result<(), String> {
+        let inds: Vec<usize> = self
+            .meta_arrays
+            .time_array
+            .iter()
+            .enumerate()
+            .filter(|(_, &time)| time >= start && time <= end)
+            .map(|(blt, _)| blt)
+            .collect();
+        match inds.is_empty() {
+            true => Err(format!("No data between times {} and {}.", start, end)),
+            false => {
+                self.take_blts(&inds);
+                Ok(())
+            }
+        }
+    }
+
+    /// Keep only the given polarizations, in the order given.
+    ///
+    /// Polarizations can be AIPS numbers or strings like "xx", "ee" or "pI";
+    /// E/N names are resolved with `meta.x_orientation`.
+    pub fn select_polarizations<P: IntoPolNum>(&mut self, pols: &[P]) -> Result<(), String> {
+        let mut inds: Vec<usize> = Vec::with_capacity(pols.len());
+        for pol in pols {
+            let num = pol.pol_num(self.meta.x_orientation)?;
+            let ind = self
+                .meta_arrays
+                .polarization_array
+                .iter()
+                .position(|&x| x == num)
+                .ok_or(format!("Polarization {} is not in the data.", num))?;
+            if !inds.contains(&ind) {
+                inds.push(ind);
+            }
+        }
+        if inds.is_empty() {
+            return Err("No polarizations selected.".to_string());
+        }
+        self.meta_arrays.polarization_array =
+            self.meta_arrays.polarization_array.select(Axis(0), &inds);
+        self.meta.npols = inds.len() as u8;
+        if let Some(data) = &self.data_array {
+            self.data_array = Some(data.select(Axis(2), &inds));
+        }
+        if let Some(nsamples) = &self.nsample_array {
+            self.nsample_array = Some(nsamples.select(Axis(2), &inds));
+        }
+        if let Some(flags) = &self.flag_array {
+            self.flag_array = Some(flags.select(Axis(2), &inds));
+        }
+        Ok(())
+    }
+
+    /// Conjugate the given baselines: swap their antennas, negate their uvws
+    /// and conjugate their visibilities, swapping cross polarizations.
+    pub fn conjugate_bls(&mut self, baselines: &[u32]) -> Result<(), String> {
+        let pols = &self.meta_arrays.polarization_array;
+        // index of the polarization each pol turns into under conjugation
+        let mut pol_swap: Vec<usize> = Vec::with_capacity(pols.len());
+        for &pol in pols.iter() {
+            let partner = match pol {
+                -3 => -4,
+                -4 => -3,
+                -7 => -8,
+                -8 => -7,
+                other => other,
+            };
+            match pols.iter().position(|&x| x == partner) {
+                Some(ind) => pol_swap.push(ind),
+                None => {
+                    return Err(format!(
+                        "Cannot conjugate polarization {} without {}.",
+                        pol, partner
+                    ))
+                }
+            }
+        }
+
+        let blts: Vec<usize> = self
+            .meta_arrays
+            .baseline_array
+            .iter()
+            .enumerate()
+            .filter(|(_, bl)| baselines.contains(bl))
+            .map(|(blt, _)| blt)
+            .collect();
+        let arrays = &mut self.meta_arrays;
+        for &blt in blts.iter() {
+            std::mem::swap(&mut arrays.ant_1_array[blt], &mut arrays.ant_2_array[blt]);
+            arrays.uvw_array.row_mut(blt).mapv_inplace(|x| -x);
+            if let Some(data) = self.data_array.as_mut() {
+                let old = data.index_axis(Axis(0), blt).to_owned();
+                for (pol, &swap) in pol_swap.iter().enumerate() {
+                    data.slice_mut(s![blt, .., pol])
+                        .assign(&old.column(swap).mapv(|x| x.conj()));
+                }
+            }
+            if let Some(nsamples) = self.nsample_array.as_mut() {
+                let old = nsamples.index_axis(Axis(0), blt).to_owned();
+                for (pol, &swap) in pol_swap.iter().enumerate() {
+                    nsamples
+                        .slice_mut(s![blt, .., pol])
+                        .assign(&old.column(swap));
+                }
+            }
+            if let Some(flags) = self.flag_array.as_mut() {
+                let old = flags.index_axis(Axis(0), blt).to_owned();
+                for (pol, &swap) in pol_swap.iter().enumerate() {
+                    flags.slice_mut(s![blt, .., pol]).assign(&old.column(swap));
+                }
+            }
+        }
+        arrays.baseline_array =
+            utils::antnums_to_baseline(&arrays.ant_1_array, &arrays.ant_2_array, false);
+        self.invalidate_blt_index();
+        Ok(())
+    }
+
+    /// Keep only the given blts (in the given order) of every blt-shaped array.
+    pub(crate) fn take_blts(&mut self, inds: &[usize]) {
+        self.invalidate_blt_index();
+        let arrays = &mut self.meta_arrays;
+        arrays.uvw_array = arrays.uvw_array.select(Axis(0), inds);
+        arrays.time_array = arrays.time_array.select(Axis(0), inds);
+        arrays.lst_array = arrays.lst_array.select(Axis(0), inds);
+        arrays.ant_1_array = arrays.ant_1_array.select(Axis(0), inds);
+        arrays.ant_2_array = arrays.ant_2_array.select(Axis(0), inds);
+        arrays.baseline_array = arrays.baseline_array.select(Axis(0), inds);
+        arrays.integration_time = arrays.integration_time.select(Axis(0), inds);
+        arrays.phase_center_id_array = arrays.phase_center_id_array.select(Axis(0), inds);
+
+        self.data_array = self.data_array.as_ref().map(|x| x.select(Axis(0), inds));
+        self.nsample_array = self.nsample_array.as_ref().map(|x| x.select(Axis(0), inds));
+        self.flag_array = self.flag_array.as_ref().map(|x| x.select(Axis(0), inds));
+
+        self.update_blt_counts();
+    }
+
+    /// Recompute Nblts, Ntimes, Nbls and Nants_data from the blt arrays.
+    pub(crate) fn update_blt_counts(&mut self) {
+        self.invalidate_blt_index();
+        self.meta.nblts = self.meta_arrays.time_array.len() as u32;
+        self.meta.ntimes = self.unique_times().len() as u32;
+        self.meta.nbls = self
+            .meta_arrays
+            .baseline_array
+            .iter()
+            .collect::<BTreeSet<_>>()
+            .len() as u32;
+        self.meta.nants_data = self
+            .meta_arrays
+            .ant_1_array
+            .iter()
+            .chain(self.meta_arrays.ant_2_array.iter())
+            .collect::<BTreeSet<_>>()
+            .len() as u32;
+    }
 }
 
 impl From<UVMeta> for UVData<f64, f32> {
@@ -198,6 +659,7 @@ where
             data_array: uvh5.data_array,
             nsample_array: uvh5.nsample_array,
             flag_array: uvh5.flag_array,
+            blt_index: Default::default(),
         }
     }
 }
@@ -235,10 +697,43 @@ where
     }
 }
 
+#[cfg(test)]
+pub(crate) mod test_data {
+    use super::UVData;
+    use std::path::Path;
+
+    /// Read a file from tests/data with its data arrays.
+    pub(crate) fn read_test_file(name: &str) -> UVData<f64, f32> {
+        let data_file = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/data")
+            .join(name);
+        UVData::<f64, f32>::read_uvh5(data_file, true).expect("Cannot read.")
+    }
+
+    /// The drift scan test file with its data arrays.
+    pub(crate) fn read_drift() -> UVData<f64, f32> {
+        read_test_file("test_drift.uvh5")
+    }
+
+    /// The drift scan test file with its data and nothing flagged.
+    pub(crate) fn read_unflagged() -> UVData<f64, f32> {
+        let mut uvd = read_drift();
+        uvd.flag_array.as_mut().unwrap().fill(false);
+        uvd
+    }
+
+    /// The metadata of the drift scan test file.
+    pub(crate) fn read_metadata() -> UVData<f64, f32> {
+        let data_file = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/test_drift.uvh5");
+        UVData::<f64, f32>::read_uvh5(data_file, false).expect("Cannot read.")
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{compare_complex_arrays, UVData};
-    use ndarray::{array, Array, Ix1, Ix2};
+    use super::{antnums_to_baseline, compare_complex_arrays, Orientation, UVData};
+    use crate::test_data::{read_drift, read_metadata};
+    use ndarray::{array, s, Array, Ix1, Ix2};
     use num_complex::Complex;
     use std::path::Path;
 
@@ -285,6 +780,83 @@ mod test {
         assert!(!compare_complex_arrays(&array1, &array2))
     }
 
+    #[test]
+    fn select_antpairs_either_order() {
+        let mut uvd = read_drift();
+        uvd.select_antpairs(&[(2, 0), (1, 1)])
+            .expect("Cannot select antpairs.");
+        assert_eq!(uvd.meta.nbls, 2);
+        assert_eq!(uvd.meta.nblts, 40);
+        assert_eq!(uvd.meta.ntimes, 20);
+        assert_eq!(uvd.meta.nants_data, 3);
+        assert!(uvd.clone().select_antpairs(&[(3, 4)]).is_err());
+        assert_eq!(uvd.data_array.unwrap().shape(), &[40, 4, 2]);
+    }
+
+    #[test]
+    fn select_time_range() {
+        let mut uvd = read_drift();
+        let times = uvd.unique_times();
+        uvd.select_time_range(times[2], times[5])
+            .expect("Cannot select times.");
+        assert_eq!(uvd.meta.ntimes, 4);
+        assert_eq!(uvd.meta.nblts, 40);
+        assert!(uvd.select_blts(&[40]).is_err());
+    }
+
+    #[test]
+    fn jittered_times() {
+        let mut uvd = read_metadata();
+        uvd.meta_arrays.time_array[3] += 1e-8;
+        assert_eq!(uvd.unique_times().len(), 20);
+        uvd.update_blt_counts();
+        assert_eq!(uvd.meta.ntimes, 20);
+        uvd.check().expect("Jittered times do not check.");
+    }
+
+    #[test]
+    fn select_polarization_strings() {
+        let mut uvd = read_drift();
+        assert_eq!(uvd.meta.x_orientation, Orientation::North);
+        let yy = uvd
+            .data_array
+            .as_ref()
+            .unwrap()
+            .slice(s![.., .., 1])
+            .to_owned();
+        uvd.select_polarizations(&["ee"])
+            .expect("Cannot select polarizations.");
+        assert_eq!(uvd.meta.npols, 1);
+        assert_eq!(uvd.meta_arrays.polarization_array.to_vec(), vec![-6]);
+        assert!(uvd.clone().select_polarizations(&[-5i8]).is_err());
+        assert_eq!(uvd.data_array.unwrap().slice(s![.., .., 0]), yy);
+    }
+
+    #[test]
+    fn redundancies_from_antpos() {
+        let uvd = read_metadata();
+        let groups = uvd
+            .get_redundancies(1.0, true, true, false)
+            .expect("Cannot find redundancies.");
+        let uvw_groups = uvd
+            .get_redundancies(1.0, false, true, false)
+            .expect("Cannot find redundancies.");
+        assert_eq!(groups.baseline_groups, uvw_groups.baseline_groups);
+        // antennas 0, 1 and 2 sit in an east-west row, but the data
+        // holds (2, 1) so it needs conjugating to match (0, 1).
+        let bls = antnums_to_baseline(&array![0u32, 2], &array![1u32, 1], false);
+        assert!(groups.baseline_groups.contains(&bls.to_vec()));
+        assert!(groups.conjugates.contains(&bls[1]));
+        assert!(!groups.conjugates.contains(&bls[0]));
+        assert_eq!(groups.baseline_groups.len(), 5);
+
+        let no_conj = uvd
+            .get_redundancies(1.0, true, false, true)
+            .expect("Cannot find redundancies.");
+        assert_eq!(no_conj.baseline_groups.len(), 7);
+        assert_eq!(no_conj.baseline_groups[0].len(), 4);
+    }
+
     #[test]
     fn enu_antpos() {
         let ref_antpos: Array<f64, Ix2> = array![
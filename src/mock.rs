@@ -0,0 +1,425 @@
+use approx::AbsDiffEq;
+use ndarray::Array;
+use num_traits::Float;
+use std::collections::BTreeSet;
+
+use super::base::{
+    BltOrder, BltOrders, CatTypes, Catalog, Orientation, PhaseType, Polarization, SiderealVal,
+    UVMeta, UnphasedVal,
+};
+use super::layout::AntennaLayout;
+use super::utils;
+use super::UVData;
+
+/// A spectral window of evenly spaced channels.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpectralWindow {
+    pub id: u32,
+    /// Center frequency of the first channel in Hz.
+    pub start_freq: f64,
+    /// Channel width in Hz.
+    pub channel_width: f64,
+    pub nchans: u32,
+}
+
+/// Time, frequency, polarization and phase center setup of a mock
+/// observation.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MockObservation {
+    /// UTC Julian Date of the start of the first integration.
+    pub start_time: f64,
+    /// Length of the observation in seconds.
+    pub duration: f64,
+    /// Integration time in seconds.
+    pub integration_time: f64,
+    pub spws: Vec<SpectralWindow>,
+    pub polarizations: Vec<Polarization>,
+    /// ICRS (RA, Dec) in radians of a sidereal phase center, or `None` for
+    /// unprojected drift scan data.
+    pub phase_center: Option<(f64, f64)>,
+    pub include_autos: bool,
+    pub telescope_name: String,
+    pub x_orientation: Orientation,
+}
+
+impl Default for MockObservation {
+    fn default() -> Self {
+        MockObservation {
+            start_time: 2_459_000.5,
+            duration: 600.0,
+            integration_time: 10.0,
+            spws: vec![SpectralWindow {
+                id: 0,
+                start_freq: 100e6,
+                channel_width: 100e3,
+                nchans: 64,
+            }],
+            polarizations: vec![Polarization::XX, Polarization::YY],
+            phase_center: None,
+            include_autos: true,
+            telescope_name: "Unknown".to_string(),
+            x_orientation: Orientation::Unknown,
+        }
+    }
+}
+
+impl MockObservation {
+    pub fn ntimes(&self) -> usize {
+        ((self.duration / self.integration_time).round() as usize).max(1)
+    }
+
+    /// Check the setup describes at least one time, channel and
+    /// polarization.
+    pub fn check(&self) -> Result<(), String> {
+        if !(self.integration_time > 0.0 && self.duration >= 0.0) {
+            return Err("The integration time must be positive.".to_string());
+        }
+        if self.spws.is_empty() || self.spws.iter().any(|spw| spw.nchans == 0) {
+            return Err("Every spectral window needs channels.".to_string());
+        }
+        let ids = self.spws.iter().map(|spw| spw.id).collect::<BTreeSet<_>>();
+        if ids.len() != self.spws.len() {
+            return Err("Spectral window ids must be unique.".to_string());
+        }
+        let pols = self.polarizations.iter().collect::<BTreeSet<_>>();
+        match !pols.is_empty() && pols.len() == self.polarizations.len() {
+            true => Ok(()),
+            false => Err("Polarizations must be unique and not empty.".to_string()),
+        }
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Build the observation `obs` of every baseline of `layout` at the ECEF
+    /// `telescope_location`.
+    ///
+    /// Blts are time major and baseline minor, with times at the middle of
+    /// each integration and lsts computed from them. Uvws are ENU baselines
+    /// (ant 2 - ant 1) for drift scans, projected to the phase center
+    /// otherwise. Unless `metadata_only`, visibilities are zero and
+    /// unflagged with unit nsamples.
+    pub fn from_mock_observation(
+        layout: &AntennaLayout,
+        telescope_location: [f64; 3],
+        obs: &MockObservation,
+        metadata_only: bool,
+    ) -> Result<UVData<T, S>, String> {
+        layout.check()?;
+        obs.check()?;
+        let mut antennas = layout.antenna_numbers.clone();
+        antennas.sort_unstable();
+        let antpairs: Vec<(u32, u32)> = antennas
+            .iter()
+            .enumerate()
+            .flat_map(|(ind, &ant1)| {
+                antennas[ind..]
+                    .iter()
+                    .filter(move |&&ant2| obs.include_autos || ant2 != ant1)
+                    .map(move |&ant2| (ant1, ant2))
+            })
+            .collect();
+        if antpairs.is_empty() {
+            return Err("The layout has no baselines.".to_string());
+        }
+        let ntimes = obs.ntimes();
+        let nbls = antpairs.len();
+        let nblts = ntimes * nbls;
+        let nfreqs = obs.spws.iter().map(|spw| spw.nchans).sum::<u32>();
+        let data_ants = antpairs
+            .iter()
+            .flat_map(|&(ant1, ant2)| vec![ant1, ant2])
+            .collect::<BTreeSet<_>>();
+
+        let (phase_type, object_name) = match obs.phase_center {
+            Some(_) => (PhaseType::Phased, "phase_center"),
+            None => (PhaseType::Drift, "zenith"),
+        };
+        let meta = UVMeta {
+            nbls: nbls as u32,
+            nblts: nblts as u32,
+            nspws: obs.spws.len() as u32,
+            npols: obs.polarizations.len() as u8,
+            ntimes: ntimes as u32,
+            nfreqs,
+            nphases: 1,
+            nants_data: data_ants.len() as u32,
+            blt_order: BltOrder {
+                major: BltOrders::Time,
+                minor: BltOrders::Baseline,
+            },
+            nants_telescope: layout.nants() as u32,
+            phase_type,
+            x_orientation: obs.x_orientation,
+            instrument: obs.telescope_name.clone(),
+            telescope_name: obs.telescope_name.clone(),
+            telescope_location,
+            object_name: object_name.to_string(),
+            history: "Mock observation generated by uvdata.".to_string(),
+            ..UVMeta::new()
+        };
+        let mut uvd = UVData::<T, S>::new(meta, metadata_only);
+        if let Some(nsamples) = uvd.nsample_array.as_mut() {
+            nsamples.fill(S::one());
+        }
+
+        let arrays = &mut uvd.meta_arrays;
+        let mut catalog = Catalog::new();
+        let cat = match obs.phase_center {
+            Some((ra, dec)) => CatTypes::Sidereal(SiderealVal {
+                cat_id: 0,
+                cat_type: "sidereal".to_string(),
+                cat_lon: ra,
+                cat_lat: dec,
+                cat_frame: "icrs".to_string(),
+                cat_epoch: 2000.0,
+                cat_pm_ra: None,
+                cat_pm_dec: None,
+                cat_dist: None,
+                cat_vrad: None,
+                info_source: Some("user".to_string()),
+            }),
+            None => CatTypes::Unphased(UnphasedVal {
+                cat_id: 0,
+                cat_type: "unphased".to_string(),
+            }),
+        };
+        catalog.insert(object_name.to_string(), cat);
+        arrays.phase_center_catalog = catalog;
+
+        for (blt, &(ant1, ant2)) in antpairs.iter().cycle().take(nblts).enumerate() {
+            let time = (blt / nbls) as f64 + 0.5;
+            arrays.time_array[blt] = obs.start_time + time * obs.integration_time / 86_400.0;
+            arrays.ant_1_array[blt] = ant1;
+            arrays.ant_2_array[blt] = ant2;
+        }
+        arrays.baseline_array =
+            utils::antnums_to_baseline(&arrays.ant_1_array, &arrays.ant_2_array, false);
+        arrays.integration_time.fill(obs.integration_time);
+
+        let mut chan = 0;
+        for (ind, spw) in obs.spws.iter().enumerate() {
+            arrays.spw_array[ind] = spw.id;
+            for num in 0..spw.nchans as usize {
+                arrays.freq_array[chan] = spw.start_freq + num as f64 * spw.channel_width;
+                arrays.channel_width[chan] = spw.channel_width;
+                arrays.spw_id_array[chan] = spw.id;
+                chan += 1;
+            }
+        }
+        arrays.polarization_array = obs.polarizations.iter().map(|pol| pol.num()).collect();
+
+        uvd.set_antenna_layout(layout)?;
+        uvd.set_lsts_from_time_array();
+
+        let (enu, numbers) = uvd.get_enu_antpos();
+        let position = |ant: u32| {
+            let ind = numbers.iter().position(|&num| num == ant).unwrap();
+            enu.row(ind)
+        };
+        for blt in 0..nblts {
+            let pos1 = position(uvd.meta_arrays.ant_1_array[blt]);
+            let pos2 = position(uvd.meta_arrays.ant_2_array[blt]);
+            let baseline = [pos2[0] - pos1[0], pos2[1] - pos1[1], pos2[2] - pos1[2]];
+            let uvw = uvd.project_enu(blt, baseline);
+            uvd.meta_arrays
+                .uvw_array
+                .row_mut(blt)
+                .assign(&Array::from(uvw.to_vec()));
+        }
+
+        uvd.check()?;
+        Ok(uvd)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MockObservation, SpectralWindow};
+    use crate::test_data::{read_metadata, read_test_file};
+    use crate::{base::PhaseType, utils::uvw_from_enu, CatTypes, Polarization, UVData};
+    use ndarray::array;
+
+    /// The setup of the drift scan test file.
+    fn drift_setup(uvd: &UVData<f64, f32>) -> MockObservation {
+        let arrays = &uvd.meta_arrays;
+        let int_time = arrays.integration_time[0];
+        MockObservation {
+            start_time: arrays.time_array[0] - int_time / 2.0 / 86_400.0,
+            duration: int_time * uvd.meta.ntimes as f64,
+            integration_time: int_time,
+            spws: vec![SpectralWindow {
+                id: arrays.spw_array[0],
+                start_freq: arrays.freq_array[0],
+                channel_width: arrays.channel_width[0],
+                nchans: uvd.meta.nfreqs,
+            }],
+            polarizations: vec![Polarization::XX, Polarization::YY],
+            telescope_name: uvd.meta.telescope_name.clone(),
+            ..MockObservation::default()
+        }
+    }
+
+    #[test]
+    fn check_files() {
+        let uvd = read_metadata();
+        uvd.check().expect("Drift data do not check.");
+        for name in ["test_phased.uvh5", "test_multiphase.uvh5"].iter() {
+            read_test_file(name)
+                .check()
+                .expect("Phased data do not check.");
+        }
+
+        let mut bad = uvd.clone();
+        bad.meta.ntimes += 1;
+        assert!(bad.check().is_err());
+        let mut bad = uvd.clone();
+        bad.meta_arrays.baseline_array[0] += 1;
+        assert!(bad.check().is_err());
+        let mut bad = uvd;
+        bad.meta_arrays.spw_id_array[0] = 7;
+        assert!(bad.check().is_err());
+    }
+
+    #[test]
+    fn matches_drift_file() {
+        let uvd = read_metadata();
+        let obs = drift_setup(&uvd);
+        let mock = UVData::<f64, f32>::from_mock_observation(
+            &uvd.antenna_layout(),
+            uvd.meta.telescope_location,
+            &obs,
+            false,
+        )
+        .expect("Cannot build.");
+        let nants = uvd.meta.nants_telescope as usize;
+        assert_eq!(mock.meta.ntimes, uvd.meta.ntimes);
+        assert_eq!(mock.meta.nbls as usize, nants * (nants + 1) / 2);
+        assert_eq!(mock.meta.nblts, mock.meta.nbls * mock.meta.ntimes);
+        assert_eq!(mock.meta.phase_type, PhaseType::Drift);
+        assert!(mock
+            .meta_arrays
+            .freq_array
+            .abs_diff_eq(&uvd.meta_arrays.freq_array, 1e-3));
+        assert!(mock
+            .nsample_array
+            .as_ref()
+            .unwrap()
+            .iter()
+            .all(|&x| x == 1.0));
+
+        // every blt of the file is in the mock with the same time and uvw,
+        // conjugated where the file has the antennas the other way round
+        let arrays = &uvd.meta_arrays;
+        let mock_arrays = &mock.meta_arrays;
+        for blt in 0..uvd.meta.nblts as usize {
+            let (ant1, ant2) = (arrays.ant_1_array[blt], arrays.ant_2_array[blt]);
+            let found = (0..mock.meta.nblts as usize)
+                .find(|&ind| {
+                    mock_arrays.ant_1_array[ind] == ant1.min(ant2)
+                        && mock_arrays.ant_2_array[ind] == ant1.max(ant2)
+                        && (mock_arrays.time_array[ind] - arrays.time_array[blt]).abs() < 1e-6
+                })
+                .expect("Blt missing from the mock.");
+            assert_abs_diff_eq!(
+                mock_arrays.lst_array[found],
+                arrays.lst_array[blt],
+                epsilon = 1e-4
+            );
+            let sign = if ant1 > ant2 { -1.0 } else { 1.0 };
+            for ind in 0..3 {
+                assert_abs_diff_eq!(
+                    sign * mock_arrays.uvw_array[[found, ind]],
+                    arrays.uvw_array[[blt, ind]],
+                    epsilon = 1e-3
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn phased_setup() {
+        let uvd = read_metadata();
+        let lat = uvd.telescope_location_latlonalt().0;
+        let obs = MockObservation {
+            spws: vec![
+                SpectralWindow {
+                    id: 1,
+                    start_freq: 120e6,
+                    channel_width: 1e6,
+                    nchans: 3,
+                },
+                SpectralWindow {
+                    id: 4,
+                    start_freq: 160e6,
+                    channel_width: -0.5e6,
+                    nchans: 2,
+                },
+            ],
+            polarizations: vec![Polarization::XX, Polarization::XY],
+            duration: 35.0,
+            phase_center: Some((1.0, lat)),
+            include_autos: false,
+            ..MockObservation::default()
+        };
+        let mock = UVData::<f64, f32>::from_mock_observation(
+            &uvd.antenna_layout(),
+            uvd.meta.telescope_location,
+            &obs,
+            true,
+        )
+        .expect("Cannot build.");
+        assert!(mock.data_array.is_none());
+        assert_eq!(mock.meta.ntimes, 4);
+        assert_eq!(mock.meta.nbls, 36);
+        assert_eq!(mock.meta.nspws, 2);
+        assert_eq!(mock.meta_arrays.spw_id_array.to_vec(), vec![1, 1, 1, 4, 4]);
+        assert_abs_diff_eq!(mock.meta_arrays.freq_array[4], 159.5e6);
+        assert!(matches!(
+            mock.meta_arrays.phase_center_catalog.get("phase_center"),
+            Some(CatTypes::Sidereal(_))
+        ));
+        // w is the baseline along the phase center, and phasing keeps the
+        // baseline lengths
+        let (enu, numbers) = mock.get_enu_antpos();
+        let arrays = &mock.meta_arrays;
+        let (ra, dec) = (1.0, lat);
+        for blt in 0..arrays.uvw_array.nrows() {
+            let pos = |ant| enu.row(numbers.iter().position(|&num| num == ant).unwrap());
+            let diff = &pos(arrays.ant_2_array[blt]) - &pos(arrays.ant_1_array[blt]);
+            let uvw = arrays.uvw_array.row(blt);
+            let hour_angle = arrays.lst_array[blt] - ra;
+            let direction = array![
+                -dec.cos() * hour_angle.sin(),
+                lat.cos() * dec.sin() - lat.sin() * dec.cos() * hour_angle.cos(),
+                lat.sin() * dec.sin() + lat.cos() * dec.cos() * hour_angle.cos(),
+            ];
+            assert_abs_diff_eq!(uvw[2], diff.dot(&direction), epsilon = 1e-9);
+            assert_abs_diff_eq!(uvw.dot(&uvw).sqrt(), diff.dot(&diff).sqrt(), epsilon = 1e-9);
+        }
+        let blt = 17;
+        let diff = [0, 1, 2].map(|axis| {
+            let pos = |ant| enu[[numbers.iter().position(|&num| num == ant).unwrap(), axis]];
+            pos(arrays.ant_2_array[blt]) - pos(arrays.ant_1_array[blt])
+        });
+        let expected = uvw_from_enu(diff, arrays.lst_array[blt] - ra, dec, lat);
+        assert_ne!(arrays.ant_1_array[blt], arrays.ant_2_array[blt]);
+        assert_abs_diff_eq!(arrays.uvw_array[[blt, 0]], expected[0], epsilon = 1e-9);
+        assert_abs_diff_eq!(arrays.uvw_array[[blt, 1]], expected[1], epsilon = 1e-9);
+
+        let empty = MockObservation {
+            polarizations: vec![],
+            ..MockObservation::default()
+        };
+        assert!(UVData::<f64, f32>::from_mock_observation(
+            &uvd.antenna_layout(),
+            uvd.meta.telescope_location,
+            &empty,
+            true
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,482 @@
+use approx::AbsDiffEq;
+use ndarray::{s, Array, Ix2, Ix3};
+use num_complex::Complex;
+use num_traits::Float;
+use std::{collections::HashMap, f64::consts::PI, str::FromStr};
+
+use super::base::Polarization;
+use super::utils;
+use super::UVData;
+
+/// Boltzmann's constant in J/K.
+pub const BOLTZMANN: f64 = 1.380_649e-23;
+/// One Jansky in W / m^2 / Hz.
+pub const JANSKY: f64 = 1e-26;
+
+/// System noise of the antennas.
+///
+/// Arrays have shape (1 or Nants_telescope, 1 or Nfreqs): a single row
+/// applies to every antenna (otherwise rows follow `antenna_numbers`) and a
+/// single column to every frequency.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SystemNoise {
+    /// System equivalent flux density in Jy.
+    Sefd(Array<f64, Ix2>),
+    /// System temperature in K, converted to an SEFD with the antenna
+    /// diameters and the given aperture efficiency.
+    Tsys {
+        tsys: Array<f64, Ix2>,
+        aperture_efficiency: f64,
+    },
+}
+
+impl SystemNoise {
+    /// A single SEFD (Jy) for every antenna and frequency.
+    pub fn scalar_sefd(sefd: f64) -> SystemNoise {
+        SystemNoise::Sefd(Array::from_elem((1, 1), sefd))
+    }
+}
+
+/// Which neighbouring samples are differenced to estimate the noise.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DifferenceAxis {
+    Time,
+    Frequency,
+}
+
+impl FromStr for DifferenceAxis {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<DifferenceAxis, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "time" => Ok(DifferenceAxis::Time),
+            "freq" | "frequency" => Ok(DifferenceAxis::Frequency),
+            other => Err(format!("Unknown difference axis: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for DifferenceAxis {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+// the noise on the real (or imaginary) part from differences of neighbouring
+// samples, each of which has twice the variance in both parts
+fn difference_sigma<T: Float>(diffs: impl Iterator<Item = Complex<T>>) -> f64 {
+    let (count, total) = diffs.fold((0usize, 0.0), |(count, total), diff| {
+        (count + 1, total + diff.norm_sqr().to_f64().unwrap())
+    });
+    match count {
+        0 => f64::NAN,
+        _ => (total / count as f64 / 4.0).sqrt(),
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Per-antenna, per-frequency SEFDs in Jy with shape (Nants_telescope, Nfreqs).
+    fn sefd_array(&self, system_noise: &SystemNoise) -> Result<Array<f64, Ix2>, String> {
+        let nants = self.meta.nants_telescope as usize;
+        let nfreqs = self.meta.nfreqs as usize;
+        let (values, scale) = match system_noise {
+            SystemNoise::Sefd(sefd) => (sefd, Array::from_elem(nants, 1.0)),
+            SystemNoise::Tsys {
+                tsys,
+                aperture_efficiency,
+            } => {
+                let diameters = self
+                    .meta_arrays
+                    .antenna_diameters
+                    .as_ref()
+                    .ok_or("Antenna diameters are needed to convert Tsys to SEFD.")?;
+                let scale = diameters.mapv(|diam| {
+                    let area = aperture_efficiency * PI * (diam as f64 / 2.0).powi(2);
+                    2.0 * BOLTZMANN / area / JANSKY
+                });
+                (tsys, scale)
+            }
+        };
+        let (rows, cols) = values.dim();
+        if !(rows == 1 || rows == nants) || !(cols == 1 || cols == nfreqs) {
+            return Err(format!(
+                "System noise shape ({}, {}) does not broadcast to (Nants_telescope, Nfreqs) = ({}, {}).",
+                rows, cols, nants, nfreqs
+            ));
+        }
+        Ok(Array::from_shape_fn((nants, nfreqs), |(ant, freq)| {
+            let row = if rows == 1 { 0 } else { ant };
+            let col = if cols == 1 { 0 } else { freq };
+            values[[row, col]] * scale[ant]
+        }))
+    }
+
+    /// Expected thermal noise of every visibility from the radiometer equation.
+    ///
+    /// Returns the standard deviation (Jy) of the real and of the imaginary
+    /// part, sigma = sqrt(SEFD_1 SEFD_2 / (2 channel_width integration_time
+    /// nsample)), shaped like `data_array`. Samples with zero nsample have
+    /// infinite noise; nsamples of one are assumed if none are loaded.
+    pub fn expected_noise(&self, system_noise: &SystemNoise) -> Result<Array<f64, Ix3>, String> {
+        let sefd = self.sefd_array(system_noise)?;
+        let arrays = &self.meta_arrays;
+        let ant_index: HashMap<u32, usize> = arrays
+            .antenna_numbers
+            .iter()
+            .enumerate()
+            .map(|(ind, &num)| (num, ind))
+            .collect();
+        let index_of = |ant: u32| -> Result<usize, String> {
+            ant_index
+                .get(&ant)
+                .copied()
+                .ok_or(format!("Antenna {} is not in antenna_numbers.", ant))
+        };
+
+        let shape = (
+            self.meta.nblts as usize,
+            self.meta.nfreqs as usize,
+            self.meta.npols as usize,
+        );
+        let mut noise = Array::<f64, Ix3>::zeros(shape);
+        for (blt, mut blt_noise) in noise.outer_iter_mut().enumerate() {
+            let ind1 = index_of(arrays.ant_1_array[blt])?;
+            let ind2 = index_of(arrays.ant_2_array[blt])?;
+            let int_time = arrays.integration_time[blt];
+            for (freq, mut pols) in blt_noise.outer_iter_mut().enumerate() {
+                let power = sefd[[ind1, freq]] * sefd[[ind2, freq]];
+                let bandwidth_time = 2.0 * arrays.channel_width[freq] * int_time;
+                for (pol, sigma) in pols.iter_mut().enumerate() {
+                    let nsample = match &self.nsample_array {
+                        Some(nsamples) => nsamples[[blt, freq, pol]].to_f64().unwrap(),
+                        None => 1.0,
+                    };
+                    *sigma = match nsample > 0.0 {
+                        true => (power / (bandwidth_time * nsample)).sqrt(),
+                        false => f64::INFINITY,
+                    };
+                }
+            }
+        }
+        Ok(noise)
+    }
+
+    /// Estimate the noise of every visibility by differencing neighbouring samples.
+    ///
+    /// Unflagged neighbours of each baseline are differenced along `axis`
+    /// and the standard deviation of the real (or imaginary) part is
+    /// broadcast over that axis: time differencing gives one value per
+    /// baseline, frequency and polarization, frequency differencing one per
+    /// baseline-time and polarization. Values without any unflagged pair
+    /// are NaN.
+    pub fn estimate_noise(&self, axis: DifferenceAxis) -> Result<Array<f64, Ix3>, String> {
+        let data = self
+            .data_array
+            .as_ref()
+            .ok_or("Data must be loaded to estimate the noise.")?;
+        let flagged = |blt: usize, freq: usize, pol: usize| {
+            self.flag_array
+                .as_ref()
+                .is_some_and(|flags| flags[[blt, freq, pol]])
+        };
+        let (nblts, nfreqs, npols) = data.dim();
+        let mut noise = Array::<f64, Ix3>::from_elem((nblts, nfreqs, npols), f64::NAN);
+        match axis {
+            DifferenceAxis::Time => {
+                let times = &self.meta_arrays.time_array;
+                for (ant1, ant2) in self.antpairs() {
+                    let mut blts = self.antpair_blts(ant1, ant2);
+                    blts.sort_by(|&blt1, &blt2| times[blt1].total_cmp(&times[blt2]));
+                    for freq in 0..nfreqs {
+                        for pol in 0..npols {
+                            let sigma = difference_sigma(
+                                blts.windows(2)
+                                    .filter(|pair| {
+                                        !flagged(pair[0], freq, pol) && !flagged(pair[1], freq, pol)
+                                    })
+                                    .map(|pair| {
+                                        data[[pair[1], freq, pol]] - data[[pair[0], freq, pol]]
+                                    }),
+                            );
+                            for &blt in blts.iter() {
+                                noise[[blt, freq, pol]] = sigma;
+                            }
+                        }
+                    }
+                }
+            }
+            DifferenceAxis::Frequency => {
+                for blt in 0..nblts {
+                    for pol in 0..npols {
+                        let sigma = difference_sigma(
+                            (1..nfreqs)
+                                .filter(|&freq| {
+                                    !flagged(blt, freq - 1, pol) && !flagged(blt, freq, pol)
+                                })
+                                .map(|freq| data[[blt, freq, pol]] - data[[blt, freq - 1, pol]]),
+                        );
+                        noise.slice_mut(s![blt, .., pol]).fill(sigma);
+                    }
+                }
+            }
+        }
+        Ok(noise)
+    }
+
+    /// Estimate per-antenna SEFDs from the autocorrelations and the noise
+    /// of the cross-correlations.
+    ///
+    /// The time-averaged unflagged autocorrelations give the shape of each
+    /// SEFD; a scale per frequency and polarization is the median ratio of
+    /// the `estimate_noise` values to the noise the autos predict through
+    /// the radiometer equation. The result has shape (Nants_telescope,
+    /// Nfreqs, Npols) and is in the units of the visibilities. It is NaN
+    /// for antennas without autos and for cross-hand polarizations.
+    pub fn estimate_sefd(&self, axis: DifferenceAxis) -> Result<Array<f64, Ix3>, String> {
+        let noise = self.estimate_noise(axis)?;
+        let data = self.data_array.as_ref().unwrap();
+        let arrays = &self.meta_arrays;
+        let (nblts, nfreqs, npols) = data.dim();
+        let nants = self.meta.nants_telescope as usize;
+        let ant_index: HashMap<u32, usize> = arrays
+            .antenna_numbers
+            .iter()
+            .enumerate()
+            .map(|(ind, &num)| (num, ind))
+            .collect();
+        let flagged = |blt: usize, freq: usize, pol: usize| {
+            self.flag_array
+                .as_ref()
+                .is_some_and(|flags| flags[[blt, freq, pol]])
+        };
+        let nsample = |blt: usize, freq: usize, pol: usize| match &self.nsample_array {
+            Some(nsamples) => nsamples[[blt, freq, pol]].to_f64().unwrap(),
+            None => 1.0,
+        };
+
+        // time averaged autos
+        let mut auto_sum = Array::<f64, Ix3>::zeros((nants, nfreqs, npols));
+        let mut auto_count = Array::<f64, Ix3>::zeros((nants, nfreqs, npols));
+        for blt in 0..nblts {
+            if arrays.ant_1_array[blt] != arrays.ant_2_array[blt] {
+                continue;
+            }
+            let Some(&ant) = ant_index.get(&arrays.ant_1_array[blt]) else {
+                continue;
+            };
+            for freq in 0..nfreqs {
+                for pol in 0..npols {
+                    if !flagged(blt, freq, pol) {
+                        auto_sum[[ant, freq, pol]] += data[[blt, freq, pol]].re.to_f64().unwrap();
+                        auto_count[[ant, freq, pol]] += 1.0;
+                    }
+                }
+            }
+        }
+        let autos = auto_sum / auto_count;
+
+        let mut sefd = Array::<f64, Ix3>::from_elem((nants, nfreqs, npols), f64::NAN);
+        for (pol, &pol_num) in arrays.polarization_array.iter().enumerate() {
+            let parallel = matches!(
+                Polarization::from_num(pol_num),
+                Ok(Polarization::XX | Polarization::YY | Polarization::RR | Polarization::LL)
+            );
+            if !parallel {
+                continue;
+            }
+            for freq in 0..nfreqs {
+                let ratios: Vec<f64> = (0..nblts)
+                    .filter(|&blt| arrays.ant_1_array[blt] != arrays.ant_2_array[blt])
+                    .filter(|&blt| !flagged(blt, freq, pol) && nsample(blt, freq, pol) > 0.0)
+                    .filter_map(|blt| {
+                        let ind1 = ant_index.get(&arrays.ant_1_array[blt])?;
+                        let ind2 = ant_index.get(&arrays.ant_2_array[blt])?;
+                        let power = autos[[*ind1, freq, pol]] * autos[[*ind2, freq, pol]];
+                        let predicted = (power
+                            / (2.0
+                                * arrays.channel_width[freq]
+                                * arrays.integration_time[blt]
+                                * nsample(blt, freq, pol)))
+                        .sqrt();
+                        Some(noise[[blt, freq, pol]] / predicted)
+                    })
+                    .filter(|ratio| ratio.is_finite())
+                    .collect();
+                let scale = utils::median(ratios);
+                for ant in 0..nants {
+                    sefd[[ant, freq, pol]] = scale * autos[[ant, freq, pol]];
+                }
+            }
+        }
+        Ok(sefd)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DifferenceAxis, SystemNoise, UVData, BOLTZMANN, JANSKY};
+    use crate::test_data::read_drift;
+    use ndarray::Array;
+    use num_complex::Complex;
+    use std::{f64::consts::PI, str::FromStr};
+
+    #[test]
+    fn radiometer_equation() {
+        let mut uvd = read_drift();
+        uvd.nsample_array.as_mut().unwrap()[[3, 1, 0]] = 4.0;
+        uvd.nsample_array.as_mut().unwrap()[[3, 2, 0]] = 0.0;
+        let noise = uvd
+            .expected_noise(&SystemNoise::scalar_sefd(400.0))
+            .expect("Cannot compute noise.");
+        assert_eq!(noise.shape(), uvd.data_array.as_ref().unwrap().shape());
+
+        let int_time = uvd.meta_arrays.integration_time[3];
+        let width = uvd.meta_arrays.channel_width[1];
+        let expected = 400.0 / (2.0 * width * int_time).sqrt();
+        assert_abs_diff_eq!(noise[[3, 1, 1]], expected, epsilon = 1e-9);
+        assert_abs_diff_eq!(noise[[3, 1, 0]], expected / 2.0, epsilon = 1e-9);
+        assert!(noise[[3, 2, 0]].is_infinite());
+    }
+
+    #[test]
+    fn per_antenna_tsys() {
+        let mut uvd = read_drift();
+        let nants = uvd.meta.nants_telescope as usize;
+        let nfreqs = uvd.meta.nfreqs as usize;
+        uvd.meta_arrays.antenna_diameters = Some(Array::from_elem(nants, 14.0));
+
+        let tsys = Array::from_shape_fn((nants, nfreqs), |(ant, _)| 100.0 + ant as f64);
+        let noise = uvd
+            .expected_noise(&SystemNoise::Tsys {
+                tsys: tsys.clone(),
+                aperture_efficiency: 0.7,
+            })
+            .expect("Cannot compute noise.");
+
+        let blt = 5;
+        let arrays = &uvd.meta_arrays;
+        let ind = |ant: u32| {
+            arrays
+                .antenna_numbers
+                .iter()
+                .position(|&x| x == ant)
+                .unwrap()
+        };
+        let (ind1, ind2) = (ind(arrays.ant_1_array[blt]), ind(arrays.ant_2_array[blt]));
+        let to_sefd = 2.0 * BOLTZMANN / (0.7 * PI * 49.0) / JANSKY;
+        let sefd1 = tsys[[ind1, 0]] * to_sefd;
+        let sefd2 = tsys[[ind2, 0]] * to_sefd;
+        let expected =
+            (sefd1 * sefd2 / (2.0 * arrays.channel_width[0] * arrays.integration_time[blt])).sqrt();
+        assert_abs_diff_eq!(noise[[blt, 0, 0]], expected, epsilon = 1e-9);
+
+        let bad = SystemNoise::Sefd(Array::from_elem((2, 3), 1.0));
+        assert!(uvd.expected_noise(&bad).is_err());
+        uvd.meta_arrays.antenna_diameters = None;
+        assert!(uvd
+            .expected_noise(&SystemNoise::Tsys {
+                tsys,
+                aperture_efficiency: 0.7
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn difference_axis_from_str() {
+        assert_eq!(
+            DifferenceAxis::from_str(" Freq").unwrap(),
+            DifferenceAxis::Frequency
+        );
+        assert!(DifferenceAxis::from_str("baseline").is_err());
+    }
+
+    // sign of each sample when alternating along the time of its antenna pair
+    fn time_signs(uvd: &UVData<f64, f32>) -> Vec<f64> {
+        let mut signs = vec![1.0; uvd.meta.nblts as usize];
+        for (ant1, ant2) in uvd.antpairs().collect::<Vec<_>>() {
+            let times = &uvd.meta_arrays.time_array;
+            let mut blts = uvd.antpair_blts(ant1, ant2);
+            blts.sort_by(|&blt1, &blt2| times[blt1].total_cmp(&times[blt2]));
+            for (ind, blt) in blts.into_iter().enumerate() {
+                signs[blt] = if ind % 2 == 0 { 1.0 } else { -1.0 };
+            }
+        }
+        signs
+    }
+
+    #[test]
+    fn differenced_noise() {
+        let mut uvd = read_drift();
+        let signs = time_signs(&uvd);
+        let data = uvd.data_array.as_mut().unwrap();
+        for ((blt, freq, _), vis) in data.indexed_iter_mut() {
+            *vis = Complex::new(5.0 + 0.5 * signs[blt], 3.0 * freq as f64);
+        }
+        uvd.flag_array.as_mut().unwrap().fill(false);
+        uvd.flag_array.as_mut().unwrap()[[0, 0, 0]] = true;
+
+        let noise = uvd
+            .estimate_noise(DifferenceAxis::Time)
+            .expect("Cannot estimate noise.");
+        assert_eq!(noise.shape(), uvd.data_array.as_ref().unwrap().shape());
+        assert!(noise.iter().all(|&sigma| (sigma - 0.5).abs() < 1e-9));
+
+        // adjacent channels differ by 3i
+        let noise = uvd
+            .estimate_noise(DifferenceAxis::Frequency)
+            .expect("Cannot estimate noise.");
+        assert_abs_diff_eq!(noise[[4, 0, 1]], 1.5, epsilon = 1e-9);
+        assert_abs_diff_eq!(noise[[0, 3, 0]], 1.5, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn sefd_from_autos() {
+        let mut uvd = read_drift();
+        let signs = time_signs(&uvd);
+        uvd.flag_array.as_mut().unwrap().fill(false);
+        uvd.nsample_array.as_mut().unwrap().fill(1.0);
+        let arrays = uvd.meta_arrays.clone();
+        let auto_power = |ant: u32| 100.0 + 10.0 * ant as f64;
+        let data = uvd.data_array.as_mut().unwrap();
+        for ((blt, freq, _), vis) in data.indexed_iter_mut() {
+            let (ant1, ant2) = (arrays.ant_1_array[blt], arrays.ant_2_array[blt]);
+            *vis = match ant1 == ant2 {
+                true => Complex::new(auto_power(ant1), 0.0),
+                // twice the noise the autos predict
+                false => {
+                    let sigma = (auto_power(ant1) * auto_power(ant2)
+                        / (2.0 * arrays.channel_width[freq] * arrays.integration_time[blt]))
+                        .sqrt();
+                    Complex::new(2.0 * sigma * signs[blt], 0.0)
+                }
+            };
+        }
+
+        let sefd = uvd
+            .estimate_sefd(DifferenceAxis::Time)
+            .expect("Cannot estimate SEFDs.");
+        assert_eq!(
+            sefd.shape(),
+            [
+                uvd.meta.nants_telescope as usize,
+                uvd.meta.nfreqs as usize,
+                2
+            ]
+        );
+        for (ind, &ant) in arrays.antenna_numbers.iter().enumerate() {
+            match [0, 1, 2, 11].contains(&ant) {
+                true => {
+                    assert_abs_diff_eq!(sefd[[ind, 2, 1]], 2.0 * auto_power(ant), epsilon = 1e-6)
+                }
+                false => assert!(sefd[[ind, 2, 1]].is_nan()),
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use approx::AbsDiffEq;
+use ndarray::{Array, Axis, Ix3};
+use num_complex::Complex;
+use num_traits::Float;
+
+use super::base::IntoPolNum;
+use super::UVData;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum FeedBasis {
+    Stokes,
+    Circular,
+    Linear,
+}
+
+fn basis_of(pol: i8) -> Result<FeedBasis, String> {
+    match pol {
+        1..=4 => Ok(FeedBasis::Stokes),
+        -4..=-1 => Ok(FeedBasis::Circular),
+        -8..=-5 => Ok(FeedBasis::Linear),
+        other => Err(format!("Unknown polarization number: {}.", other)),
+    }
+}
+
+fn half(re: f64, im: f64) -> Complex<f64> {
+    Complex::new(re, im) / 2.0
+}
+
+/// The stokes parameter `stokes` as a combination of correlations in `basis`.
+fn stokes_from(stokes: i8, basis: FeedBasis) -> Vec<(i8, Complex<f64>)> {
+    // I = (XX + YY) / 2, Q = (XX - YY) / 2, U = (XY + YX) / 2, V = -i (XY - YX) / 2
+    // I = (RR + LL) / 2, V = (RR - LL) / 2, Q = (RL + LR) / 2, U = -i (RL - LR) / 2
+    match (basis, stokes) {
+        (FeedBasis::Linear, 1) => vec![(-5, half(1.0, 0.0)), (-6, half(1.0, 0.0))],
+        (FeedBasis::Linear, 2) => vec![(-5, half(1.0, 0.0)), (-6, half(-1.0, 0.0))],
+        (FeedBasis::Linear, 3) => vec![(-7, half(1.0, 0.0)), (-8, half(1.0, 0.0))],
+        (FeedBasis::Linear, 4) => vec![(-7, half(0.0, -1.0)), (-8, half(0.0, 1.0))],
+        (FeedBasis::Circular, 1) => vec![(-1, half(1.0, 0.0)), (-2, half(1.0, 0.0))],
+        (FeedBasis::Circular, 2) => vec![(-3, half(1.0, 0.0)), (-4, half(1.0, 0.0))],
+        (FeedBasis::Circular, 3) => vec![(-3, half(0.0, -1.0)), (-4, half(0.0, 1.0))],
+        (FeedBasis::Circular, 4) => vec![(-1, half(1.0, 0.0)), (-2, half(-1.0, 0.0))],
+        _ => vec![(stokes, Complex::new(1.0, 0.0))],
+    }
+}
+
+/// The correlation `pol` as a combination of stokes parameters.
+fn correlation_from_stokes(pol: i8) -> Vec<(i8, Complex<f64>)> {
+    let one = Complex::new(1.0, 0.0);
+    let i = Complex::new(0.0, 1.0);
+    match pol {
+        // RR = I + V, LL = I - V, RL = Q + iU, LR = Q - iU
+        -1 => vec![(1, one), (4, one)],
+        -2 => vec![(1, one), (4, -one)],
+        -3 => vec![(2, one), (3, i)],
+        -4 => vec![(2, one), (3, -i)],
+        // XX = I + Q, YY = I - Q, XY = U + iV, YX = U - iV
+        -5 => vec![(1, one), (2, one)],
+        -6 => vec![(1, one), (2, -one)],
+        -7 => vec![(3, one), (4, i)],
+        -8 => vec![(3, one), (4, -i)],
+        _ => vec![(pol, one)],
+    }
+}
+
+/// Coefficients expressing `pol` in terms of correlations in `basis`.
+fn recipe(pol: i8, basis: FeedBasis) -> Result<Vec<(i8, Complex<f64>)>, String> {
+    let out_basis = basis_of(pol)?;
+    if out_basis == basis {
+        return Ok(vec![(pol, Complex::new(1.0, 0.0))]);
+    }
+    let stokes_terms = match out_basis {
+        FeedBasis::Stokes => vec![(pol, Complex::new(1.0, 0.0))],
+        _ => correlation_from_stokes(pol),
+    };
+    if basis == FeedBasis::Stokes {
+        return Ok(stokes_terms);
+    }
+    let mut terms: Vec<(i8, Complex<f64>)> = Vec::new();
+    for (stokes, coeff) in stokes_terms {
+        for (input, sub_coeff) in stokes_from(stokes, basis) {
+            match terms.iter_mut().find(|(num, _)| *num == input) {
+                Some(term) => term.1 += coeff * sub_coeff,
+                None => terms.push((input, coeff * sub_coeff)),
+            }
+        }
+    }
+    Ok(terms
+        .into_iter()
+        .filter(|(_, coeff)| coeff.norm() > 1e-12)
+        .collect())
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Convert the data to the polarizations `pols`, given as AIPS numbers
+    /// or strings like "pI" or "ee".
+    ///
+    /// The current polarizations must all be in one basis (linear, circular
+    /// or pseudo-Stokes). Each output is flagged if any of its inputs is
+    /// flagged and takes the smallest nsample of its inputs.
+    pub fn convert_polarizations<P: IntoPolNum>(&mut self, pols: &[P]) -> Result<(), String> {
+        if pols.is_empty() {
+            return Err("No polarizations requested.".to_string());
+        }
+        let pols = pols
+            .iter()
+            .map(|pol| pol.pol_num(self.meta.x_orientation))
+            .collect::<Result<Vec<i8>, String>>()?;
+        let current = self.meta_arrays.polarization_array.to_vec();
+        let basis = basis_of(current[0])?;
+        for &pol in current.iter() {
+            if basis_of(pol)? != basis {
+                return Err("Input polarizations are in mixed bases.".to_string());
+            }
+        }
+
+        let mut recipes: Vec<Vec<(usize, Complex<T>)>> = Vec::with_capacity(pols.len());
+        for &pol in pols.iter() {
+            let mut terms = Vec::new();
+            for (input, coeff) in recipe(pol, basis)? {
+                let ind = current.iter().position(|&num| num == input).ok_or(format!(
+                    "Polarization {} requires {} which is not in the data.",
+                    pol, input
+                ))?;
+                terms.push((
+                    ind,
+                    Complex::new(T::from(coeff.re).unwrap(), T::from(coeff.im).unwrap()),
+                ));
+            }
+            recipes.push(terms);
+        }
+
+        let nblts = self.meta.nblts as usize;
+        let nfreqs = self.meta.nfreqs as usize;
+        let shape = (nblts, nfreqs, pols.len());
+        if let Some(data) = &self.data_array {
+            let mut new_data = Array::<Complex<T>, Ix3>::zeros(shape);
+            for (out, terms) in recipes.iter().enumerate() {
+                for &(ind, coeff) in terms {
+                    let mut out_view = new_data.index_axis_mut(Axis(2), out);
+                    out_view.zip_mut_with(&data.index_axis(Axis(2), ind), |vis, &x| {
+                        *vis = *vis + x * coeff
+                    });
+                }
+            }
+            self.data_array = Some(new_data);
+        }
+        if let Some(flags) = &self.flag_array {
+            let mut new_flags = Array::<bool, Ix3>::from_elem(shape, false);
+            for (out, terms) in recipes.iter().enumerate() {
+                for &(ind, _) in terms {
+                    let mut out_view = new_flags.index_axis_mut(Axis(2), out);
+                    out_view.zip_mut_with(&flags.index_axis(Axis(2), ind), |flag, &x| *flag |= x);
+                }
+            }
+            self.flag_array = Some(new_flags);
+        }
+        if let Some(nsamples) = &self.nsample_array {
+            let mut new_nsamples = Array::<S, Ix3>::zeros(shape);
+            for (out, terms) in recipes.iter().enumerate() {
+                let mut out_view = new_nsamples.index_axis_mut(Axis(2), out);
+                out_view.assign(&nsamples.index_axis(Axis(2), terms[0].0));
+                for &(ind, _) in terms.iter().skip(1) {
+                    out_view
+                        .zip_mut_with(&nsamples.index_axis(Axis(2), ind), |ns, &x| *ns = ns.min(x));
+                }
+            }
+            self.nsample_array = Some(new_nsamples);
+        }
+
+        self.meta.npols = pols.len() as u8;
+        self.meta_arrays.polarization_array = Array::from(pols);
+        Ok(())
+    }
+
+    /// Convert linear or circular correlations to every pseudo-Stokes
+    /// parameter that can be formed from the polarizations present.
+    pub fn to_pseudo_stokes(&mut self) -> Result<(), String> {
+        let current = self.meta_arrays.polarization_array.to_vec();
+        let basis = basis_of(*current.first().ok_or("No polarizations in the data.")?)?;
+        let stokes: Vec<i8> = (1..=4)
+            .filter(|&pol| {
+                recipe(pol, basis)
+                    .map(|terms| terms.iter().all(|(input, _)| current.contains(input)))
+                    .unwrap_or(false)
+            })
+            .collect();
+        if stokes.is_empty() {
+            return Err("No pseudo-Stokes parameters can be formed from the data.".to_string());
+        }
+        self.convert_polarizations(&stokes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{recipe, FeedBasis};
+    use crate::test_data::read_drift;
+    use num_complex::Complex;
+
+    #[test]
+    fn linear_circular_recipes() {
+        // XY = U + iV = -i/2 (RL - LR) + i/2 (RR - LL)
+        let mut terms = recipe(-7, FeedBasis::Circular).unwrap();
+        terms.sort_by_key(|(pol, _)| *pol);
+        let expected = [
+            (-4, Complex::new(0.0, 0.5)),
+            (-3, Complex::new(0.0, -0.5)),
+            (-2, Complex::new(0.0, -0.5)),
+            (-1, Complex::new(0.0, 0.5)),
+        ];
+        assert_eq!(terms.len(), expected.len());
+        for ((pol, coeff), (exp_pol, exp_coeff)) in terms.iter().zip(expected.iter()) {
+            assert_eq!(pol, exp_pol);
+            assert_abs_diff_eq!(coeff.re, exp_coeff.re);
+            assert_abs_diff_eq!(coeff.im, exp_coeff.im);
+        }
+    }
+
+    #[test]
+    fn pseudo_stokes_roundtrip() {
+        let uvd = read_drift();
+        let mut stokes = uvd.clone();
+        stokes.to_pseudo_stokes().expect("Cannot convert.");
+        assert_eq!(stokes.meta.npols, 2);
+        assert_eq!(stokes.meta_arrays.polarization_array.to_vec(), vec![1, 2]);
+
+        let data = uvd.data_array.as_ref().unwrap();
+        let stokes_i = stokes.data_array.as_ref().unwrap()[[7, 1, 0]];
+        let expected = (data[[7, 1, 0]] + data[[7, 1, 1]]) / 2.0;
+        assert_abs_diff_eq!(stokes_i.re, expected.re);
+        assert_abs_diff_eq!(stokes_i.im, expected.im);
+
+        stokes
+            .convert_polarizations(&[-5, -6])
+            .expect("Cannot convert.");
+        assert_eq!(stokes, uvd);
+    }
+
+    #[test]
+    fn flags_are_combined() {
+        let mut uvd = read_drift();
+        uvd.flag_array.as_mut().unwrap()[[3, 2, 1]] = true;
+        uvd.nsample_array.as_mut().unwrap()[[3, 2, 0]] = 0.5;
+        uvd.convert_polarizations(&["pI"]).expect("Cannot convert.");
+        assert!(uvd.flag_array.as_ref().unwrap()[[3, 2, 0]]);
+        assert_abs_diff_eq!(uvd.nsample_array.as_ref().unwrap()[[3, 2, 0]], 0.5);
+    }
+
+    #[test]
+    fn missing_inputs() {
+        let mut uvd = read_drift();
+        assert!(uvd.convert_polarizations(&[3i8]).is_err());
+        assert!(uvd.convert_polarizations(&[-1i8]).is_err());
+        assert!(uvd.convert_polarizations(&[9i8]).is_err());
+        assert!(uvd.convert_polarizations(&["ll"]).is_err());
+    }
+}
@@ -0,0 +1,1094 @@
+use approx::AbsDiffEq;
+use ndarray::{Array, Ix1, Ix3, Ix4};
+use num_complex::Complex;
+use num_traits::{Float, Zero};
+use std::{
+    collections::{BTreeSet, HashMap},
+    f64::consts::PI,
+    str::FromStr,
+};
+
+use super::uvcal::{CalStyle, CalType, UVCal};
+use super::UVData;
+
+/// Solver refining the logcal solution.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RedSolver {
+    /// Stop after logcal.
+    Logcal,
+    Lincal,
+    Omnical,
+}
+
+impl FromStr for RedSolver {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<RedSolver, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "logcal" => Ok(RedSolver::Logcal),
+            "lincal" => Ok(RedSolver::Lincal),
+            "omnical" => Ok(RedSolver::Omnical),
+            other => Err(format!("Unknown redundant calibration solver: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for RedSolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Options for redundant calibration.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RedCal {
+    /// Redundancy tolerance in meters.
+    pub tol: f64,
+    /// Groups with fewer baselines do not constrain the gains and are dropped.
+    pub min_bls_per_group: usize,
+    pub solver: RedSolver,
+    pub max_iter: usize,
+    /// Relative change of the solution at which iterations stop.
+    pub conv_crit: f64,
+    /// Omnical step size, between 0 and 1.
+    pub step: f64,
+}
+
+impl Default for RedCal {
+    fn default() -> RedCal {
+        RedCal {
+            tol: 1.0,
+            min_bls_per_group: 2,
+            solver: RedSolver::Omnical,
+            max_iter: 500,
+            conv_crit: 1e-10,
+            step: 0.3,
+        }
+    }
+}
+
+/// Redundant calibration solutions.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RedSolution {
+    /// Per-antenna gains for the Jones element of each parallel-hand
+    /// polarization.
+    pub gains: UVCal,
+    /// Antenna pairs of each redundant group, oriented like the group.
+    pub groups: Vec<Vec<(u32, u32)>>,
+    /// Model visibility of each group, shape (Ngroups, Ntimes, Nfreqs, Njones).
+    pub model_vis: Array<Complex<f64>, Ix4>,
+    /// Summed squared residual magnitudes, shape (Ntimes, Nfreqs, Njones).
+    pub chisq: Array<f64, Ix3>,
+}
+
+/// A weighted row of a sparse linear system.
+struct Row {
+    entries: Vec<(usize, f64)>,
+    value: f64,
+    weight: f64,
+}
+
+/// Weighted least squares by conjugate gradients on the normal equations
+/// (CGLS).
+///
+/// Only products with the sparse rows are formed, so the cost of an
+/// iteration scales with the number of row entries rather than with the
+/// square of `nparams`. Starting from zero keeps the iterates in the span of
+/// the rows, leaving unconstrained directions at zero.
+fn least_squares(rows: &[Row], nparams: usize) -> Array<f64, Ix1> {
+    let rows: Vec<&Row> = rows.iter().filter(|row| row.weight > 0.0).collect();
+    let apply = |params: &Array<f64, Ix1>| -> Vec<f64> {
+        rows.iter()
+            .map(|row| {
+                row.entries
+                    .iter()
+                    .map(|&(ind, value)| value * params[ind])
+                    .sum()
+            })
+            .collect()
+    };
+    let apply_transpose = |residual: &[f64]| -> Array<f64, Ix1> {
+        let mut out = Array::<f64, Ix1>::zeros(nparams);
+        for (row, &res) in rows.iter().zip(residual) {
+            for &(ind, value) in &row.entries {
+                out[ind] += row.weight * value * res;
+            }
+        }
+        out
+    };
+
+    let mut solution = Array::<f64, Ix1>::zeros(nparams);
+    let mut residual: Vec<f64> = rows.iter().map(|row| row.value).collect();
+    let mut gradient = apply_transpose(&residual);
+    let mut direction = gradient.clone();
+    let mut gamma = gradient.dot(&gradient);
+    // iterating once the gradient reaches roundoff level, relative to its
+    // bound |A|^2 |b|^2, only amplifies noise in the unconstrained directions
+    let bound: f64 = rows
+        .iter()
+        .map(|row| {
+            row.weight
+                * row
+                    .entries
+                    .iter()
+                    .map(|(_, value)| value * value)
+                    .sum::<f64>()
+        })
+        .sum::<f64>()
+        * rows
+            .iter()
+            .map(|row| row.weight * row.value * row.value)
+            .sum::<f64>();
+    let target = bound * 1e-20;
+    for _ in 0..2 * nparams.max(1) {
+        if gamma <= target {
+            break;
+        }
+        let product = apply(&direction);
+        let curvature: f64 = rows
+            .iter()
+            .zip(&product)
+            .map(|(row, value)| row.weight * value * value)
+            .sum();
+        if curvature <= 0.0 {
+            break;
+        }
+        let alpha = gamma / curvature;
+        solution.scaled_add(alpha, &direction);
+        for (res, value) in residual.iter_mut().zip(&product) {
+            *res -= alpha * value;
+        }
+        gradient = apply_transpose(&residual);
+        let new_gamma = gradient.dot(&gradient);
+        direction = &gradient + &(direction * (new_gamma / gamma));
+        gamma = new_gamma;
+    }
+    solution
+}
+
+/// Fit `values` per antenna as an offset plus a gradient in east/north.
+fn fit_plane(values: &[f64], positions: &[[f64; 2]], weights: &[f64]) -> [f64; 3] {
+    let rows: Vec<Row> = values
+        .iter()
+        .zip(positions)
+        .zip(weights)
+        .map(|((&value, pos), &weight)| Row {
+            entries: vec![(0, 1.0), (1, pos[0]), (2, pos[1])],
+            value,
+            weight,
+        })
+        .collect();
+    let fit = least_squares(&rows, 3);
+    [fit[0], fit[1], fit[2]]
+}
+
+/// Visibilities of the redundant baselines, arranged for calibration.
+struct RedData {
+    ants: Vec<u32>,
+    /// East/north antenna positions.
+    positions: Vec<[f64; 2]>,
+    groups: Vec<Vec<(u32, u32)>>,
+    /// (antenna index 1, antenna index 2, group) of each baseline.
+    baselines: Vec<(usize, usize, usize)>,
+    /// Shape (Nbls, Ntimes, Nfreqs, Njones).
+    vis: Array<Complex<f64>, Ix4>,
+    weights: Array<f64, Ix4>,
+}
+
+impl RedData {
+    fn ngroups(&self) -> usize {
+        self.groups.len()
+    }
+
+    fn nants(&self) -> usize {
+        self.ants.len()
+    }
+
+    /// Visibilities and weights of one time, frequency and polarization.
+    fn sample(&self, time: usize, freq: usize, pol: usize) -> (Vec<Complex<f64>>, Vec<f64>) {
+        (0..self.baselines.len())
+            .map(|bl| {
+                (
+                    self.vis[[bl, time, freq, pol]],
+                    self.weights[[bl, time, freq, pol]],
+                )
+            })
+            .unzip()
+    }
+
+    fn model(&self, gains: &[Complex<f64>], model: &[Complex<f64>]) -> Vec<Complex<f64>> {
+        self.baselines
+            .iter()
+            .map(|&(ind1, ind2, group)| gains[ind1] * gains[ind2].conj() * model[group])
+            .collect()
+    }
+
+    fn chisq(
+        &self,
+        vis: &[Complex<f64>],
+        weights: &[f64],
+        gains: &[Complex<f64>],
+        model: &[Complex<f64>],
+    ) -> f64 {
+        self.model(gains, model)
+            .iter()
+            .zip(vis)
+            .zip(weights)
+            .filter(|(_, &weight)| weight > 0.0)
+            .map(|((model, vis), _)| (vis - model).norm_sqr())
+            .sum()
+    }
+
+    /// Delay combinations τ_i - τ_j - τ_k + τ_l from pairs of baselines of
+    /// a group, returning each antenna's delay with the mean delay and
+    /// delay gradient removed.
+    fn firstcal(&self, time: usize, pol: usize, freqs: &[f64]) -> (Vec<f64>, Vec<bool>) {
+        let nfreqs = freqs.len();
+        let dnu = match nfreqs {
+            0 | 1 => 1.0,
+            _ => (freqs[nfreqs - 1] - freqs[0]) / (nfreqs - 1) as f64,
+        };
+        let mut rows = Vec::new();
+        for group in 0..self.ngroups() {
+            let bls: Vec<usize> = (0..self.baselines.len())
+                .filter(|&bl| self.baselines[bl].2 == group)
+                .collect();
+            for &bl in &bls[1..] {
+                let ratio = |freq: usize| {
+                    let weight = self.weights[[bl, time, freq, pol]]
+                        * self.weights[[bls[0], time, freq, pol]];
+                    match weight > 0.0 {
+                        true => {
+                            self.vis[[bl, time, freq, pol]]
+                                * self.vis[[bls[0], time, freq, pol]].conj()
+                        }
+                        false => Complex::zero(),
+                    }
+                };
+                let slope: Complex<f64> = (0..nfreqs.saturating_sub(1))
+                    .map(|freq| ratio(freq + 1) * ratio(freq).conj())
+                    .sum();
+                if slope.norm() == 0.0 {
+                    continue;
+                }
+                let (ind1, ind2, _) = self.baselines[bl];
+                let (ind3, ind4, _) = self.baselines[bls[0]];
+                let mut entries = HashMap::new();
+                for (ind, sign) in [(ind1, 1.0), (ind2, -1.0), (ind3, -1.0), (ind4, 1.0)] {
+                    *entries.entry(ind).or_insert(0.0) += sign;
+                }
+                rows.push(Row {
+                    entries: entries.into_iter().collect(),
+                    value: -slope.arg() / (2.0 * PI * dnu),
+                    weight: 1.0,
+                });
+            }
+        }
+        let mut delays = least_squares(&rows, self.nants()).to_vec();
+        let solved: Vec<bool> = (0..self.nants())
+            .map(|ant| {
+                rows.iter().any(|row| {
+                    row.entries
+                        .iter()
+                        .any(|&(ind, val)| ind == ant && val != 0.0)
+                })
+            })
+            .collect();
+        let weights: Vec<f64> = solved.iter().map(|&solved| solved as u8 as f64).collect();
+        let [offset, east, north] = fit_plane(&delays, &self.positions, &weights);
+        for (delay, pos) in delays.iter_mut().zip(&self.positions) {
+            *delay -= offset + east * pos[0] + north * pos[1];
+        }
+        (delays, solved.iter().map(|&solved| !solved).collect())
+    }
+
+    /// Solve the logarithms of the data for gains relative to `start`.
+    fn logcal(
+        &self,
+        vis: &[Complex<f64>],
+        weights: &[f64],
+        start: &[Complex<f64>],
+    ) -> (Vec<Complex<f64>>, Vec<Complex<f64>>) {
+        let nants = self.nants();
+        let mut amp_rows = Vec::new();
+        let mut phase_rows = Vec::new();
+        for (&(ind1, ind2, group), (&vis, &weight)) in
+            self.baselines.iter().zip(vis.iter().zip(weights))
+        {
+            let vis = vis / (start[ind1] * start[ind2].conj());
+            let weight = match vis.norm() > 0.0 {
+                true => weight,
+                false => 0.0,
+            };
+            amp_rows.push(Row {
+                entries: vec![(ind1, 1.0), (ind2, 1.0), (nants + group, 1.0)],
+                value: vis.norm().ln(),
+                weight,
+            });
+            let mut entries = vec![(nants + group, 1.0)];
+            if ind1 != ind2 {
+                entries.extend([(ind1, 1.0), (ind2, -1.0)]);
+            }
+            phase_rows.push(Row {
+                entries,
+                value: vis.arg(),
+                weight,
+            });
+        }
+        let amps = least_squares(&amp_rows, nants + self.ngroups());
+        let phases = least_squares(&phase_rows, nants + self.ngroups());
+        let solution = |ind: usize| Complex::from_polar(amps[ind].exp(), phases[ind]);
+        (
+            (0..nants).map(|ind| start[ind] * solution(ind)).collect(),
+            (0..self.ngroups())
+                .map(|group| solution(nants + group))
+                .collect(),
+        )
+    }
+
+    /// Gauss-Newton iterations on the linearized complex equations.
+    ///
+    /// The sparse Jacobian is laid out once and only its values are
+    /// updated between iterations.
+    fn lincal(
+        &self,
+        vis: &[Complex<f64>],
+        weights: &[f64],
+        gains: &mut [Complex<f64>],
+        model: &mut [Complex<f64>],
+        opts: &RedCal,
+    ) {
+        let nants = self.nants();
+        let ngroups = self.ngroups();
+        // real and imaginary rows of each baseline, over the columns
+        // dη_1, dη_2, dφ_1, dφ_2, Re(dy), Im(dy)
+        let mut rows: Vec<Row> = self
+            .baselines
+            .iter()
+            .zip(weights)
+            .flat_map(|(&(ind1, ind2, group), &weight)| {
+                let cols = [
+                    ind1,
+                    ind2,
+                    nants + ind1,
+                    nants + ind2,
+                    2 * nants + group,
+                    2 * nants + ngroups + group,
+                ];
+                [0, 1].map(|_| Row {
+                    entries: cols.iter().map(|&col| (col, 0.0)).collect(),
+                    value: 0.0,
+                    weight,
+                })
+            })
+            .collect();
+        for _ in 0..opts.max_iter {
+            for (bl, (&(ind1, ind2, group), &vis)) in self.baselines.iter().zip(vis).enumerate() {
+                let product = gains[ind1] * gains[ind2].conj();
+                let current = product * model[group];
+                let residual = vis - current;
+                // d(model) = current (dη_1 + dη_2 + i dφ_1 - i dφ_2) + product dy
+                let parts = [
+                    current,
+                    current,
+                    current * Complex::i(),
+                    -current * Complex::i(),
+                    product,
+                    product * Complex::i(),
+                ];
+                for (part, entry) in parts.iter().zip(rows[2 * bl].entries.iter_mut()) {
+                    entry.1 = part.re;
+                }
+                rows[2 * bl].value = residual.re;
+                for (part, entry) in parts.iter().zip(rows[2 * bl + 1].entries.iter_mut()) {
+                    entry.1 = part.im;
+                }
+                rows[2 * bl + 1].value = residual.im;
+            }
+            let update = least_squares(&rows, 2 * nants + 2 * ngroups);
+            for (ant, gain) in gains.iter_mut().enumerate() {
+                *gain *= Complex::new(update[ant], update[nants + ant]).exp();
+            }
+            for (group, vis) in model.iter_mut().enumerate() {
+                *vis += Complex::new(
+                    update[2 * nants + group],
+                    update[2 * nants + ngroups + group],
+                );
+            }
+            let norm: f64 = gains.iter().chain(model.iter()).map(|x| x.norm_sqr()).sum();
+            if update.iter().map(|x| x * x).sum::<f64>() <= opts.conv_crit * norm {
+                break;
+            }
+        }
+    }
+
+    /// Damped alternating updates of the gains and model visibilities.
+    fn omnical(
+        &self,
+        vis: &[Complex<f64>],
+        weights: &[f64],
+        gains: &mut [Complex<f64>],
+        model: &mut [Complex<f64>],
+        opts: &RedCal,
+    ) {
+        let nants = self.nants();
+        let ngroups = self.ngroups();
+        for _ in 0..opts.max_iter {
+            let mut gain_num = vec![Complex::<f64>::zero(); nants];
+            let mut gain_den = vec![0.0; nants];
+            let mut model_num = vec![Complex::<f64>::zero(); ngroups];
+            let mut model_den = vec![0.0; ngroups];
+            for (&(ind1, ind2, group), (&vis, &weight)) in
+                self.baselines.iter().zip(vis.iter().zip(weights))
+            {
+                let partner1 = gains[ind2].conj() * model[group];
+                gain_num[ind1] += vis * partner1.conj() * weight;
+                gain_den[ind1] += partner1.norm_sqr() * weight;
+                let partner2 = gains[ind1] * model[group];
+                gain_num[ind2] += vis.conj() * partner2 * weight;
+                gain_den[ind2] += partner2.norm_sqr() * weight;
+                let product = gains[ind1] * gains[ind2].conj();
+                model_num[group] += vis * product.conj() * weight;
+                model_den[group] += product.norm_sqr() * weight;
+            }
+            let mut change = 0.0;
+            let mut norm = 0.0;
+            let updates = gains
+                .iter_mut()
+                .zip(gain_num.iter().zip(&gain_den))
+                .chain(model.iter_mut().zip(model_num.iter().zip(&model_den)));
+            for (value, (num, &den)) in updates {
+                if den > 0.0 {
+                    let new = *value * (1.0 - opts.step) + num / den * opts.step;
+                    change += (new - *value).norm_sqr();
+                    *value = new;
+                }
+                norm += value.norm_sqr();
+            }
+            if change <= opts.conv_crit * norm {
+                break;
+            }
+        }
+    }
+
+    /// Gain phases unwrapped across the array: each solved antenna is
+    /// reached from the first one along a nearest neighbour spanning tree,
+    /// stepping by its phase relative to its neighbour. Unsolved antennas
+    /// are measured relative to the first solved one.
+    fn unwrapped_phases(&self, gains: &[Complex<f64>], solved: &[bool]) -> Vec<f64> {
+        let nants = self.nants();
+        let reference = solved.iter().position(|&solved| solved).unwrap_or(0);
+        let relative = |ant: usize, other: usize| (gains[ant] * gains[other].conj()).arg();
+        let ref_phase = gains[reference].arg();
+        let mut phases: Vec<f64> = (0..nants)
+            .map(|ant| ref_phase + relative(ant, reference))
+            .collect();
+        let distance = |ant: usize, other: usize| {
+            let (pos1, pos2) = (self.positions[ant], self.positions[other]);
+            (pos1[0] - pos2[0]).hypot(pos1[1] - pos2[1])
+        };
+        // Prim's algorithm over the solved antennas
+        let mut in_tree = vec![false; nants];
+        in_tree[reference] = true;
+        let mut nearest: Vec<(f64, usize)> = (0..nants)
+            .map(|ant| (distance(ant, reference), reference))
+            .collect();
+        while let Some(ant) = (0..nants)
+            .filter(|&ant| solved[ant] && !in_tree[ant])
+            .min_by(|&ant1, &ant2| nearest[ant1].0.partial_cmp(&nearest[ant2].0).unwrap())
+        {
+            let parent = nearest[ant].1;
+            phases[ant] = phases[parent] + relative(ant, parent);
+            in_tree[ant] = true;
+            for (other, near) in nearest.iter_mut().enumerate() {
+                let dist = distance(other, ant);
+                if dist < near.0 {
+                    *near = (dist, ant);
+                }
+            }
+        }
+        phases
+    }
+
+    /// Fix the amplitude, phase and phase gradient degeneracies so the
+    /// antennas have zero mean log amplitude and no phase offset or gradient.
+    ///
+    /// The plane is fit to unwrapped phases, so gradients spanning more than
+    /// a turn across the array are removed whole.
+    fn project_degeneracies(
+        &self,
+        gains: &mut [Complex<f64>],
+        model: &mut [Complex<f64>],
+        solved: &[bool],
+    ) {
+        let weights: Vec<f64> = solved.iter().map(|&solved| solved as u8 as f64).collect();
+        let nsolved = weights.iter().sum::<f64>().max(1.0);
+        let amp = gains
+            .iter()
+            .zip(&weights)
+            .map(|(gain, weight)| gain.norm().ln() * weight)
+            .sum::<f64>()
+            / nsolved;
+        let phases = self.unwrapped_phases(gains, solved);
+        let [offset, east, north] = fit_plane(&phases, &self.positions, &weights);
+        for (gain, pos) in gains.iter_mut().zip(&self.positions) {
+            *gain *= Complex::from_polar((-amp).exp(), -(offset + east * pos[0] + north * pos[1]));
+        }
+        for (group, vis) in model.iter_mut().enumerate() {
+            let (ind1, ind2, _) =
+                self.baselines[self.baselines.iter().position(|bl| bl.2 == group).unwrap()];
+            let (pos1, pos2) = (self.positions[ind1], self.positions[ind2]);
+            let phase = east * (pos1[0] - pos2[0]) + north * (pos1[1] - pos2[1]);
+            *vis *= Complex::from_polar((2.0 * amp).exp(), phase);
+        }
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Gather the redundant baselines of the parallel-hand polarizations.
+    fn red_data(&self, opts: &RedCal, template: &UVCal) -> Result<RedData, String> {
+        let arrays = &self.meta_arrays;
+        let data = self
+            .data_array
+            .as_ref()
+            .ok_or("Data must be loaded for redundant calibration.")?;
+        let flags = self.flag_array.as_ref();
+        let redundancies = self.get_redundancies(opts.tol, true, true, false)?;
+        let conjugates: BTreeSet<u32> = redundancies.conjugates.iter().copied().collect();
+        let mut antpairs: HashMap<u32, (u32, u32)> = HashMap::new();
+        for blt in 0..arrays.baseline_array.len() {
+            antpairs
+                .entry(arrays.baseline_array[blt])
+                .or_insert((arrays.ant_1_array[blt], arrays.ant_2_array[blt]));
+        }
+        let groups: Vec<Vec<(u32, u32)>> = redundancies
+            .baseline_groups
+            .iter()
+            .filter(|group| group.len() >= opts.min_bls_per_group.max(1))
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|baseline| {
+                        let (ant1, ant2) = antpairs[baseline];
+                        match conjugates.contains(baseline) {
+                            true => (ant2, ant1),
+                            false => (ant1, ant2),
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        if groups.is_empty() {
+            return Err("No redundant baseline groups to calibrate.".to_string());
+        }
+
+        let ants: Vec<u32> = groups
+            .iter()
+            .flatten()
+            .flat_map(|&(ant1, ant2)| [ant1, ant2])
+            .collect::<BTreeSet<u32>>()
+            .into_iter()
+            .collect();
+        let (enu, antnums) = self.get_enu_antpos();
+        let positions = ants
+            .iter()
+            .map(|ant| {
+                let ind = antnums
+                    .iter()
+                    .position(|num| num == ant)
+                    .ok_or_else(|| format!("Antenna {} has no antenna position.", ant))?;
+                Ok([enu[[ind, 0]], enu[[ind, 1]]])
+            })
+            .collect::<Result<Vec<[f64; 2]>, String>>()?;
+        let ant_index: HashMap<u32, usize> = ants
+            .iter()
+            .enumerate()
+            .map(|(ind, &ant)| (ant, ind))
+            .collect();
+        let mut bl_index: HashMap<(u32, u32), (usize, bool)> = HashMap::new();
+        let mut baselines = Vec::new();
+        for (group, members) in groups.iter().enumerate() {
+            for &(ant1, ant2) in members {
+                bl_index.insert((ant1, ant2), (baselines.len(), false));
+                bl_index.insert((ant2, ant1), (baselines.len(), true));
+                baselines.push((ant_index[&ant1], ant_index[&ant2], group));
+            }
+        }
+
+        let pols: Vec<usize> = template
+            .jones_array
+            .iter()
+            .map(|&jones| {
+                arrays
+                    .polarization_array
+                    .iter()
+                    .position(|&pol| pol == jones)
+                    .unwrap()
+            })
+            .collect();
+        let shape = (
+            baselines.len(),
+            template.time_array.len(),
+            arrays.freq_array.len(),
+            pols.len(),
+        );
+        let mut vis = Array::<Complex<f64>, Ix4>::zeros(shape);
+        let mut weights = Array::<f64, Ix4>::zeros(shape);
+        for blt in 0..arrays.time_array.len() {
+            let antpair = (arrays.ant_1_array[blt], arrays.ant_2_array[blt]);
+            let (bl, conj) = match bl_index.get(&antpair) {
+                Some(&index) => index,
+                None => continue,
+            };
+            let time = template
+                .time_index(arrays.time_array[blt])
+                .ok_or("Data times do not match the calibration times.")?;
+            for freq in 0..shape.2 {
+                for (ind, &pol) in pols.iter().enumerate() {
+                    let value = data[[blt, freq, pol]];
+                    let value =
+                        Complex::new(value.re.to_f64().unwrap(), value.im.to_f64().unwrap());
+                    vis[[bl, time, freq, ind]] = match conj {
+                        true => value.conj(),
+                        false => value,
+                    };
+                    let flagged = flags.is_some_and(|flags| flags[[blt, freq, pol]]);
+                    weights[[bl, time, freq, ind]] = !flagged as u8 as f64;
+                }
+            }
+        }
+        Ok(RedData {
+            ants,
+            positions,
+            groups,
+            baselines,
+            vis,
+            weights,
+        })
+    }
+
+    /// A gain UVCal over the parallel-hand polarizations of the data.
+    fn red_template(&self) -> Result<UVCal, String> {
+        let mut template = UVCal::from_uvdata(self)?;
+        let jones: Vec<i8> = self
+            .meta_arrays
+            .polarization_array
+            .iter()
+            .copied()
+            .filter(|&pol| [-1, -2, -5, -6].contains(&pol))
+            .collect();
+        if jones.is_empty() {
+            return Err("Redundant calibration needs a parallel-hand polarization.".to_string());
+        }
+        template.jones_array = Array::from(jones);
+        template.cal_style = CalStyle::Redundant;
+        Ok(template)
+    }
+
+    /// Estimate per-antenna delays from the phase slopes between redundant
+    /// baselines (firstcal).
+    ///
+    /// Delays have their mean and east/north gradient removed, as these are
+    /// not constrained by redundancy.
+    pub fn redcal_firstcal(&self, opts: &RedCal) -> Result<UVCal, String> {
+        let mut uvcal = self.red_template()?;
+        let red = self.red_data(opts, &uvcal)?;
+        let freqs = self.meta_arrays.freq_array.to_vec();
+        let shape = (
+            red.nants(),
+            1,
+            uvcal.time_array.len(),
+            uvcal.jones_array.len(),
+        );
+        let mut delays = Array::<f64, Ix4>::zeros(shape);
+        let mut flags = Array::from_elem(shape, false);
+        for time in 0..shape.2 {
+            for pol in 0..shape.3 {
+                let (sol, flagged) = red.firstcal(time, pol, &freqs);
+                for ant in 0..shape.0 {
+                    delays[[ant, 0, time, pol]] = sol[ant];
+                    flags[[ant, 0, time, pol]] = flagged[ant];
+                }
+            }
+        }
+        uvcal.cal_type = CalType::Delay;
+        uvcal.ant_array = Array::from(red.ants.clone());
+        uvcal.gain_array = None;
+        uvcal.delay_array = Some(delays);
+        uvcal.flag_array = flags;
+        Ok(uvcal)
+    }
+
+    /// Redundantly calibrate the parallel-hand polarizations.
+    ///
+    /// Firstcal delays seed logcal, which `opts.solver` may refine with
+    /// lincal or omnical. The degenerate amplitude, phase and phase
+    /// gradient are then fixed to zero across the antennas.
+    pub fn redundantly_calibrate(&self, opts: &RedCal) -> Result<RedSolution, String> {
+        let firstcal = self.redcal_firstcal(opts)?;
+        let mut uvcal = self.red_template()?;
+        let red = self.red_data(opts, &uvcal)?;
+        let starts = firstcal.gains_at(&self.meta_arrays.freq_array)?;
+        let (nants, ngroups) = (red.nants(), red.ngroups());
+        let ntimes = uvcal.time_array.len();
+        let nfreqs = self.meta_arrays.freq_array.len();
+        let njones = uvcal.jones_array.len();
+
+        let mut gains = Array::<Complex<f64>, Ix4>::zeros((nants, nfreqs, ntimes, njones));
+        let mut flags = Array::from_elem((nants, nfreqs, ntimes, njones), false);
+        let mut model_vis = Array::<Complex<f64>, Ix4>::zeros((ngroups, ntimes, nfreqs, njones));
+        let mut chisq = Array::<f64, Ix3>::zeros((ntimes, nfreqs, njones));
+        for time in 0..ntimes {
+            for freq in 0..nfreqs {
+                for pol in 0..njones {
+                    let (vis, weights) = red.sample(time, freq, pol);
+                    let start: Vec<Complex<f64>> = (0..nants)
+                        .map(|ant| starts[[ant, freq, time, pol]])
+                        .collect();
+                    let (mut sol_gains, mut sol_model) = red.logcal(&vis, &weights, &start);
+                    match opts.solver {
+                        RedSolver::Logcal => {}
+                        RedSolver::Lincal => {
+                            red.lincal(&vis, &weights, &mut sol_gains, &mut sol_model, opts)
+                        }
+                        RedSolver::Omnical => {
+                            red.omnical(&vis, &weights, &mut sol_gains, &mut sol_model, opts)
+                        }
+                    }
+                    let solved: Vec<bool> = (0..nants)
+                        .map(|ant| {
+                            red.baselines
+                                .iter()
+                                .zip(&weights)
+                                .any(|(bl, &weight)| weight > 0.0 && (bl.0 == ant || bl.1 == ant))
+                        })
+                        .collect();
+                    red.project_degeneracies(&mut sol_gains, &mut sol_model, &solved);
+                    chisq[[time, freq, pol]] = red.chisq(&vis, &weights, &sol_gains, &sol_model);
+                    for ant in 0..nants {
+                        gains[[ant, freq, time, pol]] = sol_gains[ant];
+                        flags[[ant, freq, time, pol]] = !solved[ant];
+                    }
+                    for group in 0..ngroups {
+                        model_vis[[group, time, freq, pol]] = sol_model[group];
+                    }
+                }
+            }
+        }
+        uvcal.ant_array = Array::from(red.ants.clone());
+        uvcal.gain_array = Some(gains);
+        uvcal.flag_array = flags;
+        Ok(RedSolution {
+            gains: uvcal,
+            groups: red.groups,
+            model_vis,
+            chisq,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{least_squares, RedCal, RedData, RedSolver, Row};
+    use crate::test_data::read_unflagged;
+    use crate::{utils, UVData};
+    use ndarray::{Array, Ix2, Ix4};
+    use num_complex::Complex;
+    use std::{collections::HashMap, f64::consts::PI, str::FromStr};
+
+    /// The test data with its antennas on an east-west line 14 m apart and
+    /// visibilities g_i g_j^* y built from the per-antenna `gains`.
+    fn linear_array(gains: &HashMap<u32, Complex<f64>>) -> UVData<f64, f32> {
+        let mut uvd = read_unflagged();
+        let (lat, lon, alt) = uvd.telescope_location_latlonalt_degrees();
+        let location = uvd.meta.telescope_location;
+        let ants = uvd.meta_arrays.antenna_numbers.clone();
+        let east = |ant: u32| match ant {
+            11 => 42.0,
+            ant => 14.0 * ant as f64,
+        };
+        let enu = Array::<f64, Ix2>::from_shape_fn((ants.len(), 3), |(ind, axis)| match axis {
+            0 => east(ants[ind]),
+            _ => 0.0,
+        });
+        let mut ecef = utils::ecef_from_enu(&enu, lat, lon, alt);
+        for mut row in ecef.outer_iter_mut() {
+            for axis in 0..3 {
+                row[axis] -= location[axis];
+            }
+        }
+        uvd.meta_arrays.antenna_positions = ecef;
+
+        let arrays = uvd.meta_arrays.clone();
+        let data = uvd.data_array.as_mut().unwrap();
+        for blt in 0..arrays.time_array.len() {
+            let (ant1, ant2) = (arrays.ant_1_array[blt], arrays.ant_2_array[blt]);
+            let length = east(ant2) - east(ant1);
+            for freq in 0..4 {
+                for pol in 0..2 {
+                    let model = Complex::from_polar(
+                        1.0 + length.abs() / 100.0 + pol as f64,
+                        length / 20.0 * (1.0 + freq as f64 * 0.1),
+                    );
+                    data[[blt, freq, pol]] = gains[&ant1] * gains[&ant2].conj() * model;
+                }
+            }
+        }
+        uvd
+    }
+
+    fn true_gains() -> HashMap<u32, Complex<f64>> {
+        // zero mean log amplitude, phase offset and gradient
+        let positions = [0.0, 14.0, 28.0, 42.0];
+        let amps = [0.1, -0.2, 0.3, -0.2];
+        let phases = [0.2, -0.3, -0.1, 0.2];
+        let mean_pos = 21.0;
+        let slope = phases
+            .iter()
+            .zip(positions)
+            .map(|(phase, pos)| phase * (pos - mean_pos))
+            .sum::<f64>()
+            / positions
+                .iter()
+                .map(|pos| (pos - mean_pos).powi(2))
+                .sum::<f64>();
+        let mean_phase = phases.iter().sum::<f64>() / 4.0;
+        [0, 1, 2, 11]
+            .iter()
+            .enumerate()
+            .map(|(ind, &ant)| {
+                let phase = phases[ind] - mean_phase - slope * (positions[ind] - mean_pos);
+                (ant, Complex::from_polar(f64::exp(amps[ind]), phase))
+            })
+            .collect()
+    }
+
+    /// Redundant data of antennas at `positions`, with every cross baseline
+    /// grouped by its separation and visibilities g_i g_j^* y. Returns the
+    /// data and the model visibility of each group.
+    fn grid_red_data(
+        positions: &[[f64; 2]],
+        gains: &[Complex<f64>],
+    ) -> (RedData, Vec<Complex<f64>>) {
+        let nants = positions.len();
+        let mut separations: Vec<(i64, i64)> = Vec::new();
+        let mut groups: Vec<Vec<(u32, u32)>> = Vec::new();
+        let mut baselines = Vec::new();
+        for ind1 in 0..nants {
+            for ind2 in ind1 + 1..nants {
+                let separation = (
+                    (positions[ind2][0] - positions[ind1][0]).round() as i64,
+                    (positions[ind2][1] - positions[ind1][1]).round() as i64,
+                );
+                let group = match separations.iter().position(|&sep| sep == separation) {
+                    Some(group) => group,
+                    None => {
+                        separations.push(separation);
+                        groups.push(Vec::new());
+                        groups.len() - 1
+                    }
+                };
+                groups[group].push((ind1 as u32, ind2 as u32));
+                baselines.push((ind1, ind2, group));
+            }
+        }
+        let model: Vec<Complex<f64>> = (0..groups.len())
+            .map(|group| Complex::from_polar(1.0 + 0.1 * (group % 5) as f64, (group as f64).sin()))
+            .collect();
+        let vis = Array::<Complex<f64>, Ix4>::from_shape_fn(
+            (baselines.len(), 1, 1, 1),
+            |(bl, _, _, _)| {
+                let (ind1, ind2, group) = baselines[bl];
+                gains[ind1] * gains[ind2].conj() * model[group]
+            },
+        );
+        let red = RedData {
+            ants: (0..nants as u32).collect(),
+            positions: positions.to_vec(),
+            groups,
+            baselines,
+            weights: Array::ones(vis.dim()),
+            vis,
+        };
+        (red, model)
+    }
+
+    #[test]
+    fn solver_from_str() {
+        assert_eq!(RedSolver::from_str("Lincal").unwrap(), RedSolver::Lincal);
+        assert!(RedSolver::from_str("stefcal").is_err());
+    }
+
+    #[test]
+    fn degenerate_least_squares() {
+        // only x0 + x1 is constrained
+        let rows = vec![Row {
+            entries: vec![(0, 1.0), (1, 1.0)],
+            value: 2.0,
+            weight: 1.0,
+        }];
+        let solution = least_squares(&rows, 2);
+        assert_abs_diff_eq!(solution[0], 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(solution[1], 1.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn firstcal_delays() {
+        let gains: HashMap<u32, Complex<f64>> = [0, 1, 2, 11]
+            .iter()
+            .map(|&ant| (ant, Complex::new(1.0, 0.0)))
+            .collect();
+        let mut uvd = linear_array(&gains);
+        // antenna 1 has a 3 ns delay relative to the others
+        let freqs = uvd.meta_arrays.freq_array.clone();
+        let arrays = uvd.meta_arrays.clone();
+        let data = uvd.data_array.as_mut().unwrap();
+        for blt in 0..arrays.time_array.len() {
+            for freq in 0..4 {
+                let phase = |ant: u32| match ant {
+                    1 => -2.0 * std::f64::consts::PI * 3e-9 * freqs[freq],
+                    _ => 0.0,
+                };
+                let factor = Complex::from_polar(
+                    1.0,
+                    phase(arrays.ant_1_array[blt]) - phase(arrays.ant_2_array[blt]),
+                );
+                for pol in 0..2 {
+                    data[[blt, freq, pol]] *= factor;
+                }
+            }
+        }
+        let uvcal = uvd.redcal_firstcal(&RedCal::default()).unwrap();
+        assert_eq!(uvcal.ant_array.to_vec(), vec![0, 1, 2, 11]);
+        let delays = uvcal.delay_array.unwrap();
+        // the injected delays with their mean and gradient removed
+        let expected = [-1.2e-9, 2.1e-9, -0.6e-9, -0.3e-9];
+        for ant in 0..4 {
+            assert_abs_diff_eq!(delays[[ant, 0, 3, 1]], expected[ant], epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn recovers_gains() {
+        let truth = true_gains();
+        let uvd = linear_array(&truth);
+        for solver in [RedSolver::Logcal, RedSolver::Lincal, RedSolver::Omnical] {
+            let opts = RedCal {
+                solver,
+                ..Default::default()
+            };
+            let sol = uvd.redundantly_calibrate(&opts).expect("Cannot calibrate.");
+            assert_eq!(sol.groups.len(), 2);
+            let gains = sol.gains.gain_array.as_ref().unwrap();
+            for (ind, ant) in sol.gains.ant_array.iter().enumerate() {
+                let gain = gains[[ind, 2, 5, 1]];
+                assert_abs_diff_eq!(gain.re, truth[ant].re, epsilon = 1e-6);
+                assert_abs_diff_eq!(gain.im, truth[ant].im, epsilon = 1e-6);
+            }
+            assert!(sol.chisq.iter().all(|&chisq| chisq < 1e-10));
+            assert!(sol.gains.flag_array.iter().all(|&flag| !flag));
+        }
+    }
+
+    #[test]
+    fn flagged_antenna() {
+        let mut uvd = linear_array(&true_gains());
+        let arrays = uvd.meta_arrays.clone();
+        let flags = uvd.flag_array.as_mut().unwrap();
+        for blt in 0..arrays.time_array.len() {
+            if arrays.ant_1_array[blt] == 11 || arrays.ant_2_array[blt] == 11 {
+                flags[[blt, 0, 0]] = true;
+            }
+        }
+        let sol = uvd.redundantly_calibrate(&RedCal::default()).unwrap();
+        let flags = &sol.gains.flag_array;
+        for time in 0..20 {
+            assert!(flags[[3, 0, time, 0]]);
+            assert!(!flags[[3, 1, time, 0]]);
+            assert!(!flags[[0, 0, time, 0]]);
+        }
+        assert!(sol.chisq.iter().all(|chisq| chisq.is_finite()));
+    }
+
+    #[test]
+    fn degeneracies_of_wrapped_phases() {
+        // a phase gradient of 0.15 rad/m turns the gains several times over
+        // the 84 m line, on top of zero mean, zero gradient deviations
+        let positions: Vec<[f64; 2]> = (0..7).map(|ant| [14.0 * ant as f64, 0.0]).collect();
+        let amps = [0.1, -0.2, 0.05, 0.1, -0.1, 0.15, -0.1];
+        let deviations = [0.1, -0.1, -0.05, 0.1, -0.05, -0.1, 0.1];
+        let gains: Vec<Complex<f64>> = positions
+            .iter()
+            .enumerate()
+            .map(|(ant, pos)| {
+                Complex::from_polar(
+                    f64::exp(amps[ant] + 0.3),
+                    2.5 + 0.15 * pos[0] + deviations[ant],
+                )
+            })
+            .collect();
+        assert!(gains.iter().any(|gain| gain.arg() > PI / 2.0));
+        assert!(gains.iter().any(|gain| gain.arg() < -PI / 2.0));
+        let (red, model) = grid_red_data(&positions, &gains);
+
+        let (mut sol_gains, mut sol_model) = (gains.clone(), model);
+        red.project_degeneracies(&mut sol_gains, &mut sol_model, &[true; 7]);
+        let (vis, weights) = red.sample(0, 0, 0);
+        assert!(red.chisq(&vis, &weights, &sol_gains, &sol_model) < 1e-20);
+        // the deviations keep a residual offset and gradient, which are removed
+        let fit = super::fit_plane(&deviations, &positions, &[1.0; 7]);
+        for (ant, gain) in sol_gains.iter().enumerate() {
+            let phase = deviations[ant] - fit[0] - fit[1] * positions[ant][0];
+            assert_abs_diff_eq!(gain.norm().ln(), amps[ant], epsilon = 1e-9);
+            assert_abs_diff_eq!(gain.arg(), phase, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn large_array() {
+        // a 7 x 7 grid of 49 antennas 14 m apart, 1176 baselines in 84 groups
+        let positions: Vec<[f64; 2]> = (0..49)
+            .map(|ant| [14.0 * (ant % 7) as f64, 14.0 * (ant / 7) as f64])
+            .collect();
+        let gains: Vec<Complex<f64>> = (0..49)
+            .map(|ant| {
+                let ant = ant as f64;
+                Complex::from_polar(f64::exp(0.1 * (1.3 * ant).sin()), 0.3 * (0.7 * ant).cos())
+            })
+            .collect();
+        let (red, model) = grid_red_data(&positions, &gains);
+        assert_eq!(red.ngroups(), 84);
+        let (mut true_gains, mut true_model) = (gains, model);
+        let solved = [true; 49];
+        red.project_degeneracies(&mut true_gains, &mut true_model, &solved);
+
+        let (vis, weights) = red.sample(0, 0, 0);
+        for solver in [RedSolver::Lincal, RedSolver::Omnical] {
+            let opts = RedCal {
+                solver,
+                ..Default::default()
+            };
+            let start = vec![Complex::new(1.0, 0.0); 49];
+            let (mut sol_gains, mut sol_model) = red.logcal(&vis, &weights, &start);
+            match solver {
+                RedSolver::Lincal => {
+                    red.lincal(&vis, &weights, &mut sol_gains, &mut sol_model, &opts)
+                }
+                _ => red.omnical(&vis, &weights, &mut sol_gains, &mut sol_model, &opts),
+            }
+            red.project_degeneracies(&mut sol_gains, &mut sol_model, &solved);
+            assert!(red.chisq(&vis, &weights, &sol_gains, &sol_model) < 1e-10);
+            for (gain, truth) in sol_gains.iter().zip(&true_gains) {
+                assert_abs_diff_eq!(gain.re, truth.re, epsilon = 1e-6);
+                assert_abs_diff_eq!(gain.im, truth.im, epsilon = 1e-6);
+            }
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use approx::AbsDiffEq;
+use ndarray::Array;
+use num_traits::Float;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    str::FromStr,
+};
+
+use super::utils;
+use super::UVData;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RedundancyMethod {
+    Select,
+    Average,
+}
+
+impl FromStr for RedundancyMethod {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<RedundancyMethod, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "select" => Ok(RedundancyMethod::Select),
+            "average" => Ok(RedundancyMethod::Average),
+            other => Err(format!("Unknown redundancy compression method: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for RedundancyMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+fn swap_baseline(baseline: u32) -> u32 {
+    let (ant1, ant2) = utils::baseline_to_antnums(&Array::from_elem(1, baseline), false);
+    utils::antnums_to_baseline(&ant2, &ant1, false)[0]
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Project an ENU offset into the uvw frame of the phase center of `blt`.
+    pub(crate) fn project_enu(&self, blt: usize, enu: [f64; 3]) -> [f64; 3] {
+        let lat = self.telescope_location_latlonalt().0;
+        let lst = self.meta_arrays.lst_array[blt];
+        match self.phase_center_of(blt) {
+            Some((ra, dec)) => utils::uvw_from_enu(enu, lst - ra, dec, lat),
+            None => enu,
+        }
+    }
+
+    /// Reduce the data to one baseline per redundant group.
+    ///
+    /// Baselines are grouped by their uvws to within `tol` meters (conjugating
+    /// where needed) and each group is represented by its first baseline.
+    /// With `RedundancyMethod::Average` the group's visibilities are averaged
+    /// at each time, otherwise only the representative baseline is kept.
+    pub fn compress_by_redundancy(
+        &mut self,
+        method: RedundancyMethod,
+        tol: f64,
+    ) -> Result<(), String> {
+        let groups = self.get_redundancies(tol, false, true, true)?;
+        self.conjugate_bls(&groups.conjugates)?;
+        // conjugated baselines have new baseline numbers
+        let baseline_groups: Vec<Vec<u32>> = groups
+            .baseline_groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|&bl| match groups.conjugates.contains(&bl) {
+                        true => swap_baseline(bl),
+                        false => bl,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        match method {
+            RedundancyMethod::Select => {
+                let keep: Vec<u32> = baseline_groups.iter().map(|group| group[0]).collect();
+                let inds: Vec<usize> = self
+                    .meta_arrays
+                    .baseline_array
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, bl)| keep.contains(bl))
+                    .map(|(blt, _)| blt)
+                    .collect();
+                self.take_blts(&inds);
+            }
+            RedundancyMethod::Average => {
+                // (position in group, group) of every baseline
+                let mut group_of: HashMap<u32, (usize, usize)> = HashMap::new();
+                for (group_ind, group) in baseline_groups.iter().enumerate() {
+                    for (pos, &bl) in group.iter().enumerate() {
+                        group_of.insert(bl, (pos, group_ind));
+                    }
+                }
+                let mut blocks: BTreeMap<(usize, u64), Vec<(usize, usize)>> = BTreeMap::new();
+                let arrays = &self.meta_arrays;
+                for (blt, bl) in arrays.baseline_array.iter().enumerate() {
+                    let (pos, group_ind) = group_of[bl];
+                    blocks
+                        .entry((group_ind, arrays.time_array[blt].to_bits()))
+                        .or_default()
+                        .push((pos, blt));
+                }
+                // order each block so its earliest group member carries the metadata
+                let blocks: Vec<Vec<usize>> = blocks
+                    .into_values()
+                    .map(|mut block| {
+                        block.sort_unstable();
+                        block.into_iter().map(|(_, blt)| blt).collect()
+                    })
+                    .collect();
+                // nsamples of the group are summed
+                let weights = Array::ones(self.meta.nblts as usize);
+                self.average_blocks(&blocks, &weights, None);
+            }
+        }
+        self.sort_blts_by_time();
+        Ok(())
+    }
+
+    /// Expand compressed data to every baseline of each redundant group.
+    ///
+    /// Groups are found from the antenna positions of every antenna in the
+    /// telescope, so baselines of antennas without data are filled in too.
+    /// Copies take the visibilities of the baseline present in the data,
+    /// with uvws shifted by the difference in antenna positions. All
+    /// baselines are conjugated to the ant1 <= ant2 convention afterwards.
+    pub fn inflate_by_redundancy(&mut self, tol: f64) -> Result<(), String> {
+        let (enu, antnums) = self.get_enu_antpos();
+        let groups = utils::get_antenna_redundancies(&antnums, &enu, tol, true);
+        let ant_index: HashMap<u32, usize> = antnums
+            .iter()
+            .enumerate()
+            .map(|(ind, &num)| (num, ind))
+            .collect();
+        let baseline_vec = |ant1: u32, ant2: u32| -> [f64; 3] {
+            let (ind1, ind2) = (ant_index[&ant1], ant_index[&ant2]);
+            [
+                enu[[ind2, 0]] - enu[[ind1, 0]],
+                enu[[ind2, 1]] - enu[[ind1, 1]],
+                enu[[ind2, 2]] - enu[[ind1, 2]],
+            ]
+        };
+
+        let blt_index = self.blt_index();
+        let blt_groups = blt_index.antpairs();
+        let nblts = self.meta.nblts as usize;
+        let mut sources: Vec<usize> = (0..nblts).collect();
+        let mut antpairs: Vec<(u32, u32)> = Vec::new();
+        let mut uvws: Vec<[f64; 3]> = Vec::new();
+        for group in groups.baseline_groups.iter() {
+            let (ant1s, ant2s) = utils::baseline_to_antnums(&Array::from_vec(group.clone()), false);
+            let pairs: Vec<(u32, u32)> = ant1s.iter().cloned().zip(ant2s.iter().cloned()).collect();
+            let present = |&(ant1, ant2): &(u32, u32)| {
+                blt_groups.contains_key(&(ant1, ant2)) || blt_groups.contains_key(&(ant2, ant1))
+            };
+            // the first pair of the group in the data, and whether it is stored flipped
+            let rep = pairs.iter().find_map(|&(ant1, ant2)| {
+                match (
+                    blt_groups.contains_key(&(ant1, ant2)),
+                    blt_groups.contains_key(&(ant2, ant1)),
+                ) {
+                    (true, _) => Some(((ant1, ant2), false)),
+                    (false, true) => Some(((ant2, ant1), true)),
+                    (false, false) => None,
+                }
+            });
+            let ((rep1, rep2), flipped) = match rep {
+                Some(rep) => rep,
+                None => continue,
+            };
+            let rep_vec = baseline_vec(rep1, rep2);
+            for &(ant1, ant2) in pairs.iter().filter(|pair| !present(pair)) {
+                // orient the copy like the representative so no conjugation is needed
+                let (ant1, ant2) = match flipped {
+                    true => (ant2, ant1),
+                    false => (ant1, ant2),
+                };
+                let new_vec = baseline_vec(ant1, ant2);
+                let delta = [
+                    new_vec[0] - rep_vec[0],
+                    new_vec[1] - rep_vec[1],
+                    new_vec[2] - rep_vec[2],
+                ];
+                for &blt in blt_groups[&(rep1, rep2)].iter() {
+                    let shift = self.project_enu(blt, delta);
+                    let uvw = self.meta_arrays.uvw_array.row(blt);
+                    sources.push(blt);
+                    antpairs.push((ant1, ant2));
+                    uvws.push([uvw[0] + shift[0], uvw[1] + shift[1], uvw[2] + shift[2]]);
+                }
+            }
+        }
+        if sources.len() == nblts {
+            return Ok(());
+        }
+
+        self.take_blts(&sources);
+        let arrays = &mut self.meta_arrays;
+        for (ind, (&(ant1, ant2), uvw)) in antpairs.iter().zip(uvws.iter()).enumerate() {
+            arrays.ant_1_array[nblts + ind] = ant1;
+            arrays.ant_2_array[nblts + ind] = ant2;
+            for (axis, &x) in uvw.iter().enumerate() {
+                arrays.uvw_array[[nblts + ind, axis]] = x;
+            }
+        }
+        arrays.baseline_array =
+            utils::antnums_to_baseline(&arrays.ant_1_array, &arrays.ant_2_array, false);
+        let flip: Vec<u32> = arrays
+            .baseline_array
+            .iter()
+            .zip(arrays.ant_1_array.iter().zip(arrays.ant_2_array.iter()))
+            .filter(|(_, (ant1, ant2))| ant1 > ant2)
+            .map(|(&bl, _)| bl)
+            .collect::<BTreeSet<u32>>()
+            .into_iter()
+            .collect();
+        self.conjugate_bls(&flip)?;
+        self.update_blt_counts();
+        self.sort_blts_by_time();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RedundancyMethod, UVData};
+    use crate::test_data::read_drift;
+    use crate::utils::antnums_to_baseline;
+    use ndarray::array;
+    use std::str::FromStr;
+
+    fn blt_of(uvd: &UVData<f64, f32>, ant1: u32, ant2: u32) -> usize {
+        uvd.meta_arrays
+            .ant_1_array
+            .iter()
+            .zip(uvd.meta_arrays.ant_2_array.iter())
+            .position(|(&a1, &a2)| a1 == ant1 && a2 == ant2)
+            .unwrap()
+    }
+
+    #[test]
+    fn method_from_str() {
+        assert_eq!(
+            RedundancyMethod::from_str(" Average").unwrap(),
+            RedundancyMethod::Average
+        );
+        assert!(RedundancyMethod::from_str("median").is_err());
+    }
+
+    #[test]
+    fn compress_select() {
+        let mut uvd = read_drift();
+        uvd.compress_by_redundancy(RedundancyMethod::Select, 1.0)
+            .expect("Cannot compress.");
+        // the four autos and (0, 1) ~ (2, 1)* collapse
+        assert_eq!(uvd.meta.nbls, 6);
+        assert_eq!(uvd.meta.nblts, 120);
+        assert_eq!(uvd.meta.ntimes, 20);
+    }
+
+    #[test]
+    fn compress_average() {
+        let uvd = read_drift();
+        let data = uvd.data_array.as_ref().unwrap();
+        let expected =
+            (data[[blt_of(&uvd, 0, 1), 2, 1]] + data[[blt_of(&uvd, 2, 1), 2, 1]].conj()) / 2.0;
+
+        let mut avg = uvd.clone();
+        avg.compress_by_redundancy(RedundancyMethod::Average, 1.0)
+            .expect("Cannot compress.");
+        assert_eq!(avg.meta.nbls, 6);
+        let blt = blt_of(&avg, 0, 1);
+        let avg_data = avg.data_array.as_ref().unwrap();
+        assert_abs_diff_eq!(avg_data[[blt, 2, 1]].re, expected.re, epsilon = 1e-8);
+        assert_abs_diff_eq!(avg_data[[blt, 2, 1]].im, expected.im, epsilon = 1e-8);
+        assert_abs_diff_eq!(avg.nsample_array.as_ref().unwrap()[[blt, 2, 1]], 2.0);
+    }
+
+    #[test]
+    fn inflate_after_compress() {
+        let uvd = read_drift();
+        let mut inflated = uvd.clone();
+        inflated
+            .compress_by_redundancy(RedundancyMethod::Select, 1.0)
+            .expect("Cannot compress.");
+        inflated
+            .inflate_by_redundancy(1.0)
+            .expect("Cannot inflate.");
+        assert!(inflated.meta.nbls > uvd.meta.nbls);
+        assert!(inflated
+            .meta_arrays
+            .ant_1_array
+            .iter()
+            .zip(inflated.meta_arrays.ant_2_array.iter())
+            .all(|(a1, a2)| a1 <= a2));
+
+        // (1, 2) is filled from (0, 1), and (2, 1) in the input is its conjugate
+        let blt = blt_of(&inflated, 1, 2);
+        let inflated_data = inflated.data_array.as_ref().unwrap();
+        let data = uvd.data_array.as_ref().unwrap();
+        let orig = data[[blt_of(&uvd, 0, 1), 3, 0]];
+        assert_abs_diff_eq!(inflated_data[[blt, 3, 0]].re, orig.re);
+        assert_abs_diff_eq!(inflated_data[[blt, 3, 0]].im, orig.im);
+
+        let uvw = inflated.meta_arrays.uvw_array.row(blt);
+        let orig_uvw = uvd.meta_arrays.uvw_array.row(blt_of(&uvd, 2, 1));
+        assert!(uvw.abs_diff_eq(&orig_uvw.mapv(|x| -x), 1e-6));
+
+        let bls = antnums_to_baseline(&array![1u32], &array![2u32], false);
+        assert!(inflated
+            .meta_arrays
+            .baseline_array
+            .iter()
+            .any(|bl| *bl == bls[0]));
+    }
+}
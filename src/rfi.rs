@@ -0,0 +1,758 @@
+use approx::AbsDiffEq;
+use ndarray::{s, Array, Axis, Ix2, Ix3};
+use num_complex::Complex;
+use num_traits::Float;
+use std::str::FromStr;
+
+use super::utils;
+use super::UVData;
+
+/// How the polarizations of a baseline are combined when flagging.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PolCombination {
+    /// Flag every polarization on its own.
+    Separate,
+    /// Flag every polarization on its own, then flag a sample in all
+    /// polarizations if any of them is flagged.
+    Any,
+    /// Flag the root sum of squares of the amplitudes of all polarizations.
+    Rss,
+}
+
+impl FromStr for PolCombination {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<PolCombination, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "separate" => Ok(PolCombination::Separate),
+            "any" | "or" => Ok(PolCombination::Any),
+            "rss" => Ok(PolCombination::Rss),
+            other => Err(format!("Unknown polarization combination: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for PolCombination {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Settings of the SumThreshold flagger.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SumThreshold {
+    /// Threshold of single samples in units of the robust standard deviation.
+    pub threshold: f64,
+    /// Longest window in samples; window lengths double from one.
+    pub max_window: usize,
+    /// Windows of length M use the threshold divided by rho^log2(M).
+    pub rho: f64,
+    /// Number of passes. Each pass re-estimates the background from the
+    /// unflagged samples and halves the threshold until it reaches `threshold`.
+    pub iterations: usize,
+    /// Aggressiveness of the scale-invariant rank dilation; zero disables it.
+    pub eta: f64,
+    pub pol_combination: PolCombination,
+}
+
+/// Which visibilities `flag_xrfi` computes z-scores from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum XrfiSource {
+    /// Every baseline flags itself.
+    Baselines,
+    /// The combined autocorrelations flag every baseline.
+    Autos,
+}
+
+impl FromStr for XrfiSource {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<XrfiSource, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "baselines" => Ok(XrfiSource::Baselines),
+            "autos" => Ok(XrfiSource::Autos),
+            other => Err(format!("Unknown xrfi source: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for XrfiSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Settings of the median filter flagger.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Xrfi {
+    /// Half-widths (times, channels) of the median filter window.
+    pub kernel: (usize, usize),
+    /// Samples whose |z| reaches this are flagged.
+    pub threshold: f64,
+    /// Flags grow into neighbours whose |z| reaches this.
+    pub watershed_threshold: f64,
+    pub source: XrfiSource,
+    pub pol_combination: PolCombination,
+}
+
+impl Default for Xrfi {
+    fn default() -> Self {
+        Xrfi {
+            kernel: (8, 8),
+            threshold: 6.0,
+            watershed_threshold: 2.0,
+            source: XrfiSource::Baselines,
+            pol_combination: PolCombination::Separate,
+        }
+    }
+}
+
+impl Default for SumThreshold {
+    fn default() -> Self {
+        SumThreshold {
+            threshold: 6.0,
+            max_window: 64,
+            rho: 1.5,
+            iterations: 3,
+            eta: 0.2,
+            pol_combination: PolCombination::Separate,
+        }
+    }
+}
+
+/// Flag windows of one line whose summed values exceed `window` times `chi`.
+///
+/// Flagged samples count as `chi` so they neither trigger nor mask windows.
+fn sumthreshold_line(values: &[f64], flags: &[bool], chi: f64, window: usize) -> Vec<bool> {
+    let mut new_flags = flags.to_vec();
+    if window > values.len() {
+        return new_flags;
+    }
+    let value = |ind: usize| if flags[ind] { chi } else { values[ind] };
+    let mut sum: f64 = (0..window).map(value).sum();
+    for start in 0..=values.len() - window {
+        if start > 0 {
+            sum += value(start + window - 1) - value(start - 1);
+        }
+        if sum > chi * window as f64 {
+            new_flags[start..start + window].fill(true);
+        }
+    }
+    new_flags
+}
+
+/// Run SumThreshold on excursions of either sign along both axes of a
+/// waterfall of residuals.
+fn sumthreshold(
+    values: &Array<f64, Ix2>,
+    flags: &mut Array<bool, Ix2>,
+    chi1: f64,
+    options: &SumThreshold,
+) {
+    let mut window = 1;
+    while window <= options.max_window {
+        let chi = chi1 / options.rho.powf((window as f64).log2());
+        for axis in [Axis(0), Axis(1)] {
+            for (line, mut line_flags) in values.lanes(axis).into_iter().zip(flags.lanes_mut(axis))
+            {
+                let old: Vec<bool> = line_flags.to_vec();
+                let positive = sumthreshold_line(&line.to_vec(), &old, chi, window);
+                let negative = sumthreshold_line(&line.mapv(|x| -x).to_vec(), &old, chi, window);
+                line_flags
+                    .iter_mut()
+                    .zip(positive.into_iter().zip(negative))
+                    .for_each(|(flag, (pos, neg))| *flag = pos || neg);
+            }
+        }
+        window *= 2;
+    }
+}
+
+/// Scale-invariant rank dilation of one line.
+///
+/// A sample is flagged if it lies in any window where the fraction of
+/// unflagged samples is at most `eta`.
+fn sir_line(flags: &[bool], eta: f64) -> Vec<bool> {
+    // prefix sums of (unflagged - eta): a window [a, b) qualifies when
+    // cumsum[b] <= cumsum[a]
+    let mut cumsum = vec![0.0; flags.len() + 1];
+    for (ind, &flag) in flags.iter().enumerate() {
+        cumsum[ind + 1] = cumsum[ind] + if flag { 0.0 } else { 1.0 } - eta;
+    }
+    let mut prefix_max = cumsum.clone();
+    for ind in 1..prefix_max.len() {
+        prefix_max[ind] = prefix_max[ind].max(prefix_max[ind - 1]);
+    }
+    let mut suffix_min = cumsum;
+    for ind in (0..suffix_min.len() - 1).rev() {
+        suffix_min[ind] = suffix_min[ind].min(suffix_min[ind + 1]);
+    }
+    (0..flags.len())
+        .map(|ind| flags[ind] || suffix_min[ind + 1] <= prefix_max[ind])
+        .collect()
+}
+
+fn sir_dilate(flags: &mut Array<bool, Ix2>, eta: f64) {
+    for axis in [Axis(0), Axis(1)] {
+        for mut line in flags.lanes_mut(axis) {
+            let new = sir_line(&line.to_vec(), eta);
+            line.iter_mut().zip(new).for_each(|(flag, new)| *flag = new);
+        }
+    }
+}
+
+// channels in the running median which smooths the bandpass
+const BANDPASS_WINDOW: usize = 9;
+
+fn unflagged_median<'a>(
+    values: impl Iterator<Item = &'a f64>,
+    flags: impl Iterator<Item = &'a bool>,
+) -> f64 {
+    utils::median(
+        values
+            .zip(flags)
+            .filter(|(_, &flag)| !flag)
+            .map(|(&value, _)| value)
+            .collect(),
+    )
+}
+
+/// Residuals after removing a smooth bandpass, then the median over
+/// frequency of every time.
+///
+/// The bandpass is a running median over channels of the median spectrum,
+/// so RFI confined to a few channels stays in the residuals.
+fn residuals(amps: &Array<f64, Ix2>, flags: &Array<bool, Ix2>) -> Array<f64, Ix2> {
+    let spectrum: Vec<f64> = amps
+        .columns()
+        .into_iter()
+        .zip(flags.columns())
+        .map(|(amps, flags)| unflagged_median(amps.iter(), flags.iter()))
+        .collect();
+    let nfreqs = spectrum.len();
+    let half = BANDPASS_WINDOW / 2;
+    let bandpass: Vec<f64> = (0..nfreqs)
+        .map(|freq| {
+            let window = &spectrum[freq.saturating_sub(half)..(freq + half + 1).min(nfreqs)];
+            utils::median(window.iter().copied().filter(|x| x.is_finite()).collect())
+        })
+        .collect();
+
+    let mut residuals = amps.clone();
+    for (mut row, row_flags) in residuals.rows_mut().into_iter().zip(flags.rows()) {
+        row.iter_mut()
+            .zip(bandpass.iter())
+            .filter(|(_, level)| level.is_finite())
+            .for_each(|(amp, level)| *amp -= level);
+        let level = unflagged_median(row.iter(), row_flags.iter());
+        if level.is_finite() {
+            row -= level;
+        }
+    }
+    residuals
+}
+
+/// Flag one waterfall of amplitudes with shape (Ntimes, Nfreqs).
+fn flag_waterfall(
+    amps: &Array<f64, Ix2>,
+    flags: &Array<bool, Ix2>,
+    options: &SumThreshold,
+) -> Array<bool, Ix2> {
+    let mut new_flags = flags.clone();
+    for iteration in 0..options.iterations {
+        let residuals = residuals(amps, &new_flags);
+        let unflagged: Vec<f64> = residuals
+            .iter()
+            .zip(new_flags.iter())
+            .filter(|(_, &flag)| !flag)
+            .map(|(&value, _)| value)
+            .collect();
+        let center = utils::median(unflagged.clone());
+        let sigma = 1.4826 * utils::median(unflagged.iter().map(|x| (x - center).abs()).collect());
+        if !sigma.is_finite() || sigma <= 0.0 {
+            break;
+        }
+        let scale = 2.0.powi((options.iterations - 1 - iteration) as i32);
+        let chi1 = options.threshold * scale * sigma;
+        sumthreshold(
+            &residuals.mapv(|x| x - center),
+            &mut new_flags,
+            chi1,
+            options,
+        );
+    }
+    if options.eta > 0.0 {
+        sir_dilate(&mut new_flags, options.eta);
+    }
+    new_flags
+}
+
+/// Half-open range of the window of half-width `half` around `center`.
+fn window(center: usize, half: usize, len: usize) -> std::ops::Range<usize> {
+    center.saturating_sub(half)..(center + half + 1).min(len)
+}
+
+/// Robust modified z-scores of a waterfall with shape (Ntimes, Nfreqs).
+///
+/// Samples are compared with the median of the unflagged samples in a
+/// window of half-widths `kernel` (times, channels) around them and scaled
+/// by 1.4826 times the median absolute deviation in the same window.
+/// Flagged samples have NaN z-scores.
+fn modified_zscores(
+    amps: &Array<f64, Ix2>,
+    flags: &Array<bool, Ix2>,
+    kernel: (usize, usize),
+) -> Array<f64, Ix2> {
+    let (ntimes, nfreqs) = amps.dim();
+    let window_median = |values: &Array<f64, Ix2>, time: usize, freq: usize| {
+        let times = window(time, kernel.0, ntimes);
+        let freqs = window(freq, kernel.1, nfreqs);
+        unflagged_median(
+            values.slice(s![times.clone(), freqs.clone()]).iter(),
+            flags.slice(s![times, freqs]).iter(),
+        )
+    };
+    let medians = Array::from_shape_fn((ntimes, nfreqs), |(time, freq)| {
+        window_median(amps, time, freq)
+    });
+    let deviations = (amps - &medians).mapv(f64::abs);
+    Array::from_shape_fn((ntimes, nfreqs), |(time, freq)| {
+        if flags[[time, freq]] {
+            return f64::NAN;
+        }
+        let residual = amps[[time, freq]] - medians[[time, freq]];
+        let zscore = residual / (1.4826 * window_median(&deviations, time, freq));
+        match zscore.is_nan() {
+            true => 0.0,
+            false => zscore,
+        }
+    })
+}
+
+/// Flag samples whose |z| reaches `threshold`, then grow all flags into
+/// neighbouring samples whose |z| reaches `watershed_threshold`.
+fn watershed_flags(
+    zscores: &Array<f64, Ix2>,
+    flags: &Array<bool, Ix2>,
+    threshold: f64,
+    watershed_threshold: f64,
+) -> Array<bool, Ix2> {
+    let (ntimes, nfreqs) = zscores.dim();
+    let mut new_flags = flags.clone();
+    new_flags.zip_mut_with(zscores, |flag, &z| *flag |= z.abs() >= threshold);
+    let mut to_grow: Vec<(usize, usize)> = new_flags
+        .indexed_iter()
+        .filter(|(_, &flag)| flag)
+        .map(|(ind, _)| ind)
+        .collect();
+    while let Some((time, freq)) = to_grow.pop() {
+        let neighbours = [
+            (time.wrapping_sub(1), freq),
+            (time + 1, freq),
+            (time, freq.wrapping_sub(1)),
+            (time, freq + 1),
+        ];
+        for (time, freq) in neighbours {
+            if time < ntimes
+                && freq < nfreqs
+                && !new_flags[[time, freq]]
+                && zscores[[time, freq]].abs() >= watershed_threshold
+            {
+                new_flags[[time, freq]] = true;
+                to_grow.push((time, freq));
+            }
+        }
+    }
+    new_flags
+}
+
+/// Amplitude and flag waterfalls of `blts` (sorted by time) for each group
+/// of polarizations flagged together.
+fn pol_waterfalls<T: Float>(
+    data: &Array<Complex<T>, Ix3>,
+    flags: &Array<bool, Ix3>,
+    blts: &[usize],
+    pol_combination: PolCombination,
+) -> Vec<(Array<f64, Ix2>, Array<bool, Ix2>)> {
+    let (_, nfreqs, npols) = data.dim();
+    let shape = (blts.len(), nfreqs);
+    let amps = |pol: usize| {
+        Array::from_shape_fn(shape, |(time, freq)| {
+            data[[blts[time], freq, pol]].norm().to_f64().unwrap()
+        })
+    };
+    match pol_combination {
+        PolCombination::Separate | PolCombination::Any => (0..npols)
+            .map(|pol| {
+                let pol_flags =
+                    Array::from_shape_fn(shape, |(time, freq)| flags[[blts[time], freq, pol]]);
+                (amps(pol), pol_flags)
+            })
+            .collect(),
+        PolCombination::Rss => {
+            let rss = (0..npols)
+                .map(|pol| amps(pol).mapv(|amp| amp * amp))
+                .fold(Array::zeros(shape), |sum, power| sum + power)
+                .mapv(f64::sqrt);
+            let any_flags = Array::from_shape_fn(shape, |(time, freq)| {
+                (0..npols).any(|pol| flags[[blts[time], freq, pol]])
+            });
+            vec![(rss, any_flags)]
+        }
+    }
+}
+
+/// Flags of every polarization from the flags of the groups of `pol_waterfalls`.
+fn pol_flags(
+    group_flags: Vec<Array<bool, Ix2>>,
+    npols: usize,
+    pol_combination: PolCombination,
+) -> Vec<Array<bool, Ix2>> {
+    match pol_combination {
+        PolCombination::Separate => group_flags,
+        PolCombination::Any | PolCombination::Rss => {
+            let mut combined = group_flags[0].clone();
+            for flags in group_flags.iter().skip(1) {
+                combined.zip_mut_with(flags, |flag, &new| *flag |= new);
+            }
+            vec![combined; npols]
+        }
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Flag every antenna pair's (Ntimes, Nfreqs) amplitude waterfalls with
+    /// `flagger`, which receives the amplitudes and existing flags and
+    /// returns the new flags.
+    fn flag_antpair_waterfalls<F>(
+        &mut self,
+        pol_combination: PolCombination,
+        flagger: F,
+    ) -> Result<(), String>
+    where
+        F: Fn(&Array<f64, Ix2>, &Array<bool, Ix2>) -> Array<bool, Ix2>,
+    {
+        let data = self
+            .data_array
+            .as_ref()
+            .ok_or("Data must be loaded to flag RFI.")?;
+        let shape = data.dim();
+        let old_flags = self
+            .flag_array
+            .take()
+            .unwrap_or_else(|| Array::from_elem(shape, false));
+        let mut flags = old_flags.clone();
+
+        let times = &self.meta_arrays.time_array;
+        for (ant1, ant2) in self.antpairs().collect::<Vec<_>>() {
+            let mut blts = self.antpair_blts(ant1, ant2);
+            blts.sort_by(|&blt1, &blt2| times[blt1].total_cmp(&times[blt2]));
+            let group_flags = pol_waterfalls(data, &old_flags, &blts, pol_combination)
+                .iter()
+                .map(|(amps, group_flags)| flagger(amps, group_flags))
+                .collect();
+            for (pol, pol_flags) in pol_flags(group_flags, shape.2, pol_combination)
+                .iter()
+                .enumerate()
+            {
+                for ((time, freq), &flag) in pol_flags.indexed_iter() {
+                    flags[[blts[time], freq, pol]] |= flag;
+                }
+            }
+        }
+        self.flag_array = Some(flags);
+        Ok(())
+    }
+
+    /// Flag RFI with the SumThreshold algorithm followed by scale-invariant
+    /// rank dilation.
+    ///
+    /// Every baseline's (Ntimes, Nfreqs) waterfall of visibility amplitudes
+    /// is flagged on its own, combining polarizations following
+    /// `options.pol_combination`. Existing flags are kept and excluded from
+    /// the statistics. A flag array is created if none is loaded.
+    pub fn flag_sumthreshold(&mut self, options: &SumThreshold) -> Result<(), String> {
+        self.flag_antpair_waterfalls(options.pol_combination, |amps, flags| {
+            flag_waterfall(amps, flags, options)
+        })
+    }
+
+    /// Flag RFI by thresholding robust modified z-scores of the visibility
+    /// amplitudes, then growing the flags with a watershed.
+    ///
+    /// With `XrfiSource::Baselines` every baseline is flagged from its own
+    /// z-scores. With `XrfiSource::Autos` the z-scores of the
+    /// autocorrelations are combined as sum(z) / sqrt(Nautos) for every time
+    /// and frequency, and the resulting flags apply to all baselines.
+    /// Existing flags are kept and excluded from the statistics.
+    pub fn flag_xrfi(&mut self, options: &Xrfi) -> Result<(), String> {
+        let flagger = |zscores: &Array<f64, Ix2>, flags: &Array<bool, Ix2>| {
+            watershed_flags(
+                zscores,
+                flags,
+                options.threshold,
+                options.watershed_threshold,
+            )
+        };
+        if options.source == XrfiSource::Baselines {
+            return self.flag_antpair_waterfalls(options.pol_combination, |amps, flags| {
+                flagger(&modified_zscores(amps, flags, options.kernel), flags)
+            });
+        }
+
+        let data = self
+            .data_array
+            .as_ref()
+            .ok_or("Data must be loaded to flag RFI.")?;
+        let shape = data.dim();
+        let (nblts, nfreqs, npols) = shape;
+        let old_flags = self
+            .flag_array
+            .take()
+            .unwrap_or_else(|| Array::from_elem(shape, false));
+        let autos: Vec<(u32, u32)> = self
+            .antpairs()
+            .filter(|(ant1, ant2)| ant1 == ant2)
+            .collect();
+        if autos.is_empty() {
+            self.flag_array = Some(old_flags);
+            return Err("There are no autocorrelations to flag from.".to_string());
+        }
+
+        let time_array = &self.meta_arrays.time_array;
+        let mut times = time_array.to_vec();
+        times.sort_by(f64::total_cmp);
+        times.dedup();
+        let time_index = |blt: usize| {
+            times
+                .binary_search_by(|time| time.total_cmp(&time_array[blt]))
+                .unwrap()
+        };
+        let ngroups = match options.pol_combination {
+            PolCombination::Rss => 1,
+            _ => npols,
+        };
+        let mut zsum = Array::<f64, Ix3>::zeros((ngroups, times.len(), nfreqs));
+        let mut count = Array::<f64, Ix3>::zeros((ngroups, times.len(), nfreqs));
+        for (ant1, ant2) in autos {
+            let mut blts = self.antpair_blts(ant1, ant2);
+            blts.sort_by(|&blt1, &blt2| time_array[blt1].total_cmp(&time_array[blt2]));
+            let groups = pol_waterfalls(data, &old_flags, &blts, options.pol_combination);
+            for (group, (amps, flags)) in groups.iter().enumerate() {
+                let zscores = modified_zscores(amps, flags, options.kernel);
+                for ((time, freq), &zscore) in zscores.indexed_iter() {
+                    if zscore.is_finite() {
+                        zsum[[group, time_index(blts[time]), freq]] += zscore;
+                        count[[group, time_index(blts[time]), freq]] += 1.0;
+                    }
+                }
+            }
+        }
+        let combined = zsum / count.mapv(f64::sqrt);
+        let no_flags = Array::from_elem((times.len(), nfreqs), false);
+        let group_flags = combined
+            .outer_iter()
+            .map(|zscores| flagger(&zscores.to_owned(), &no_flags))
+            .collect();
+        let time_flags = pol_flags(group_flags, npols, options.pol_combination);
+
+        let mut flags = old_flags;
+        for blt in 0..nblts {
+            let time = time_index(blt);
+            for (pol, pol_flags) in time_flags.iter().enumerate() {
+                for freq in 0..nfreqs {
+                    flags[[blt, freq, pol]] |= pol_flags[[time, freq]];
+                }
+            }
+        }
+        self.flag_array = Some(flags);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        flag_waterfall, modified_zscores, sir_line, sumthreshold_line, watershed_flags,
+        PolCombination, SumThreshold, UVData, Xrfi, XrfiSource,
+    };
+    use crate::test_data::read_drift;
+    use ndarray::Array;
+    use num_complex::Complex;
+    use std::str::FromStr;
+
+    // deterministic noise in [-1, 1)
+    fn noise(seed: usize) -> f64 {
+        let x = (seed as f64 * 12.9898 + 78.233).sin() * 43758.5453;
+        2.0 * (x - x.floor()) - 1.0
+    }
+
+    #[test]
+    fn pol_combination_from_str() {
+        assert_eq!(PolCombination::from_str("OR").unwrap(), PolCombination::Any);
+        assert!(PolCombination::from_str("max").is_err());
+    }
+
+    #[test]
+    fn sumthreshold_windows() {
+        let values = [0.0, 3.0, 3.0, 3.0, 0.0, 10.0, 0.0];
+        let flags = [false; 7];
+        assert_eq!(
+            sumthreshold_line(&values, &flags, 4.0, 1),
+            vec![false, false, false, false, false, true, false]
+        );
+        assert_eq!(
+            sumthreshold_line(&values, &flags, 2.5, 3),
+            vec![false, true, true, true, true, true, true]
+        );
+        // flagged samples count as the threshold
+        let flags = [false, false, true, false, false, false, false];
+        assert_eq!(
+            sumthreshold_line(&values, &flags, 3.5, 3)[..3],
+            [false, false, true]
+        );
+    }
+
+    #[test]
+    fn sir_dilation() {
+        let flags = [
+            true, true, false, true, true, false, false, false, false, false,
+        ];
+        assert_eq!(
+            sir_line(&flags, 0.2),
+            vec![true, true, true, true, true, false, false, false, false, false]
+        );
+        assert_eq!(sir_line(&flags, 0.0), flags.to_vec());
+    }
+
+    #[test]
+    fn flags_faint_and_bright_rfi() {
+        let (ntimes, nfreqs) = (64, 64);
+        let mut amps = Array::from_shape_fn((ntimes, nfreqs), |(time, freq)| {
+            10.0 + noise(time * nfreqs + freq)
+        });
+        // a faint line in one channel and a bright spike
+        amps.column_mut(20).iter_mut().for_each(|amp| *amp += 1.0);
+        amps[[40, 50]] += 20.0;
+        let flags = flag_waterfall(
+            &amps,
+            &Array::from_elem((ntimes, nfreqs), false),
+            &SumThreshold::default(),
+        );
+        assert!(flags.column(20).iter().all(|&flag| flag));
+        assert!(flags[[40, 50]]);
+        let nflagged = flags.iter().filter(|&&flag| flag).count();
+        assert!(nflagged < ntimes + 2 * ntimes * nfreqs / 100);
+    }
+
+    #[test]
+    fn flag_uvdata() {
+        let mut uvd = noisy_drift();
+        uvd.data_array.as_mut().unwrap()[[7, 2, 0]] += Complex::new(100.0, 0.0);
+
+        let mut separate = uvd.clone();
+        separate
+            .flag_sumthreshold(&SumThreshold::default())
+            .expect("Cannot flag.");
+        let flags = separate.flag_array.as_ref().unwrap();
+        assert!(flags[[7, 2, 0]]);
+        assert!(!flags[[7, 2, 1]]);
+
+        let options = SumThreshold {
+            pol_combination: PolCombination::Any,
+            ..SumThreshold::default()
+        };
+        uvd.flag_sumthreshold(&options).expect("Cannot flag.");
+        let flags = uvd.flag_array.as_ref().unwrap();
+        assert!(flags[[7, 2, 0]] && flags[[7, 2, 1]]);
+        assert!(flags.iter().filter(|&&flag| flag).count() < flags.len() / 10);
+    }
+
+    fn noisy_drift() -> UVData<f64, f32> {
+        let mut uvd = read_drift();
+        let data = uvd.data_array.as_mut().unwrap();
+        for ((blt, freq, pol), vis) in data.indexed_iter_mut() {
+            let seed = (blt * 4 + freq) * 2 + pol;
+            *vis = Complex::new(10.0 + noise(seed), noise(seed + 100_000));
+        }
+        uvd.flag_array = None;
+        uvd
+    }
+
+    #[test]
+    fn zscores_and_watershed() {
+        let mut amps =
+            Array::from_shape_fn((20, 20), |(time, freq)| 10.0 + noise(time * 20 + freq));
+        amps[[5, 5]] = 30.0;
+        let mut flags = Array::from_elem((20, 20), false);
+        flags[[0, 0]] = true;
+        let zscores = modified_zscores(&amps, &flags, (3, 3));
+        assert!(zscores[[0, 0]].is_nan());
+        assert!(zscores[[5, 5]] > 10.0);
+        assert!(zscores[[10, 10]].abs() < 5.0);
+
+        let zscores = Array::from_shape_vec((1, 6), vec![0.0, 3.0, 7.0, 2.5, 1.0, 8.0]).unwrap();
+        let flags = watershed_flags(&zscores, &Array::from_elem((1, 6), false), 6.0, 2.0);
+        assert_eq!(
+            flags.into_raw_vec(),
+            vec![false, true, true, true, false, true]
+        );
+    }
+
+    #[test]
+    fn xrfi_baselines() {
+        let mut uvd = noisy_drift();
+        uvd.data_array.as_mut().unwrap()[[7, 2, 0]] += Complex::new(100.0, 0.0);
+        let source = "baselines".parse::<XrfiSource>().unwrap();
+        uvd.flag_xrfi(&Xrfi {
+            source,
+            ..Xrfi::default()
+        })
+        .expect("Cannot flag.");
+        let flags = uvd.flag_array.as_ref().unwrap();
+        assert!(flags[[7, 2, 0]]);
+        assert!(!flags[[7, 2, 1]]);
+        assert!(flags.iter().filter(|&&flag| flag).count() < flags.len() / 10);
+    }
+
+    #[test]
+    fn xrfi_autos() {
+        let mut uvd = noisy_drift();
+        let time = uvd.meta_arrays.time_array[7];
+        let arrays = uvd.meta_arrays.clone();
+        let data = uvd.data_array.as_mut().unwrap();
+        for blt in 0..arrays.time_array.len() {
+            if arrays.time_array[blt] == time && arrays.ant_1_array[blt] == arrays.ant_2_array[blt]
+            {
+                data[[blt, 1, 1]] += Complex::new(50.0, 0.0);
+            }
+        }
+        let options = Xrfi {
+            source: XrfiSource::Autos,
+            ..Xrfi::default()
+        };
+        uvd.flag_xrfi(&options).expect("Cannot flag.");
+        let flags = uvd.flag_array.as_ref().unwrap();
+        for blt in 0..arrays.time_array.len() {
+            assert_eq!(flags[[blt, 1, 1]], arrays.time_array[blt] == time);
+            assert!(!flags[[blt, 1, 0]]);
+        }
+
+        let mut uvd = noisy_drift();
+        let crosses: Vec<usize> = (0..arrays.time_array.len())
+            .filter(|&blt| arrays.ant_1_array[blt] != arrays.ant_2_array[blt])
+            .collect();
+        uvd.select_blts(&crosses).expect("Cannot select.");
+        assert!(uvd.flag_xrfi(&options).is_err());
+    }
+}
@@ -0,0 +1,618 @@
+use approx::AbsDiffEq;
+use ndarray::{Array, Ix3};
+use num_complex::Complex;
+use num_traits::Float;
+use std::f64::consts::{FRAC_2_PI, PI};
+
+use super::base::VisUnit;
+use super::healpix::HealpixMap;
+use super::utils::SPEED_OF_LIGHT;
+use super::UVData;
+
+/// An unresolved source with a power law spectrum.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PointSource {
+    /// Right ascension in radians.
+    pub ra: f64,
+    /// Declination in radians.
+    pub dec: f64,
+    /// Stokes I flux density in Jy at `reference_freq`.
+    pub flux: f64,
+    pub spectral_index: f64,
+    /// Reference frequency in Hz.
+    pub reference_freq: f64,
+    /// Stokes (Q, U, V) as fractions of Stokes I.
+    pub polarization: Option<[f64; 3]>,
+}
+
+/// Antenna beam, pointed at zenith.
+#[derive(Debug, PartialEq, Clone)]
+pub enum BeamModel {
+    Uniform,
+    /// Achromatic Gaussian power beam with a full width at half maximum
+    /// in radians.
+    Gaussian {
+        fwhm: f64,
+    },
+    /// Airy disk of a uniformly illuminated dish of `antenna_diameters`.
+    Airy,
+    /// Achromatic power beam tabulated against increasing zenith angles in
+    /// radians, linearly interpolated and held at the last value.
+    Tabulated {
+        za: Vec<f64>,
+        power: Vec<f64>,
+    },
+}
+
+/// Bessel function of the first kind of order one, from the rational
+/// approximations of Numerical Recipes.
+fn bessel_j1(x: f64) -> f64 {
+    let ax = x.abs();
+    if ax < 8.0 {
+        let y = x * x;
+        let num = x
+            * (72362614232.0
+                + y * (-7895059235.0
+                    + y * (242396853.1
+                        + y * (-2972611.439 + y * (15704.48260 + y * (-30.16036606))))));
+        let den = 144725228442.0
+            + y * (2300535178.0 + y * (18583304.74 + y * (99447.43394 + y * (376.9991397 + y))));
+        num / den
+    } else {
+        let z = 8.0 / ax;
+        let y = z * z;
+        let xx = ax - 0.75 * PI;
+        let p = 1.0
+            + y * (0.183105e-2
+                + y * (-0.3516396496e-4 + y * (0.2457520174e-5 + y * (-0.240337019e-6))));
+        let q = 0.04687499995
+            + y * (-0.2002690873e-3
+                + y * (0.8449199096e-5 + y * (-0.88228987e-6 + y * 0.105787412e-6)));
+        let ans = (FRAC_2_PI / ax).sqrt() * (xx.cos() * p - z * xx.sin() * q);
+        ans * x.signum()
+    }
+}
+
+/// Voltage response of a dish of `diameter` meters at zenith angle `za`.
+fn airy_voltage(za: f64, diameter: f64, wavelength: f64) -> f64 {
+    let x = PI * diameter * za.sin() / wavelength;
+    match x.abs() < 1e-12 {
+        true => 1.0,
+        false => 2.0 * bessel_j1(x) / x,
+    }
+}
+
+/// East, north and up components of the direction of (`hour_angle`, `dec`)
+/// seen from latitude `lat`.
+pub(crate) fn enu_direction(hour_angle: f64, dec: f64, lat: f64) -> [f64; 3] {
+    [
+        -dec.cos() * hour_angle.sin(),
+        lat.cos() * dec.sin() - lat.sin() * dec.cos() * hour_angle.cos(),
+        lat.sin() * dec.sin() + lat.cos() * dec.cos() * hour_angle.cos(),
+    ]
+}
+
+/// Coherency of each polarization for Stokes (I, Q, U, V), with feeds
+/// aligned to the equatorial frame.
+pub(crate) fn stokes_to_pol(pol: i8, stokes: [f64; 4]) -> Result<Complex<f64>, String> {
+    let [i, q, u, v] = stokes;
+    match pol {
+        1 => Ok(Complex::new(i, 0.0)),
+        2 => Ok(Complex::new(q, 0.0)),
+        3 => Ok(Complex::new(u, 0.0)),
+        4 => Ok(Complex::new(v, 0.0)),
+        -1 => Ok(Complex::new(i + v, 0.0)),
+        -2 => Ok(Complex::new(i - v, 0.0)),
+        -3 => Ok(Complex::new(q, u)),
+        -4 => Ok(Complex::new(q, -u)),
+        -5 => Ok(Complex::new(i + q, 0.0)),
+        -6 => Ok(Complex::new(i - q, 0.0)),
+        -7 => Ok(Complex::new(u, v)),
+        -8 => Ok(Complex::new(u, -v)),
+        other => Err(format!("Cannot simulate polarization {}.", other)),
+    }
+}
+
+/// What every source added to one baseline-time shares, resolved once per
+/// baseline-time rather than once per source.
+struct BltSky<'a> {
+    lst: f64,
+    lat: f64,
+    /// Phase center, or `None` for unprojected uvws.
+    center: Option<(f64, f64)>,
+    uvw: [f64; 3],
+    beam: &'a BeamModel,
+    /// Dish diameters of both antennas, for Airy beams.
+    diameters: (f64, f64),
+}
+
+impl BltSky<'_> {
+    /// Direction of `(ra, dec)` relative to the phase center, for the fringe
+    /// term of the uvw.
+    ///
+    /// Unprojected uvws are ENU baseline vectors, so the ENU direction is
+    /// used; projected ones use the direction cosines (l, m, n - 1).
+    fn fringe_direction(&self, ra: f64, dec: f64) -> [f64; 3] {
+        match self.center {
+            Some((ra0, dec0)) => {
+                let dra = ra - ra0;
+                [
+                    dec.cos() * dra.sin(),
+                    dec.sin() * dec0.cos() - dec.cos() * dec0.sin() * dra.cos(),
+                    dec.sin() * dec0.sin() + dec.cos() * dec0.cos() * dra.cos() - 1.0,
+                ]
+            }
+            None => enu_direction(self.lst - ra, dec, self.lat),
+        }
+    }
+
+    /// Beam response of the baseline at zenith angle `za`.
+    fn beam_response(&self, za: f64, wavelength: f64) -> f64 {
+        match self.beam {
+            BeamModel::Uniform => 1.0,
+            BeamModel::Gaussian { fwhm } => {
+                let sigma = fwhm / (8.0 * 2.0.ln()).sqrt();
+                (-za.powi(2) / (2.0 * sigma.powi(2))).exp()
+            }
+            BeamModel::Airy => {
+                airy_voltage(za, self.diameters.0, wavelength)
+                    * airy_voltage(za, self.diameters.1, wavelength)
+            }
+            BeamModel::Tabulated { za: angles, power } => {
+                let upper = angles.iter().position(|&angle| angle > za);
+                match upper {
+                    Some(0) => power[0],
+                    Some(ind) => {
+                        let frac = (za - angles[ind - 1]) / (angles[ind] - angles[ind - 1]);
+                        power[ind - 1] + frac * (power[ind] - power[ind - 1])
+                    }
+                    None => power[power.len() - 1],
+                }
+            }
+        }
+    }
+
+    /// Add a source at (`ra`, `dec`) with flux `flux[freq]` and coherency
+    /// `coherencies[pol]` per unit flux to `data` for `blt`, unless it is
+    /// below the horizon.
+    fn add_source<T: Float>(
+        &self,
+        data: &mut Array<Complex<T>, Ix3>,
+        blt: usize,
+        (ra, dec): (f64, f64),
+        fluxes: &[f64],
+        coherencies: &[Complex<f64>],
+        wavelengths: &[f64],
+    ) {
+        let up = enu_direction(self.lst - ra, dec, self.lat)[2];
+        if up <= 0.0 {
+            return;
+        }
+        let za = up.min(1.0).acos();
+        let dir = self.fringe_direction(ra, dec);
+        let path = self.uvw[0] * dir[0] + self.uvw[1] * dir[1] + self.uvw[2] * dir[2];
+        for (freq, &wavelength) in wavelengths.iter().enumerate() {
+            if fluxes[freq] == 0.0 {
+                continue;
+            }
+            let gain = self.beam_response(za, wavelength);
+            let fringe = Complex::from_polar(fluxes[freq] * gain, -2.0 * PI * path / wavelength);
+            for (pol, coherency) in coherencies.iter().enumerate() {
+                let vis = fringe * coherency;
+                data[[blt, freq, pol]] = data[[blt, freq, pol]]
+                    + Complex::new(T::from(vis.re).unwrap(), T::from(vis.im).unwrap());
+            }
+        }
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Resolve the lst, phase center, uvw and antenna diameters of `blt`.
+    fn blt_sky<'a>(&self, blt: usize, beam: &'a BeamModel, lat: f64) -> Result<BltSky<'a>, String> {
+        let arrays = &self.meta_arrays;
+        let diameters = match beam {
+            BeamModel::Airy => {
+                let diameters = arrays
+                    .antenna_diameters
+                    .as_ref()
+                    .ok_or("Airy beams need antenna_diameters.")?;
+                let diameter = |ant: u32| {
+                    arrays
+                        .antenna_numbers
+                        .iter()
+                        .position(|&num| num == ant)
+                        .map(|ind| diameters[ind] as f64)
+                        .ok_or_else(|| format!("Antenna {} has no diameter.", ant))
+                };
+                (
+                    diameter(arrays.ant_1_array[blt])?,
+                    diameter(arrays.ant_2_array[blt])?,
+                )
+            }
+            BeamModel::Tabulated { za, power } if za.is_empty() || za.len() != power.len() => {
+                return Err("Tabulated beams need one power per zenith angle.".to_string());
+            }
+            _ => (0.0, 0.0),
+        };
+        let uvw = arrays.uvw_array.row(blt);
+        Ok(BltSky {
+            lst: arrays.lst_array[blt],
+            lat,
+            center: self.phase_center_of(blt),
+            uvw: [uvw[0], uvw[1], uvw[2]],
+            beam,
+            diameters,
+        })
+    }
+
+    /// Coherency per unit Stokes I of each polarization for fractional
+    /// Stokes (Q, U, V).
+    fn coherencies(&self, [q, u, v]: [f64; 3]) -> Result<Vec<Complex<f64>>, String> {
+        self.meta_arrays
+            .polarization_array
+            .iter()
+            .map(|&pol| stokes_to_pol(pol, [1.0, q, u, v]))
+            .collect()
+    }
+
+    fn wavelengths(&self) -> Vec<f64> {
+        self.meta_arrays
+            .freq_array
+            .iter()
+            .map(|freq| SPEED_OF_LIGHT / freq)
+            .collect()
+    }
+
+    fn set_simulated(&mut self, data: Array<Complex<T>, Ix3>) {
+        let shape = data.dim();
+        self.data_array = Some(data);
+        self.nsample_array
+            .get_or_insert_with(|| Array::from_elem(shape, S::one()));
+        self.flag_array
+            .get_or_insert_with(|| Array::from_elem(shape, false));
+        self.meta.vis_units = VisUnit::Jansky;
+    }
+
+    /// Fill `data_array` with the visibilities of point `sources` seen
+    /// through `beam`.
+    ///
+    /// Sources below the horizon are skipped. Visibilities follow
+    /// V = sum S B exp(-2 pi i uvw . s / lambda) with the stored uvws, and
+    /// are in Jy. Missing flags and nsamples are created unflagged with
+    /// unit weight.
+    pub fn simulate_point_sources(
+        &mut self,
+        sources: &[PointSource],
+        beam: &BeamModel,
+    ) -> Result<(), String> {
+        let lat = self.telescope_location_latlonalt().0;
+        let shape = (
+            self.meta.nblts as usize,
+            self.meta.nfreqs as usize,
+            self.meta.npols as usize,
+        );
+        let wavelengths = self.wavelengths();
+        let spectra: Vec<Vec<f64>> = sources
+            .iter()
+            .map(|source| {
+                self.meta_arrays
+                    .freq_array
+                    .iter()
+                    .map(|freq| {
+                        source.flux * (freq / source.reference_freq).powf(source.spectral_index)
+                    })
+                    .collect()
+            })
+            .collect();
+        let coherencies = sources
+            .iter()
+            .map(|source| self.coherencies(source.polarization.unwrap_or([0.0; 3])))
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut data = Array::<Complex<T>, Ix3>::zeros(shape);
+        for blt in 0..shape.0 {
+            let blt_sky = self.blt_sky(blt, beam, lat)?;
+            for ((source, fluxes), coherencies) in sources.iter().zip(&spectra).zip(&coherencies) {
+                blt_sky.add_source(
+                    &mut data,
+                    blt,
+                    (source.ra, source.dec),
+                    fluxes,
+                    coherencies,
+                    &wavelengths,
+                );
+            }
+        }
+        self.set_simulated(data);
+        Ok(())
+    }
+
+    /// Fill `data_array` with the visibilities of the unpolarized diffuse
+    /// `sky` seen through `beam`.
+    ///
+    /// Each row of the map is the sky at the matching channel of
+    /// `freq_array`, with Kelvin maps converted to Jy/sr. Every pixel above
+    /// the horizon is treated as a point source of flux density I times the
+    /// pixel area, rotated to the topocentric frame at the lst of each
+    /// baseline-time.
+    pub fn simulate_diffuse_sky(
+        &mut self,
+        sky: &HealpixMap,
+        beam: &BeamModel,
+    ) -> Result<(), String> {
+        let shape = (
+            self.meta.nblts as usize,
+            self.meta.nfreqs as usize,
+            self.meta.npols as usize,
+        );
+        if sky.data.nrows() != shape.1 || sky.data.ncols() != sky.npix() {
+            return Err(format!(
+                "Expected a ({}, {}) map, found {:?}.",
+                shape.1,
+                sky.npix(),
+                sky.data.dim()
+            ));
+        }
+        let lat = self.telescope_location_latlonalt().0;
+        let wavelengths = self.wavelengths();
+        let area = sky.pixel_area();
+        let intensity = sky.jy_per_sr(&self.meta_arrays.freq_array.to_vec())?;
+        let pixels: Vec<((f64, f64), Vec<f64>)> = (0..sky.npix())
+            .map(|pix| {
+                let fluxes = intensity
+                    .column(pix)
+                    .iter()
+                    .map(|intensity| intensity * area)
+                    .collect();
+                (sky.pix2radec(pix), fluxes)
+            })
+            .filter(|(_, fluxes): &(_, Vec<f64>)| fluxes.iter().any(|&flux| flux != 0.0))
+            .collect();
+        let coherencies = self.coherencies([0.0; 3])?;
+        let mut data = Array::<Complex<T>, Ix3>::zeros(shape);
+        for blt in 0..shape.0 {
+            let blt_sky = self.blt_sky(blt, beam, lat)?;
+            for (radec, fluxes) in pixels.iter() {
+                blt_sky.add_source(&mut data, blt, *radec, fluxes, &coherencies, &wavelengths);
+            }
+        }
+        self.set_simulated(data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bessel_j1, BeamModel, PointSource};
+    use crate::noise::{BOLTZMANN, JANSKY};
+    use crate::test_data::read_metadata;
+    use crate::utils::SPEED_OF_LIGHT;
+    use crate::{base::VisUnit, HealpixMap, HealpixOrdering, SkyUnit, UVData};
+    use ndarray::{Array, Axis};
+    use std::f64::consts::PI;
+
+    /// A 1 Jy source transiting `za` radians south of zenith at blt 0.
+    fn transiting(uvd: &UVData<f64, f32>, za: f64) -> PointSource {
+        PointSource {
+            ra: uvd.meta_arrays.lst_array[0],
+            dec: uvd.telescope_location_latlonalt().0 - za,
+            flux: 1.0,
+            spectral_index: 0.0,
+            reference_freq: 150e6,
+            polarization: None,
+        }
+    }
+
+    #[test]
+    fn bessel() {
+        assert_abs_diff_eq!(bessel_j1(1.0), 0.4400505857, epsilon = 1e-7);
+        assert_abs_diff_eq!(bessel_j1(-2.5), -0.4970941025, epsilon = 1e-7);
+        assert_abs_diff_eq!(bessel_j1(10.0), 0.0434727462, epsilon = 1e-7);
+    }
+
+    #[test]
+    fn zenith_source() {
+        let mut uvd = read_metadata();
+        let mut source = transiting(&uvd, 0.0);
+        source.flux = 2.0;
+        source.spectral_index = -0.8;
+        source.polarization = Some([0.5, 0.0, 0.0]);
+        uvd.simulate_point_sources(&[source.clone()], &BeamModel::Uniform)
+            .expect("Cannot simulate.");
+        assert_eq!(uvd.meta.vis_units, VisUnit::Jansky);
+        assert!(uvd.flag_array.as_ref().unwrap().iter().all(|&flag| !flag));
+
+        let data = uvd.data_array.as_ref().unwrap();
+        let arrays = &uvd.meta_arrays;
+        for freq in 0..4 {
+            let flux = 2.0 * (arrays.freq_array[freq] / 150e6).powf(-0.8);
+            // xx is I + Q and yy is I - Q
+            assert_abs_diff_eq!(data[[0, freq, 0]].norm(), 1.5 * flux, epsilon = 1e-9);
+            assert_abs_diff_eq!(data[[0, freq, 1]].norm(), 0.5 * flux, epsilon = 1e-9);
+            let wavelength = SPEED_OF_LIGHT / arrays.freq_array[freq];
+            let phase = -2.0 * PI * arrays.uvw_array[[0, 2]] / wavelength;
+            let expected = num_complex::Complex::from_polar(1.5 * flux, phase);
+            assert_abs_diff_eq!(data[[0, freq, 0]].re, expected.re, epsilon = 1e-6);
+            assert_abs_diff_eq!(data[[0, freq, 0]].im, expected.im, epsilon = 1e-6);
+        }
+
+        // below the horizon
+        source.ra += PI;
+        source.dec = -uvd.telescope_location_latlonalt().0;
+        uvd.simulate_point_sources(&[source], &BeamModel::Uniform)
+            .unwrap();
+        let data = uvd.data_array.as_ref().unwrap();
+        assert!(data
+            .outer_iter()
+            .next()
+            .unwrap()
+            .iter()
+            .all(|vis| vis.norm() == 0.0));
+    }
+
+    #[test]
+    fn beams() {
+        let mut uvd = read_metadata();
+        let source = transiting(&uvd, 0.2);
+        let fwhm: f64 = 0.3;
+        uvd.simulate_point_sources(std::slice::from_ref(&source), &BeamModel::Gaussian { fwhm })
+            .unwrap();
+        let sigma = fwhm / (8.0 * 2.0f64.ln()).sqrt();
+        let expected = (-0.04 / (2.0 * sigma * sigma)).exp();
+        assert_abs_diff_eq!(
+            uvd.data_array.as_ref().unwrap()[[0, 1, 0]].norm(),
+            expected,
+            epsilon = 1e-9
+        );
+
+        uvd.meta_arrays.antenna_diameters = None;
+        assert!(uvd
+            .simulate_point_sources(&[source], &BeamModel::Airy)
+            .is_err());
+        let nants = uvd.meta_arrays.antenna_numbers.len();
+        uvd.meta_arrays.antenna_diameters = Some(Array::from_elem(nants, 14.0));
+        uvd.simulate_point_sources(&[transiting(&uvd, 0.0)], &BeamModel::Airy)
+            .unwrap();
+        assert_abs_diff_eq!(
+            uvd.data_array.as_ref().unwrap()[[0, 0, 0]].norm(),
+            1.0,
+            epsilon = 1e-9
+        );
+        // the first null of a 14 m dish at zenith angle asin(1.2197 lambda / D)
+        let wavelength = SPEED_OF_LIGHT / uvd.meta_arrays.freq_array[0];
+        let null = (3.831705970 / PI * wavelength / 14.0).asin();
+        uvd.simulate_point_sources(&[transiting(&uvd, null)], &BeamModel::Airy)
+            .unwrap();
+        assert_abs_diff_eq!(
+            uvd.data_array.as_ref().unwrap()[[0, 0, 0]].norm(),
+            0.0,
+            epsilon = 1e-8
+        );
+    }
+
+    #[test]
+    fn tabulated_beam() {
+        let mut uvd = read_metadata();
+        let beam = BeamModel::Tabulated {
+            za: vec![0.0, 0.2, 0.4],
+            power: vec![1.0, 0.5, 0.0],
+        };
+        uvd.simulate_point_sources(&[transiting(&uvd, 0.1)], &beam)
+            .unwrap();
+        assert_abs_diff_eq!(
+            uvd.data_array.as_ref().unwrap()[[0, 0, 0]].norm(),
+            0.75,
+            epsilon = 1e-9
+        );
+        uvd.simulate_point_sources(&[transiting(&uvd, 0.5)], &beam)
+            .unwrap();
+        assert_abs_diff_eq!(
+            uvd.data_array.as_ref().unwrap()[[0, 0, 0]].norm(),
+            0.0,
+            epsilon = 1e-12
+        );
+        let bad = BeamModel::Tabulated {
+            za: vec![0.0],
+            power: vec![],
+        };
+        assert!(uvd
+            .simulate_point_sources(&[transiting(&uvd, 0.0)], &bad)
+            .is_err());
+    }
+
+    #[test]
+    fn diffuse_pixel_matches_point_source() {
+        let mut uvd = read_metadata();
+        let nside = 8;
+        let mut sky = HealpixMap::new(
+            nside as u32,
+            HealpixOrdering::Nested,
+            SkyUnit::JanskyPerSr,
+            Array::zeros((4, 12 * nside * nside)),
+        )
+        .unwrap();
+        // a pixel just east of zenith at the first time
+        let lat = uvd.telescope_location_latlonalt().0;
+        let lst = uvd.meta_arrays.lst_array[0];
+        let pix = (0..sky.npix())
+            .min_by(|&a, &b| {
+                let dist = |pix| {
+                    let (ra, dec) = sky.pix2radec(pix);
+                    (ra - lst - 0.1).abs() + (dec - lat).abs()
+                };
+                dist(a).partial_cmp(&dist(b)).unwrap()
+            })
+            .unwrap();
+        sky.data.column_mut(pix).fill(2.0);
+        uvd.simulate_diffuse_sky(&sky, &BeamModel::Gaussian { fwhm: 0.5 })
+            .unwrap();
+        let diffuse = uvd.data_array.clone().unwrap();
+
+        let (ra, dec) = sky.pix2radec(pix);
+        let source = PointSource {
+            ra,
+            dec,
+            flux: 2.0 * sky.pixel_area(),
+            spectral_index: 0.0,
+            reference_freq: 150e6,
+            polarization: None,
+        };
+        uvd.simulate_point_sources(&[source], &BeamModel::Gaussian { fwhm: 0.5 })
+            .unwrap();
+        let points = uvd.data_array.as_ref().unwrap();
+        assert!(points.iter().any(|vis| vis.norm() > 0.0));
+        for (a, b) in diffuse.iter().zip(points.iter()) {
+            assert_abs_diff_eq!(a.re, b.re, epsilon = 1e-12);
+            assert_abs_diff_eq!(a.im, b.im, epsilon = 1e-12);
+        }
+
+        let wrong = HealpixMap::new(
+            nside as u32,
+            HealpixOrdering::Ring,
+            SkyUnit::JanskyPerSr,
+            Array::zeros((2, 768)),
+        )
+        .unwrap();
+        assert!(uvd
+            .simulate_diffuse_sky(&wrong, &BeamModel::Uniform)
+            .is_err());
+    }
+
+    #[test]
+    fn uniform_sky_autos() {
+        let mut uvd = read_metadata();
+        let mut sky = HealpixMap::new(
+            16,
+            HealpixOrdering::Ring,
+            SkyUnit::JanskyPerSr,
+            Array::ones((4, 3072)),
+        )
+        .unwrap();
+        uvd.simulate_diffuse_sky(&sky, &BeamModel::Uniform).unwrap();
+        let data = uvd.data_array.clone().unwrap();
+        let arrays = &uvd.meta_arrays;
+        let auto = (0..arrays.ant_1_array.len())
+            .find(|&blt| arrays.ant_1_array[blt] == arrays.ant_2_array[blt])
+            .unwrap();
+        // an auto-correlation sees the whole hemisphere, 2 pi sr
+        for vis in data.index_axis(Axis(0), auto).iter() {
+            assert_abs_diff_eq!(vis.re, 2.0 * PI, epsilon = 0.05);
+            assert_abs_diff_eq!(vis.im, 0.0, epsilon = 1e-12);
+        }
+
+        // 1 K is 2 k nu^2 / c^2 in Rayleigh-Jeans
+        sky.unit = SkyUnit::Kelvin;
+        uvd.simulate_diffuse_sky(&sky, &BeamModel::Uniform).unwrap();
+        let kelvin = uvd.data_array.as_ref().unwrap();
+        for (freq, &nu) in uvd.meta_arrays.freq_array.iter().enumerate() {
+            let scale = 2.0 * BOLTZMANN * (nu / SPEED_OF_LIGHT).powi(2) / JANSKY;
+            for pol in 0..kelvin.dim().2 {
+                let vis = kelvin[[auto, freq, pol]];
+                assert_abs_diff_eq!(vis.re, scale * data[[auto, freq, pol]].re, epsilon = 1e-9);
+            }
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use approx::AbsDiffEq;
+use ndarray::{Array, Ix4};
+use num_complex::Complex;
+use num_traits::{Float, Zero};
+use std::collections::HashMap;
+
+use super::base::VisUnit;
+use super::utils::FREQ_TOL;
+use super::uvcal::{CalStyle, UVCal};
+use super::UVData;
+
+/// Options for StEFCal sky-model calibration.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StefCal {
+    /// Integrations per solution interval.
+    pub time_interval: usize,
+    /// Channels per solution interval.
+    pub freq_interval: usize,
+    pub max_iter: usize,
+    /// Relative change of the gains at which iterations stop.
+    pub conv_crit: f64,
+    /// Antenna given zero phase, defaulting to the first solved antenna.
+    pub ref_ant: Option<u32>,
+}
+
+impl Default for StefCal {
+    fn default() -> StefCal {
+        StefCal {
+            time_interval: 1,
+            freq_interval: 1,
+            max_iter: 1000,
+            conv_crit: 1e-10,
+            ref_ant: None,
+        }
+    }
+}
+
+/// A cross-correlation sample: antenna indices, data, model and weight.
+struct Sample {
+    ind1: usize,
+    ind2: usize,
+    vis: Complex<f64>,
+    model: Complex<f64>,
+    weight: f64,
+}
+
+/// Solve V_pq = g_p M_pq g_q^* by alternating least squares, averaging
+/// every other update as in Salvini & Wijnholds (2014).
+///
+/// Returns the gains and whether each antenna had any weighted data.
+fn stefcal(samples: &[Sample], nants: usize, opts: &StefCal) -> (Vec<Complex<f64>>, Vec<bool>) {
+    let mut gains = vec![Complex::new(1.0, 0.0); nants];
+    let mut solved = vec![false; nants];
+    for sample in samples.iter().filter(|sample| sample.weight > 0.0) {
+        solved[sample.ind1] = true;
+        solved[sample.ind2] = true;
+    }
+    for iter in 0..opts.max_iter {
+        let mut num = vec![Complex::<f64>::zero(); nants];
+        let mut den = vec![0.0; nants];
+        for sample in samples.iter().filter(|sample| sample.weight > 0.0) {
+            // V_pq = g_p z_p with z_p = M_pq g_q^*, and V_pq^* = g_q z_q
+            let z1 = sample.model * gains[sample.ind2].conj();
+            num[sample.ind1] += sample.vis * z1.conj() * sample.weight;
+            den[sample.ind1] += z1.norm_sqr() * sample.weight;
+            let z2 = sample.model.conj() * gains[sample.ind1].conj();
+            num[sample.ind2] += sample.vis.conj() * z2.conj() * sample.weight;
+            den[sample.ind2] += z2.norm_sqr() * sample.weight;
+        }
+        let mut change = 0.0;
+        let mut norm = 0.0;
+        for ant in 0..nants {
+            if den[ant] <= 0.0 {
+                continue;
+            }
+            let mut new = num[ant] / den[ant];
+            if iter % 2 == 1 {
+                new = (new + gains[ant]) / 2.0;
+            }
+            change += (new - gains[ant]).norm_sqr();
+            norm += new.norm_sqr();
+            gains[ant] = new;
+        }
+        if change.sqrt() <= opts.conv_crit * norm.sqrt() {
+            break;
+        }
+    }
+    (gains, solved)
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Solve for per-antenna gains calibrating these data to `model` with
+    /// StEFCal.
+    ///
+    /// The model must hold every baseline-time of the data, in any blt
+    /// order and with times matching within `TIME_TOL`, at the same
+    /// frequencies and polarizations. Each Jones element is solved from its
+    /// parallel-hand cross-correlations over solution intervals of
+    /// `opts.time_interval` integrations and `opts.freq_interval` channels,
+    /// weighted by nsamples. Flagged samples of either data set are ignored
+    /// and antennas without data are flagged. The gains follow the divide
+    /// convention, so `calibrate` with them brings the data to the model.
+    pub fn stefcal(&self, model: &UVData<T, S>, opts: &StefCal) -> Result<UVCal, String> {
+        let arrays = &self.meta_arrays;
+        let model_arrays = &model.meta_arrays;
+        let same_freqs = arrays.freq_array.len() == model_arrays.freq_array.len()
+            && arrays
+                .freq_array
+                .iter()
+                .zip(model_arrays.freq_array.iter())
+                .all(|(freq1, freq2)| (freq1 - freq2).abs() < FREQ_TOL);
+        if !same_freqs || arrays.polarization_array != model_arrays.polarization_array {
+            return Err("The model must match the data's frequencies and polarizations.".into());
+        }
+        let model_blts = (0..arrays.time_array.len())
+            .map(|blt| {
+                let time = arrays.time_array[blt];
+                model
+                    .antpair_time_blt(arrays.ant_1_array[blt], arrays.ant_2_array[blt], time)
+                    .ok_or_else(|| {
+                        format!(
+                            "Baseline {} at {} is not in the model.",
+                            arrays.baseline_array[blt], time
+                        )
+                    })
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+        if opts.time_interval == 0 || opts.freq_interval == 0 {
+            return Err("Solution intervals must be at least one sample.".to_string());
+        }
+        let data = self
+            .data_array
+            .as_ref()
+            .ok_or("Data must be loaded to calibrate.")?;
+        let model_data = model
+            .data_array
+            .as_ref()
+            .ok_or("Model data must be loaded to calibrate.")?;
+
+        let mut uvcal = UVCal::from_uvdata(self)?;
+        let pols = uvcal
+            .jones_array
+            .iter()
+            .map(|&jones| {
+                arrays
+                    .polarization_array
+                    .iter()
+                    .position(|&pol| pol == jones)
+                    .ok_or_else(|| format!("Jones element {} has no parallel-hand data.", jones))
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+        let ant_index: HashMap<u32, usize> = uvcal
+            .ant_array
+            .iter()
+            .enumerate()
+            .map(|(ind, &ant)| (ant, ind))
+            .collect();
+        let ref_ant = match opts.ref_ant {
+            Some(ant) => Some(
+                *ant_index
+                    .get(&ant)
+                    .ok_or_else(|| format!("Reference antenna {} is not in the data.", ant))?,
+            ),
+            None => None,
+        };
+        let blt_times = (0..arrays.time_array.len())
+            .map(|blt| uvcal.time_index(arrays.time_array[blt]).unwrap())
+            .collect::<Vec<usize>>();
+
+        let nants = uvcal.nants();
+        let (nfreqs, ntimes, njones) = (
+            arrays.freq_array.len(),
+            uvcal.time_array.len(),
+            uvcal.jones_array.len(),
+        );
+        let mut gains = Array::<Complex<f64>, Ix4>::zeros((nants, nfreqs, ntimes, njones));
+        let mut flags = Array::from_elem((nants, nfreqs, ntimes, njones), false);
+        let weight = |blt: usize, freq: usize, pol: usize| -> f64 {
+            let flagged = self
+                .flag_array
+                .as_ref()
+                .is_some_and(|flags| flags[[blt, freq, pol]])
+                || model
+                    .flag_array
+                    .as_ref()
+                    .is_some_and(|flags| flags[[model_blts[blt], freq, pol]]);
+            match flagged {
+                true => 0.0,
+                false => self
+                    .nsample_array
+                    .as_ref()
+                    .map_or(1.0, |nsamples| nsamples[[blt, freq, pol]].to_f64().unwrap()),
+            }
+        };
+        let to_f64 =
+            |vis: Complex<T>| Complex::new(vis.re.to_f64().unwrap(), vis.im.to_f64().unwrap());
+
+        for time_start in (0..ntimes).step_by(opts.time_interval) {
+            let times = time_start..(time_start + opts.time_interval).min(ntimes);
+            let blts: Vec<usize> = (0..arrays.time_array.len())
+                .filter(|&blt| {
+                    times.contains(&blt_times[blt])
+                        && arrays.ant_1_array[blt] != arrays.ant_2_array[blt]
+                })
+                .collect();
+            for freq_start in (0..nfreqs).step_by(opts.freq_interval) {
+                let freqs = freq_start..(freq_start + opts.freq_interval).min(nfreqs);
+                for (jones, &pol) in pols.iter().enumerate() {
+                    let samples: Vec<Sample> = blts
+                        .iter()
+                        .flat_map(|&blt| freqs.clone().map(move |freq| (blt, freq)))
+                        .map(|(blt, freq)| Sample {
+                            ind1: ant_index[&arrays.ant_1_array[blt]],
+                            ind2: ant_index[&arrays.ant_2_array[blt]],
+                            vis: to_f64(data[[blt, freq, pol]]),
+                            model: to_f64(model_data[[model_blts[blt], freq, pol]]),
+                            weight: weight(blt, freq, pol),
+                        })
+                        .collect();
+                    let (mut sol, solved) = stefcal(&samples, nants, opts);
+                    let reference = ref_ant
+                        .or_else(|| solved.iter().position(|&solved| solved))
+                        .map(|ind| sol[ind])
+                        .filter(|gain| gain.norm() > 0.0);
+                    if let Some(reference) = reference {
+                        let phase = reference.conj() / reference.norm();
+                        sol.iter_mut().for_each(|gain| *gain *= phase);
+                    }
+                    for ant in 0..nants {
+                        for time in times.clone() {
+                            for freq in freqs.clone() {
+                                gains[[ant, freq, time, jones]] = match solved[ant] {
+                                    true => sol[ant],
+                                    false => Complex::new(1.0, 0.0),
+                                };
+                                flags[[ant, freq, time, jones]] = !solved[ant];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        uvcal.gain_array = Some(gains);
+        uvcal.flag_array = flags;
+        uvcal.cal_style = CalStyle::Sky;
+        uvcal.gain_scale = match model.meta.vis_units {
+            VisUnit::Uncalib => None,
+            units => Some(units),
+        };
+        Ok(uvcal)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StefCal;
+    use crate::test_data::read_unflagged;
+    use crate::{base::VisUnit, UVCal, UVData};
+    use num_complex::Complex;
+
+    /// Gains varying by antenna, channel and Jones element, with zero phase
+    /// on the first antenna.
+    fn true_gains(model: &UVData<f64, f32>) -> UVCal {
+        let mut truth = UVCal::from_uvdata(model).unwrap();
+        truth
+            .gain_array
+            .as_mut()
+            .unwrap()
+            .indexed_iter_mut()
+            .for_each(|((ant, freq, _, jones), gain)| {
+                let phase = match ant {
+                    0 => 0.0,
+                    _ => 0.3 * ant as f64 - 0.2 * freq as f64 + jones as f64,
+                };
+                *gain = Complex::from_polar(1.0 + 0.1 * ant as f64 + 0.05 * freq as f64, phase);
+            });
+        truth
+    }
+
+    #[test]
+    fn recovers_gains() {
+        let mut model = read_unflagged();
+        model.meta.vis_units = VisUnit::Jansky;
+        let truth = true_gains(&model);
+        let mut data = model.clone();
+        data.uncalibrate(&truth).unwrap();
+
+        let opts = StefCal {
+            time_interval: 20,
+            ..Default::default()
+        };
+        let uvcal = data.stefcal(&model, &opts).expect("Cannot solve.");
+        assert_eq!(uvcal.gain_scale, Some(VisUnit::Jansky));
+        // the model is joined on baseline and time, so its blt order is free
+        // and its times may differ in the last ulp
+        let mut reversed = model.clone();
+        reversed.take_blts(&(0..model.meta.nblts as usize).rev().collect::<Vec<_>>());
+        reversed
+            .meta_arrays
+            .time_array
+            .mapv_inplace(|time| f64::from_bits(time.to_bits() + 1));
+        assert_eq!(
+            data.stefcal(&reversed, &opts).unwrap().gain_array,
+            uvcal.gain_array
+        );
+        assert!(uvcal.flag_array.iter().all(|&flag| !flag));
+        for (gain, expected) in uvcal
+            .gain_array
+            .as_ref()
+            .unwrap()
+            .iter()
+            .zip(truth.gain_array.as_ref().unwrap().iter())
+        {
+            assert_abs_diff_eq!(gain.re, expected.re, epsilon = 1e-6);
+            assert_abs_diff_eq!(gain.im, expected.im, epsilon = 1e-6);
+        }
+
+        data.calibrate(&uvcal).unwrap();
+        for (vis, expected) in data
+            .data_array
+            .as_ref()
+            .unwrap()
+            .iter()
+            .zip(model.data_array.as_ref().unwrap().iter())
+        {
+            assert_abs_diff_eq!(
+                vis.re,
+                expected.re,
+                epsilon = 1e-6 * expected.norm().max(1.0)
+            );
+            assert_abs_diff_eq!(
+                vis.im,
+                expected.im,
+                epsilon = 1e-6 * expected.norm().max(1.0)
+            );
+        }
+    }
+
+    #[test]
+    fn weights_and_flags() {
+        let model = read_unflagged();
+        let truth = true_gains(&model);
+        let mut data = model.clone();
+        data.uncalibrate(&truth).unwrap();
+        // a corrupted baseline is ignored once flagged or given no weight
+        let blts = data.antpair_blts(1, 2);
+        for &blt in blts.iter().take(10) {
+            data.data_array.as_mut().unwrap()[[blt, 0, 0]] = Complex::new(1e3, 0.0);
+            data.flag_array.as_mut().unwrap()[[blt, 0, 0]] = true;
+        }
+        for &blt in blts.iter().skip(10) {
+            data.data_array.as_mut().unwrap()[[blt, 0, 0]] = Complex::new(1e3, 0.0);
+            data.nsample_array.as_mut().unwrap()[[blt, 0, 0]] = 0.0;
+        }
+        // antenna 11 is flagged at the first time
+        let first_time = data.meta_arrays.time_array[0];
+        for blt in 0..data.meta.nblts as usize {
+            let ants = [
+                data.meta_arrays.ant_1_array[blt],
+                data.meta_arrays.ant_2_array[blt],
+            ];
+            if ants.contains(&11) && data.meta_arrays.time_array[blt] == first_time {
+                data.flag_array.as_mut().unwrap()[[blt, 1, 0]] = true;
+            }
+        }
+
+        let uvcal = data.stefcal(&model, &StefCal::default()).unwrap();
+        let gains = uvcal.gain_array.as_ref().unwrap();
+        let expected = truth.gain_array.as_ref().unwrap();
+        for ant in 0..4 {
+            assert_abs_diff_eq!(
+                gains[[ant, 0, 4, 0]].re,
+                expected[[ant, 0, 4, 0]].re,
+                epsilon = 1e-6
+            );
+            assert_abs_diff_eq!(
+                gains[[ant, 0, 4, 0]].im,
+                expected[[ant, 0, 4, 0]].im,
+                epsilon = 1e-6
+            );
+        }
+        let time = uvcal.time_index(first_time).unwrap();
+        assert!(uvcal.flag_array[[3, 1, time, 0]]);
+        assert_eq!(uvcal.flag_array.iter().filter(|&&flag| flag).count(), 1);
+
+        let mut bad = model.clone();
+        bad.select_polarizations(&[-5]).unwrap();
+        assert!(data.stefcal(&bad, &StefCal::default()).is_err());
+        let mut bad = model.clone();
+        bad.meta_arrays.freq_array[2] += 1e5;
+        assert!(data.stefcal(&bad, &StefCal::default()).is_err());
+        let mut bad = model.clone();
+        bad.select_blts(&[0, 1, 2]).unwrap();
+        assert!(data.stefcal(&bad, &StefCal::default()).is_err());
+    }
+}
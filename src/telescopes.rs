@@ -0,0 +1,329 @@
+use approx::AbsDiffEq;
+use ndarray::{Array, Ix1};
+use num_traits::Float;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+use super::layout::AntennaLayout;
+use super::utils;
+use super::UVData;
+
+/// A telescope as written in a registry file.
+///
+/// The location is given either as `location` (ECEF meters) or as
+/// `latitude`/`longitude` (degrees) and `altitude` (meters).
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TelescopeSpec {
+    pub name: String,
+    pub location: Option<[f64; 3]>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub altitude: Option<f64>,
+    pub antenna_diameter: Option<f64>,
+    pub antenna_layout: Option<AntennaLayout>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Telescope {
+    pub name: String,
+    /// ECEF location in meters.
+    pub location: [f64; 3],
+    /// Default antenna (dish or tile) diameter in meters.
+    pub antenna_diameter: Option<f64>,
+    pub antenna_layout: Option<AntennaLayout>,
+}
+
+impl Telescope {
+    pub fn from_spec(spec: TelescopeSpec) -> Result<Telescope, String> {
+        let location = match (spec.location, spec.latitude, spec.longitude, spec.altitude) {
+            (Some(location), _, _, _) => location,
+            (None, Some(lat), Some(lon), Some(alt)) => utils::xyz_from_latlonalt(lat, lon, alt),
+            _ => {
+                return Err(format!(
+                    "Telescope {} needs a location or a latitude, longitude and altitude.",
+                    spec.name
+                ))
+            }
+        };
+        if let Some(layout) = &spec.antenna_layout {
+            layout
+                .check()
+                .map_err(|err| format!("Telescope {}: {}", spec.name, err))?;
+        }
+        Ok(Telescope {
+            name: spec.name,
+            location,
+            antenna_diameter: spec.antenna_diameter,
+            antenna_layout: spec.antenna_layout,
+        })
+    }
+
+    fn from_latlonalt(name: &str, lla: [f64; 3], antenna_diameter: Option<f64>) -> Telescope {
+        Telescope {
+            name: name.to_string(),
+            location: utils::xyz_from_latlonalt(lla[0], lla[1], lla[2]),
+            antenna_diameter,
+            antenna_layout: None,
+        }
+    }
+}
+
+// files hold a list of telescopes under the `telescope` key
+#[derive(Deserialize)]
+struct TelescopeFile {
+    telescope: Vec<TelescopeSpec>,
+}
+
+/// Telescopes known by name (case insensitive).
+#[derive(Debug, PartialEq, Clone)]
+pub struct TelescopeRegistry {
+    pub telescopes: BTreeMap<String, Telescope>,
+}
+
+impl TelescopeRegistry {
+    /// A registry of the built-in telescopes.
+    pub fn new() -> TelescopeRegistry {
+        // latitude, longitude (degrees), altitude (meters)
+        let known = [
+            (
+                "HERA",
+                [-30.72152612068925, 21.42830382686301, 1051.69],
+                Some(14.0),
+            ),
+            (
+                "PAPER",
+                [-30.72152612068925, 21.42830382686301, 1051.69],
+                Some(2.0),
+            ),
+            (
+                "MWA",
+                [-26.70331940556, 116.67081523611, 377.827],
+                Some(4.0),
+            ),
+            ("LWA", [34.068956, -107.628342, 2133.0], Some(3.0)),
+            ("OVRO-LWA", [37.2397808, -118.2816819, 1183.48], Some(2.0)),
+            ("ATA", [40.81743, -121.47074, 1019.222], Some(6.1)),
+            (
+                "SMA",
+                [19.82420526389, -155.47752299722, 4083.948144],
+                Some(6.0),
+            ),
+            ("GBT", [38.4331291, -79.8398397, 807.43], Some(100.0)),
+            ("MeerKAT", [-30.7110, 21.4438, 1038.0], Some(13.5)),
+        ];
+        let mut telescopes: BTreeMap<String, Telescope> = known
+            .iter()
+            .map(|(name, lla, diameter)| {
+                (
+                    name.to_uppercase(),
+                    Telescope::from_latlonalt(name, *lla, *diameter),
+                )
+            })
+            .collect();
+        telescopes.insert(
+            "VLA".to_string(),
+            Telescope {
+                name: "VLA".to_string(),
+                location: [-1601185.4, -5041977.5, 3554875.9],
+                antenna_diameter: Some(25.0),
+                antenna_layout: None,
+            },
+        );
+        TelescopeRegistry { telescopes }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Telescope> {
+        self.telescopes.get(&name.trim().to_uppercase())
+    }
+
+    /// Add a telescope, replacing any of the same name.
+    pub fn insert(&mut self, telescope: Telescope) {
+        self.telescopes
+            .insert(telescope.name.trim().to_uppercase(), telescope);
+    }
+
+    /// Add the telescopes listed in a TOML or JSON file, chosen by extension.
+    ///
+    /// Telescopes are listed under a `telescope` key, e.g. `[[telescope]]`
+    /// tables in TOML.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let file: TelescopeFile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|err| err.to_string())?,
+            Some("json") => serde_json::from_str(&contents).map_err(|err| err.to_string())?,
+            _ => {
+                return Err(format!(
+                    "Telescope files must be .toml or .json: {}.",
+                    path.display()
+                ))
+            }
+        };
+        for spec in file.telescope {
+            self.insert(Telescope::from_spec(spec)?);
+        }
+        Ok(())
+    }
+}
+
+impl Default for TelescopeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Fill telescope metadata from the built-in registry using `meta.telescope_name`.
+    ///
+    /// See `set_telescope_params_from` for what is filled.
+    pub fn set_telescope_params(&mut self, overwrite: bool) -> Result<(), String> {
+        self.set_telescope_params_from(&TelescopeRegistry::new(), overwrite)
+    }
+
+    /// Fill telescope metadata from `registry` using `meta.telescope_name`.
+    ///
+    /// The telescope location, antenna diameters and (if the registry has a
+    /// layout) antenna names, numbers and positions are set when they are
+    /// missing, or always if `overwrite` is set.
+    pub fn set_telescope_params_from(
+        &mut self,
+        registry: &TelescopeRegistry,
+        overwrite: bool,
+    ) -> Result<(), String> {
+        let telescope = registry.get(&self.meta.telescope_name).ok_or(format!(
+            "Telescope {} is not in the registry.",
+            self.meta.telescope_name
+        ))?;
+
+        if overwrite || self.meta.telescope_location.iter().all(|&x| x == 0.0) {
+            self.meta.telescope_location = telescope.location;
+        }
+
+        let mut layout_diameters = false;
+        if let Some(layout) = &telescope.antenna_layout {
+            let missing = self.meta_arrays.antenna_numbers.is_empty()
+                || self.meta_arrays.antenna_positions.iter().all(|&x| x == 0.0);
+            if overwrite || missing {
+                self.set_antenna_layout(layout)?;
+                layout_diameters = layout.antenna_diameters.is_some();
+            }
+        }
+
+        if let Some(diameter) = telescope.antenna_diameter {
+            let arrays = &mut self.meta_arrays;
+            if (overwrite && !layout_diameters) || arrays.antenna_diameters.is_none() {
+                arrays.antenna_diameters = Some(Array::<f32, Ix1>::from_elem(
+                    self.meta.nants_telescope as usize,
+                    diameter as f32,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TelescopeRegistry;
+    use crate::test_data::read_metadata;
+    use std::{fs::File, io::Write};
+    use tempdir::TempDir;
+
+    #[test]
+    fn hera_location() {
+        let uvd = read_metadata();
+        let registry = TelescopeRegistry::new();
+        let hera = registry.get("hera").expect("HERA is not registered.");
+        for (x1, x2) in hera.location.iter().zip(uvd.meta.telescope_location.iter()) {
+            assert_abs_diff_eq!(x1, x2, epsilon = 1.0);
+        }
+        assert!(registry.get("ovro-lwa").is_some());
+        assert!(registry.get("Arecibo").is_none());
+    }
+
+    #[test]
+    fn fill_missing_params() {
+        let mut uvd = read_metadata();
+        let location = uvd.meta.telescope_location;
+        uvd.meta.telescope_location = [0.0; 3];
+        uvd.meta_arrays.antenna_diameters = None;
+        uvd.set_telescope_params(false)
+            .expect("Cannot set telescope params.");
+        for (x1, x2) in location.iter().zip(uvd.meta.telescope_location.iter()) {
+            assert_abs_diff_eq!(x1, x2, epsilon = 1.0);
+        }
+        let diameters = uvd.meta_arrays.antenna_diameters.as_ref().unwrap();
+        assert_eq!(diameters.len(), uvd.meta.nants_telescope as usize);
+        assert_abs_diff_eq!(diameters[0], 14.0);
+
+        // existing values are kept unless overwriting
+        uvd.meta.telescope_location = location;
+        uvd.set_telescope_params(false)
+            .expect("Cannot set telescope params.");
+        assert_eq!(uvd.meta.telescope_location, location);
+
+        uvd.meta.telescope_name = "nowhere".to_string();
+        assert!(uvd.set_telescope_params(true).is_err());
+    }
+
+    #[test]
+    fn custom_telescopes() {
+        let tmp_dir = TempDir::new("telescopes").unwrap();
+        let toml_path = tmp_dir.path().join("telescopes.toml");
+        let mut file = File::create(&toml_path).unwrap();
+        writeln!(
+            file,
+            r#"
+[[telescope]]
+name = "Backyard"
+latitude = 10.0
+longitude = 20.0
+altitude = 100.0
+antenna_diameter = 3.0
+
+[telescope.antenna_layout]
+antenna_names = ["A0", "A1", "A2", "A11"]
+antenna_numbers = [0, 1, 2, 11]
+antenna_positions = [[0.0, 0.0, 0.0], [1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [0.0, 5.0, 0.0]]
+"#
+        )
+        .unwrap();
+        drop(file);
+
+        let json_path = tmp_dir.path().join("telescopes.json");
+        let mut file = File::create(&json_path).unwrap();
+        writeln!(
+            file,
+            r#"{{"telescope": [{{"name": "HERA", "location": [1.0, 2.0, 3.0]}}]}}"#
+        )
+        .unwrap();
+        drop(file);
+
+        let mut registry = TelescopeRegistry::new();
+        registry.load_file(&toml_path).expect("Cannot load toml.");
+        registry.load_file(&json_path).expect("Cannot load json.");
+        assert_eq!(registry.get("HERA").unwrap().location, [1.0, 2.0, 3.0]);
+        assert!(registry
+            .load_file(tmp_dir.path().join("telescopes.yaml"))
+            .is_err());
+
+        let mut uvd = read_metadata();
+        uvd.meta.telescope_name = "backyard".to_string();
+        uvd.set_telescope_params_from(&registry, true)
+            .expect("Cannot set telescope params.");
+        assert_eq!(uvd.meta.nants_telescope, 4);
+        assert_eq!(uvd.meta_arrays.antenna_positions[[1, 2]], 3.0);
+        assert_eq!(
+            uvd.meta_arrays.antenna_diameters.as_ref().unwrap().to_vec(),
+            vec![3.0; 4]
+        );
+        let (lat, lon, alt) = uvd.telescope_location_latlonalt_degrees();
+        assert_abs_diff_eq!(lat, 10.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(lon, 20.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(alt, 100.0, epsilon = 1e-3);
+    }
+}
@@ -0,0 +1,514 @@
+use approx::AbsDiffEq;
+use ndarray::{Array, Ix1};
+use num_traits::Float;
+use std::{f64::consts::PI, fs, path::Path, str::FromStr};
+
+use super::utils;
+use super::UVData;
+
+/// Offset between the Julian Date and the Modified Julian Date.
+pub const MJD_OFFSET: f64 = 2_400_000.5;
+/// Julian Date of the Unix epoch, 1970-01-01T00:00:00 UTC.
+pub const UNIX_EPOCH_JD: f64 = 2_440_587.5;
+/// Julian Date of the GPS epoch, 1980-01-06T00:00:00 UTC.
+pub const GPS_EPOCH_JD: f64 = 2_444_244.5;
+/// TT - TAI in seconds.
+pub const TT_MINUS_TAI: f64 = 32.184;
+/// TAI - GPS in seconds.
+pub const TAI_MINUS_GPS: f64 = 19.0;
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+// (MJD at which the offset starts, TAI - UTC in seconds), from the IERS
+// Leap_Second.dat bulletin.
+const LEAP_SECONDS: [(f64, f64); 28] = [
+    (41317.0, 10.0),
+    (41499.0, 11.0),
+    (41683.0, 12.0),
+    (42048.0, 13.0),
+    (42413.0, 14.0),
+    (42778.0, 15.0),
+    (43144.0, 16.0),
+    (43509.0, 17.0),
+    (43874.0, 18.0),
+    (44239.0, 19.0),
+    (44786.0, 20.0),
+    (45151.0, 21.0),
+    (45516.0, 22.0),
+    (46247.0, 23.0),
+    (47161.0, 24.0),
+    (47892.0, 25.0),
+    (48257.0, 26.0),
+    (48804.0, 27.0),
+    (49169.0, 28.0),
+    (49534.0, 29.0),
+    (50083.0, 30.0),
+    (50630.0, 31.0),
+    (51179.0, 32.0),
+    (53736.0, 33.0),
+    (54832.0, 34.0),
+    (56109.0, 35.0),
+    (57204.0, 36.0),
+    (57754.0, 37.0),
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TimeScale {
+    Utc,
+    Tai,
+    Tt,
+    Ut1,
+    Gps,
+}
+
+impl FromStr for TimeScale {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<TimeScale, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "utc" => Ok(TimeScale::Utc),
+            "tai" | "iat" => Ok(TimeScale::Tai),
+            "tt" | "tdt" => Ok(TimeScale::Tt),
+            "ut1" => Ok(TimeScale::Ut1),
+            "gps" => Ok(TimeScale::Gps),
+            other => Err(format!("Unknown time scale: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for TimeScale {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_uppercase())
+    }
+}
+
+/// Table of TAI - UTC offsets.
+///
+/// Dates before the first entry use the first offset.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LeapSeconds {
+    /// (MJD in UTC at which the offset starts, TAI - UTC in seconds), sorted.
+    pub table: Vec<(f64, f64)>,
+}
+
+impl LeapSeconds {
+    /// The table bundled with this crate.
+    pub fn new() -> LeapSeconds {
+        LeapSeconds {
+            table: LEAP_SECONDS.to_vec(),
+        }
+    }
+
+    /// Read a table in the format of the IERS `Leap_Second.dat` file.
+    ///
+    /// Lines starting with `#` are skipped, and each other line holds the MJD
+    /// of the change first and TAI - UTC in seconds last.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<LeapSeconds, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let mut table = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let parse = |field: &str| -> Result<f64, String> {
+                field
+                    .parse::<f64>()
+                    .map_err(|_| format!("Cannot parse leap second line: {}", line))
+            };
+            table.push((parse(fields[0])?, parse(fields[fields.len() - 1])?));
+        }
+        if table.is_empty() {
+            return Err("No leap seconds found in file.".to_string());
+        }
+        table.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap());
+        Ok(LeapSeconds { table })
+    }
+
+    /// TAI - UTC in seconds at the UTC Julian Date `jd`.
+    pub fn tai_minus_utc(&self, jd: f64) -> f64 {
+        let mjd = jd - MJD_OFFSET;
+        self.table
+            .iter()
+            .rev()
+            .find(|(start, _)| mjd >= *start)
+            .unwrap_or(&self.table[0])
+            .1
+    }
+
+    pub fn utc_to_tai(&self, jd: f64) -> f64 {
+        jd + self.tai_minus_utc(jd) / SECONDS_PER_DAY
+    }
+
+    pub fn tai_to_utc(&self, jd: f64) -> f64 {
+        // the offset in effect at the UTC time, which is at most one step earlier
+        let guess = jd - self.tai_minus_utc(jd) / SECONDS_PER_DAY;
+        let utc = jd - self.tai_minus_utc(guess) / SECONDS_PER_DAY;
+        jd - self.tai_minus_utc(utc) / SECONDS_PER_DAY
+    }
+
+    /// Convert the Julian Date `jd` from the time scale `from` to `to`.
+    ///
+    /// `dut1` (UT1 - UTC in seconds) is needed to convert to or from UT1.
+    pub fn convert(
+        &self,
+        jd: f64,
+        from: TimeScale,
+        to: TimeScale,
+        dut1: Option<f64>,
+    ) -> Result<f64, String> {
+        if from == to {
+            return Ok(jd);
+        }
+        let need_dut1 = || dut1.ok_or("dut1 is needed to convert to or from UT1.".to_string());
+        let tai = match from {
+            TimeScale::Tai => jd,
+            TimeScale::Utc => self.utc_to_tai(jd),
+            TimeScale::Tt => jd - TT_MINUS_TAI / SECONDS_PER_DAY,
+            TimeScale::Gps => jd + TAI_MINUS_GPS / SECONDS_PER_DAY,
+            TimeScale::Ut1 => self.utc_to_tai(jd - need_dut1()? / SECONDS_PER_DAY),
+        };
+        Ok(match to {
+            TimeScale::Tai => tai,
+            TimeScale::Utc => self.tai_to_utc(tai),
+            TimeScale::Tt => tai + TT_MINUS_TAI / SECONDS_PER_DAY,
+            TimeScale::Gps => tai - TAI_MINUS_GPS / SECONDS_PER_DAY,
+            TimeScale::Ut1 => self.tai_to_utc(tai) + need_dut1()? / SECONDS_PER_DAY,
+        })
+    }
+
+    /// GPS seconds (as used for MWA observation ids) of the UTC Julian Date `jd`.
+    pub fn gps_seconds_from_jd(&self, jd: f64) -> f64 {
+        let gps = self.utc_to_tai(jd) - TAI_MINUS_GPS / SECONDS_PER_DAY;
+        (gps - GPS_EPOCH_JD) * SECONDS_PER_DAY
+    }
+
+    /// UTC Julian Date of the GPS seconds `seconds`.
+    pub fn jd_from_gps_seconds(&self, seconds: f64) -> f64 {
+        let tai = GPS_EPOCH_JD + (seconds + TAI_MINUS_GPS) / SECONDS_PER_DAY;
+        self.tai_to_utc(tai)
+    }
+}
+
+impl Default for LeapSeconds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Convert a Julian Date between time scales with the bundled leap second table.
+pub fn convert_time(
+    jd: f64,
+    from: TimeScale,
+    to: TimeScale,
+    dut1: Option<f64>,
+) -> Result<f64, String> {
+    LeapSeconds::new().convert(jd, from, to, dut1)
+}
+
+pub fn mjd_from_jd(jd: f64) -> f64 {
+    jd - MJD_OFFSET
+}
+
+pub fn jd_from_mjd(mjd: f64) -> f64 {
+    mjd + MJD_OFFSET
+}
+
+/// Unix seconds of a UTC Julian Date. Like Unix time, leap seconds are not counted.
+pub fn unix_from_jd(jd: f64) -> f64 {
+    (jd - UNIX_EPOCH_JD) * SECONDS_PER_DAY
+}
+
+pub fn jd_from_unix(seconds: f64) -> f64 {
+    seconds / SECONDS_PER_DAY + UNIX_EPOCH_JD
+}
+
+// days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// proleptic Gregorian (year, month, day) of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Format a Julian Date as an ISO-8601 string with millisecond precision.
+pub fn iso_from_jd(jd: f64) -> String {
+    let millis = ((jd - UNIX_EPOCH_JD) * SECONDS_PER_DAY * 1e3).round() as i64;
+    let days = millis.div_euclid(86_400_000);
+    let day_millis = millis.rem_euclid(86_400_000);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        day_millis / 3_600_000,
+        day_millis / 60_000 % 60,
+        day_millis / 1000 % 60,
+        day_millis % 1000
+    )
+}
+
+/// Parse an ISO-8601 date ("2020-01-31", "2020-01-31T12:30:00.5Z") to a Julian Date.
+pub fn jd_from_iso(input: &str) -> Result<f64, String> {
+    let err = || format!("Cannot parse ISO-8601 time: {}.", input);
+    let trimmed = input.trim().trim_end_matches('Z');
+    let (date, time) = match trimmed.find(['T', ' ']) {
+        Some(ind) => (&trimmed[..ind], &trimmed[ind + 1..]),
+        None => (trimmed, ""),
+    };
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|field| field.parse::<i64>().map_err(|_| err()))
+        .collect::<Result<_, _>>()?;
+    // the day must exist in its month, so it survives a round trip
+    if date.len() != 3
+        || !(1..=12).contains(&date[1])
+        || date[2] < 1
+        || civil_from_days(days_from_civil(date[0], date[1], date[2]))
+            != (date[0], date[1], date[2])
+    {
+        return Err(err());
+    }
+    let time: Vec<f64> = match time.is_empty() {
+        true => vec![],
+        false => time
+            .split(':')
+            .map(|field| field.parse::<f64>().map_err(|_| err()))
+            .collect::<Result<_, _>>()?,
+    };
+    // hours, minutes and seconds, where a leap second runs to 60.999...
+    let in_range = time
+        .iter()
+        .zip([24.0, 60.0, 61.0].iter())
+        .all(|(&value, &limit)| (0.0..limit).contains(&value));
+    if time.len() > 3 || !in_range {
+        return Err(err());
+    }
+    let seconds = time
+        .iter()
+        .zip([3600.0, 60.0, 1.0].iter())
+        .map(|(value, scale)| value * scale)
+        .sum::<f64>();
+    let days = days_from_civil(date[0], date[1], date[2]) as f64;
+    Ok(UNIX_EPOCH_JD + days + seconds / SECONDS_PER_DAY)
+}
+
+/// Apparent local sidereal time in radians of a UTC Julian Date at east
+/// `longitude` (radians), with `dut1` = UT1 - UTC in seconds if known.
+///
+/// Uses the IAU 2006 Earth rotation angle and the leading nutation terms
+/// of the equation of the equinoxes, good to well under an arcsecond.
+pub fn lst_from_jd(jd: f64, longitude: f64, dut1: Option<f64>) -> f64 {
+    let ut1 = jd + dut1.unwrap_or(0.0) / SECONDS_PER_DAY - 2_451_545.0;
+    let era = 2.0 * PI * (0.779_057_273_264 + 1.002_737_811_911_354_5 * ut1);
+    let cent = ut1 / 36_525.0;
+    let arcsec = (PI / 180.0) / 3600.0;
+    let gmst = era
+        + (0.014_506 + 4_612.156_534 * cent + 1.391_581_7 * cent.powi(2)
+            - 0.000_000_44 * cent.powi(3))
+            * arcsec;
+
+    let node = (125.044_52 - 1_934.136_261 * cent).to_radians();
+    let sun = (280.466_5 + 36_000.769_8 * cent).to_radians();
+    let moon = (218.316_5 + 481_267.881_3 * cent).to_radians();
+    let nutation = -17.20 * node.sin() - 1.32 * (2.0 * sun).sin() - 0.23 * (2.0 * moon).sin()
+        + 0.21 * (2.0 * node).sin();
+    let obliquity = (23.439_291 - 0.013_004_2 * cent).to_radians();
+    utils::wrap_2pi(gmst + nutation * arcsec * obliquity.cos() + longitude)
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// The time scale of `time_array`, from `meta.timesys` (UTC if unset).
+    pub fn time_scale(&self) -> Result<TimeScale, String> {
+        match &self.meta.timesys {
+            Some(timesys) => TimeScale::from_str(timesys),
+            None => Ok(TimeScale::Utc),
+        }
+    }
+
+    /// `time_array` converted to the time scale `to`, using `meta.dut1` for UT1.
+    pub fn convert_time_array(
+        &self,
+        to: TimeScale,
+        leap_seconds: &LeapSeconds,
+    ) -> Result<Array<f64, Ix1>, String> {
+        let from = self.time_scale()?;
+        let dut1 = self.meta.dut1.map(f64::from);
+        self.meta_arrays
+            .time_array
+            .iter()
+            .map(|&jd| leap_seconds.convert(jd, from, to, dut1))
+            .collect::<Result<Vec<f64>, String>>()
+            .map(Array::from_vec)
+    }
+
+    /// Recompute `lst_array` from `time_array` and the telescope longitude.
+    pub fn set_lsts_from_time_array(&mut self) {
+        let lon = self.telescope_location_latlonalt().1;
+        let dut1 = self.meta.dut1.map(f64::from);
+        self.meta_arrays.lst_array = self
+            .meta_arrays
+            .time_array
+            .mapv(|jd| lst_from_jd(jd, lon, dut1));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_data::read_metadata;
+    use std::{fs::File, io::Write};
+    use tempdir::TempDir;
+
+    #[test]
+    fn scale_from_str() {
+        assert_eq!(TimeScale::from_str(" utc").unwrap(), TimeScale::Utc);
+        assert_eq!(TimeScale::Tt.to_string(), "TT");
+        assert!(TimeScale::from_str("tcb").is_err());
+    }
+
+    #[test]
+    fn iso_roundtrip() {
+        let jd = jd_from_iso("2000-01-01T12:00:00Z").unwrap();
+        assert_abs_diff_eq!(jd, 2_451_545.0);
+        assert_eq!(iso_from_jd(jd), "2000-01-01T12:00:00.000");
+        assert_abs_diff_eq!(jd_from_iso("1858-11-17").unwrap(), MJD_OFFSET);
+        assert_eq!(iso_from_jd(2_459_000.75), "2020-05-31T06:00:00.000");
+        assert!(jd_from_iso("2020-13-01").is_err());
+        assert!(jd_from_iso("yesterday").is_err());
+        assert!(jd_from_iso("2021-02-31").is_err());
+        assert!(jd_from_iso("2021-02-29").is_err());
+        assert!(jd_from_iso("2021-04-31").is_err());
+        assert!(jd_from_iso("2021-01-00").is_err());
+        assert!(jd_from_iso("2021-01-01T24:00:00").is_err());
+        assert!(jd_from_iso("2021-01-01T12:60:00").is_err());
+        assert!(jd_from_iso("2021-01-01T12:00:61").is_err());
+        assert!(jd_from_iso("2021-01-01T-1:00:00").is_err());
+        assert_abs_diff_eq!(
+            jd_from_iso("2020-02-29").unwrap(),
+            jd_from_iso("2020-03-01").unwrap() - 1.0
+        );
+        assert!(jd_from_iso("2016-12-31T23:59:60.5").is_ok());
+
+        assert_abs_diff_eq!(
+            unix_from_jd(jd_from_iso("2000-01-01").unwrap()),
+            946_684_800.0
+        );
+        assert_abs_diff_eq!(mjd_from_jd(jd_from_mjd(58_000.25)), 58_000.25);
+    }
+
+    #[test]
+    fn time_scales() {
+        let leaps = LeapSeconds::new();
+        let jd = jd_from_iso("2017-01-01T00:00:00").unwrap();
+        assert_abs_diff_eq!(leaps.tai_minus_utc(jd), 37.0);
+        assert_abs_diff_eq!(leaps.tai_minus_utc(jd - 1e-5), 36.0);
+        let tt = convert_time(jd, TimeScale::Utc, TimeScale::Tt, None).unwrap();
+        assert_abs_diff_eq!((tt - jd) * 86_400.0, 69.184, epsilon = 1e-4);
+        let ut1 = convert_time(jd, TimeScale::Utc, TimeScale::Ut1, Some(0.5)).unwrap();
+        assert_abs_diff_eq!((ut1 - jd) * 86_400.0, 0.5, epsilon = 1e-4);
+        assert!(convert_time(jd, TimeScale::Utc, TimeScale::Ut1, None).is_err());
+
+        // every conversion inverts, including just after a leap second
+        let scales = [
+            TimeScale::Utc,
+            TimeScale::Tai,
+            TimeScale::Tt,
+            TimeScale::Ut1,
+            TimeScale::Gps,
+        ];
+        for &time in [jd + 1e-6, jd + 0.3, 2_450_000.5].iter() {
+            for &from in scales.iter() {
+                for &to in scales.iter() {
+                    let there = leaps.convert(time, from, to, Some(-0.2)).unwrap();
+                    let back = leaps.convert(there, to, from, Some(-0.2)).unwrap();
+                    assert_abs_diff_eq!(back, time, epsilon = 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gps_seconds() {
+        let leaps = LeapSeconds::new();
+        let jd = jd_from_iso("2017-01-01T00:00:00").unwrap();
+        assert_abs_diff_eq!(
+            leaps.gps_seconds_from_jd(jd),
+            1_167_264_018.0,
+            epsilon = 1e-4
+        );
+        assert_abs_diff_eq!(
+            leaps.jd_from_gps_seconds(1_167_264_018.0),
+            jd,
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(leaps.gps_seconds_from_jd(GPS_EPOCH_JD), 0.0, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn leap_seconds_from_file() {
+        let tmp_dir = TempDir::new("leap_seconds").unwrap();
+        let path = tmp_dir.path().join("Leap_Second.dat");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "#  MJD        Date        TAI-UTC (s)").unwrap();
+        writeln!(file, "    41317.0    1  1 1972       10").unwrap();
+        writeln!(file, "    57754.0    1  1 2017       37").unwrap();
+        writeln!(file, "    62000.0    1  1 2028       38").unwrap();
+        drop(file);
+
+        let leaps = LeapSeconds::from_file(&path).unwrap();
+        assert_eq!(leaps.table.len(), 3);
+        assert_abs_diff_eq!(leaps.tai_minus_utc(jd_from_mjd(62_001.0)), 38.0);
+        assert_abs_diff_eq!(leaps.tai_minus_utc(jd_from_mjd(50_000.0)), 10.0);
+        assert!(LeapSeconds::from_file(tmp_dir.path().join("missing.dat")).is_err());
+    }
+
+    #[test]
+    fn uvdata_time_array() {
+        let uvd = read_metadata();
+        let tai = uvd
+            .convert_time_array(TimeScale::Tai, &LeapSeconds::new())
+            .expect("Cannot convert.");
+        // the test data were taken in 2019, when TAI - UTC was 37 s
+        let expected = uvd.meta_arrays.time_array + 37.0 / 86_400.0;
+        assert!(tai.abs_diff_eq(&expected, 1e-9));
+    }
+
+    #[test]
+    fn sidereal_time() {
+        // GMST at J2000.0 is 18h41m50.548s, and apparent differs by about -1 s
+        let gmst = 18.697_374_558 * PI / 12.0;
+        assert_abs_diff_eq!(lst_from_jd(2_451_545.0, 0.0, None), gmst, epsilon = 1e-4);
+
+        let mut uvd = read_metadata();
+        let lsts = uvd.meta_arrays.lst_array.clone();
+        uvd.set_lsts_from_time_array();
+        // without UT1 - UTC the lsts agree to a fraction of a second of time
+        assert!(uvd.meta_arrays.lst_array.abs_diff_eq(&lsts, 1e-4));
+    }
+}
@@ -1,11 +1,22 @@
 use ndarray::{array, azip, Array, Ix1, Ix2};
 use num_traits::{cast::FromPrimitive, Float, PrimInt};
+use std::collections::HashMap;
+
+use super::base::{Orientation, Polarization};
 
 const GPS_A: f64 = 6378137f64;
 const GPS_B: f64 = 6356752.31424518;
 const E2: f64 = 6.69437999014e-3;
 const EP2: f64 = 6.73949674228e-3;
 
+pub const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+// times closer than this (days) are treated as the same integration
+pub(crate) const TIME_TOL: f64 = 1e-6;
+// frequencies closer than this (Hz) are treated as the same channel
+pub(crate) const FREQ_TOL: f64 = 1.0;
+// ratio of a solar day to a sidereal day
+pub const SIDEREAL_RATE: f64 = 1.002_737_909_350_795;
+
 pub fn xyz_from_latlonalt<T: Float + FromPrimitive>(lat: T, lon: T, alt: T) -> [T; 3] {
     let gps_a: T = T::from_f64(GPS_A).unwrap();
     let gps_b: T = T::from_f64(GPS_B).unwrap();
@@ -190,6 +201,227 @@ where
     rotecef.dot(&rot_mat.t()).to_owned()
 }
 
+/// Project an ENU baseline onto the uvw frame of a source at the given
+/// hour angle and declination, for an array at latitude `lat` (all radians).
+pub fn uvw_from_enu<T: Float>(enu: [T; 3], hour_angle: T, dec: T, lat: T) -> [T; 3] {
+    // rotate ENU into the local equatorial (X towards the meridian) frame
+    let x = -lat.sin() * enu[1] + lat.cos() * enu[2];
+    let y = enu[0];
+    let z = lat.cos() * enu[1] + lat.sin() * enu[2];
+
+    let (sin_ha, cos_ha) = hour_angle.sin_cos();
+    let (sin_dec, cos_dec) = dec.sin_cos();
+    [
+        sin_ha * x + cos_ha * y,
+        -sin_dec * cos_ha * x + sin_dec * sin_ha * y + cos_dec * z,
+        cos_dec * cos_ha * x - cos_dec * sin_ha * y + sin_dec * z,
+    ]
+}
+
+/// Wrap an angle in radians into [0, 2pi).
+pub fn wrap_2pi<T: Float + FromPrimitive>(angle: T) -> T {
+    let two_pi = T::from_f64(2.0 * std::f64::consts::PI).unwrap();
+    let wrapped = angle % two_pi;
+    match wrapped < T::zero() {
+        true => wrapped + two_pi,
+        false => wrapped,
+    }
+}
+
+/// Median of the values, NaN if there are none.
+pub(crate) fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    values.sort_by(|x1, x2| x1.total_cmp(x2));
+    let mid = values.len() / 2;
+    match values.len() % 2 {
+        0 => (values[mid - 1] + values[mid]) / 2.0,
+        _ => values[mid],
+    }
+}
+
+/// Convert a polarization string ("xx", "ee", "pI", ...) to its AIPS number.
+///
+/// E/N feed names need a known `x_orientation`.
+pub fn polstr2num(pol: &str, x_orientation: Orientation) -> Result<i8, String> {
+    Polarization::from_str_oriented(pol, x_orientation).map(|pol| pol.num())
+}
+
+/// Convert an AIPS polarization number to its name, using E/N feed names
+/// when `x_orientation` is known.
+pub fn polnum2str(num: i8, x_orientation: Orientation) -> Result<String, String> {
+    Polarization::from_num(num).map(|pol| pol.to_str_oriented(x_orientation))
+}
+
+/// Convert a Jones element string ("Jxx", "jee", "rr", ...) to its number.
+pub fn jstr2num(jones: &str, x_orientation: Orientation) -> Result<i8, String> {
+    let trimmed = jones.trim_matches(char::is_whitespace).to_lowercase();
+    let name = trimmed.strip_prefix('j').unwrap_or(&trimmed);
+    match Polarization::from_str_oriented(name, x_orientation)?.num() {
+        num @ -8..=-1 => Ok(num),
+        _ => Err(format!("Unknown Jones element: {}.", jones)),
+    }
+}
+
+/// Convert a Jones element number to its name ("Jxx", "Jee", ...).
+pub fn jnum2str(num: i8, x_orientation: Orientation) -> Result<String, String> {
+    match num {
+        -8..=-1 => polnum2str(num, x_orientation).map(|name| format!("J{}", name)),
+        other => Err(format!("Unknown Jones number: {}.", other)),
+    }
+}
+
+/// Groups of redundant baselines.
+///
+/// `vectors` holds the mean baseline vector of each group (Ngroups, 3) and
+/// `lengths` their norms. `conjugates` lists the baselines which only fit
+/// their group after being conjugated (vector negated).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedundantGroups {
+    pub baseline_groups: Vec<Vec<u32>>,
+    pub vectors: Array<f64, Ix2>,
+    pub lengths: Array<f64, Ix1>,
+    pub conjugates: Vec<u32>,
+}
+
+// a baseline needs conjugating if it points into the "negative" half space
+fn needs_conjugate(vec: [f64; 3], tol: f64) -> bool {
+    vec[0] < -tol
+        || (vec[0].abs() <= tol && vec[1] < -tol)
+        || (vec[0].abs() <= tol && vec[1].abs() <= tol && vec[2] < -tol)
+}
+
+/// Group baselines whose vectors (e.g. drift uvws) agree to within `tol` meters.
+///
+/// With `include_conjugates` baselines are also grouped with the negation
+/// of their vector, and are reported in `conjugates` when they were flipped.
+pub fn get_baseline_redundancies(
+    baselines: &Array<u32, Ix1>,
+    baseline_vecs: &Array<f64, Ix2>,
+    tol: f64,
+    include_conjugates: bool,
+) -> RedundantGroups {
+    let cell = |vec: &[f64; 3]| -> [i64; 3] {
+        [
+            (vec[0] / tol).floor() as i64,
+            (vec[1] / tol).floor() as i64,
+            (vec[2] / tol).floor() as i64,
+        ]
+    };
+
+    let mut baseline_groups: Vec<Vec<u32>> = Vec::new();
+    let mut seeds: Vec<[f64; 3]> = Vec::new();
+    let mut sums: Vec<[f64; 3]> = Vec::new();
+    let mut conjugates: Vec<u32> = Vec::new();
+    // spatial hash of the group seeds so each baseline only checks its neighbourhood
+    let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+
+    for (&baseline, vec) in baselines.iter().zip(baseline_vecs.outer_iter()) {
+        let mut vec = [vec[0], vec[1], vec[2]];
+        if include_conjugates && needs_conjugate(vec, tol) {
+            vec = [-vec[0], -vec[1], -vec[2]];
+            conjugates.push(baseline);
+        }
+        let center = cell(&vec);
+        let mut found: Option<usize> = None;
+        'search: for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let key = [center[0] + dx, center[1] + dy, center[2] + dz];
+                    if let Some(candidates) = grid.get(&key) {
+                        for &group in candidates {
+                            let seed = seeds[group];
+                            let dist = ((seed[0] - vec[0]).powi(2)
+                                + (seed[1] - vec[1]).powi(2)
+                                + (seed[2] - vec[2]).powi(2))
+                            .sqrt();
+                            if dist <= tol {
+                                found = Some(group);
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        match found {
+            Some(group) => {
+                baseline_groups[group].push(baseline);
+                for (sum, x) in sums[group].iter_mut().zip(vec.iter()) {
+                    *sum += x;
+                }
+            }
+            None => {
+                grid.entry(center).or_default().push(seeds.len());
+                baseline_groups.push(vec![baseline]);
+                seeds.push(vec);
+                sums.push(vec);
+            }
+        }
+    }
+
+    let mut vectors = Array::<f64, Ix2>::zeros((baseline_groups.len(), 3));
+    for ((mut row, sum), group) in vectors
+        .outer_iter_mut()
+        .zip(sums.iter())
+        .zip(baseline_groups.iter())
+    {
+        for (x, total) in row.iter_mut().zip(sum.iter()) {
+            *x = total / group.len() as f64;
+        }
+    }
+    let lengths = vectors.map_axis(ndarray::Axis(1), |vec| vec.mapv(|x| x.powi(2)).sum().sqrt());
+
+    RedundantGroups {
+        baseline_groups,
+        vectors,
+        lengths,
+        conjugates,
+    }
+}
+
+/// Find redundant baselines among all antenna pairs of an array.
+///
+/// `antenna_positions` should be ENU positions (see `UVData::get_enu_antpos`).
+/// Antenna pairs are ordered so that every baseline points into the same half
+/// space as the rest of its group, so no conjugation is needed.
+pub fn get_antenna_redundancies(
+    antenna_numbers: &Array<u32, Ix1>,
+    antenna_positions: &Array<f64, Ix2>,
+    tol: f64,
+    include_autos: bool,
+) -> RedundantGroups {
+    let mut ant1: Vec<u32> = Vec::new();
+    let mut ant2: Vec<u32> = Vec::new();
+    let mut vecs: Vec<f64> = Vec::new();
+    for (ind1, &num1) in antenna_numbers.iter().enumerate() {
+        for (ind2, &num2) in antenna_numbers.iter().enumerate().skip(ind1) {
+            if ind1 == ind2 && !include_autos {
+                continue;
+            }
+            let vec: Vec<f64> = (0..3)
+                .map(|axis| antenna_positions[[ind2, axis]] - antenna_positions[[ind1, axis]])
+                .collect();
+            match needs_conjugate([vec[0], vec[1], vec[2]], tol) {
+                true => {
+                    ant1.push(num2);
+                    ant2.push(num1);
+                    vecs.extend(vec.iter().map(|x| -x));
+                }
+                false => {
+                    ant1.push(num1);
+                    ant2.push(num2);
+                    vecs.extend(vec);
+                }
+            }
+        }
+    }
+    let baselines = antnums_to_baseline(&Array::from_vec(ant1), &Array::from_vec(ant2), false);
+    let vecs = Array::from_shape_vec((baselines.len(), 3), vecs).unwrap();
+    get_baseline_redundancies(&baselines, &vecs, tol, false)
+}
+
 #[cfg(test)]
 mod test {
 
@@ -197,6 +429,44 @@ mod test {
     use ndarray::{array, stack, Array, Axis};
     use std::{convert::TryInto, path::Path};
 
+    #[test]
+    fn pol_strings() {
+        assert_eq!(polstr2num("xx", Orientation::Unknown).unwrap(), -5);
+        assert_eq!(polstr2num("pI", Orientation::Unknown).unwrap(), 1);
+        assert_eq!(polstr2num(" V", Orientation::Unknown).unwrap(), 4);
+        assert_eq!(polstr2num("ee", Orientation::East).unwrap(), -5);
+        assert_eq!(polstr2num("ee", Orientation::North).unwrap(), -6);
+        assert_eq!(polstr2num("NE", Orientation::North).unwrap(), -7);
+        assert!(polstr2num("ee", Orientation::Unknown).is_err());
+        assert!(polstr2num("zz", Orientation::East).is_err());
+
+        assert_eq!(polnum2str(-5, Orientation::Unknown).unwrap(), "xx");
+        assert_eq!(polnum2str(-7, Orientation::East).unwrap(), "en");
+        assert_eq!(polnum2str(-6, Orientation::North).unwrap(), "ee");
+        assert_eq!(polnum2str(-1, Orientation::North).unwrap(), "rr");
+        assert_eq!(polnum2str(2, Orientation::East).unwrap(), "pQ");
+        assert!(polnum2str(0, Orientation::East).is_err());
+
+        for num in (-8..=-1).chain(1..=4) {
+            for orientation in [Orientation::East, Orientation::North, Orientation::Unknown] {
+                let name = polnum2str(num, orientation).unwrap();
+                assert_eq!(polstr2num(&name, orientation).unwrap(), num);
+            }
+        }
+    }
+
+    #[test]
+    fn jones_strings() {
+        assert_eq!(jstr2num("Jxx", Orientation::Unknown).unwrap(), -5);
+        assert_eq!(jstr2num("jnn", Orientation::East).unwrap(), -6);
+        assert_eq!(jstr2num("rl", Orientation::Unknown).unwrap(), -3);
+        assert!(jstr2num("pI", Orientation::Unknown).is_err());
+
+        assert_eq!(jnum2str(-5, Orientation::North).unwrap(), "Jnn");
+        assert_eq!(jnum2str(-2, Orientation::Unknown).unwrap(), "Jll");
+        assert!(jnum2str(1, Orientation::Unknown).is_err());
+    }
+
     #[test]
     fn xyz_from_lla() {
         let ref_latlonalt = [-26.7f64, 116.7f64, 377.8f64];
@@ -464,4 +734,67 @@ mod test {
 
         assert!(new_rot_ecef.abs_diff_eq(&rot_ecef, 1e-6))
     }
+
+    #[test]
+    fn uvw_zenith_is_enu() {
+        let lat = -30.7215261207f64.to_radians();
+        let enu = [14.6, -3.2, 0.4];
+        let uvw = uvw_from_enu(enu, 0.0, lat, lat);
+        for (x1, x2) in uvw.iter().zip(enu.iter()) {
+            assert_abs_diff_eq!(x1, x2, epsilon = 1e-10);
+        }
+    }
+
+    #[test]
+    fn baseline_redundancies() {
+        let baselines = array![1u32, 2, 3, 4];
+        let vecs = array![
+            [14.0, 0.0, 0.0],
+            [14.1, 0.05, 0.0],
+            [-14.0, 0.0, 0.0],
+            [28.0, 0.0, 0.0]
+        ];
+        let groups = get_baseline_redundancies(&baselines, &vecs, 1.0, false);
+        assert_eq!(groups.baseline_groups, vec![vec![1, 2], vec![3], vec![4]]);
+        assert!(groups.conjugates.is_empty());
+        assert_abs_diff_eq!(groups.lengths[2], 28.0, epsilon = 1e-10);
+
+        let groups = get_baseline_redundancies(&baselines, &vecs, 1.0, true);
+        assert_eq!(groups.baseline_groups, vec![vec![1, 2, 3], vec![4]]);
+        assert_eq!(groups.conjugates, vec![3]);
+        assert_abs_diff_eq!(
+            groups.vectors[[0, 0]],
+            14.1 / 3.0 + 28.0 / 3.0,
+            epsilon = 1e-10
+        );
+    }
+
+    #[test]
+    fn antenna_redundancies() {
+        let antnums = array![0u32, 1, 2, 3];
+        let positions = array![
+            [0.0, 0.0, 0.0],
+            [14.0, 0.0, 0.0],
+            [28.0, 0.0, 0.0],
+            [7.0, 12.12, 0.0]
+        ];
+        let groups = get_antenna_redundancies(&antnums, &positions, 0.5, false);
+        assert_eq!(
+            groups
+                .baseline_groups
+                .iter()
+                .map(|x| x.len())
+                .sum::<usize>(),
+            6
+        );
+        assert!(groups.conjugates.is_empty());
+        // (0, 1) and (1, 2) are the only redundant pair
+        let bls = antnums_to_baseline(&array![0u32, 1], &array![1u32, 2], false);
+        assert!(groups.baseline_groups.contains(&bls.to_vec()));
+        assert_eq!(groups.baseline_groups.len(), 5);
+
+        let with_autos = get_antenna_redundancies(&antnums, &positions, 0.5, true);
+        assert_eq!(with_autos.baseline_groups.len(), 6);
+        assert_eq!(with_autos.baseline_groups[0].len(), 4);
+    }
 }
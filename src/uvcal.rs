@@ -0,0 +1,867 @@
+use approx::AbsDiffEq;
+use hdf5::types::FixedAscii;
+use ndarray::{Array, Axis, Dimension, Ix1, Ix2, Ix3, Ix4, IxDyn};
+use num_complex::Complex;
+use num_traits::Float;
+use std::{collections::HashMap, f64::consts::PI, path::Path, str::FromStr};
+
+use super::base::{Orientation, VisUnit};
+use super::utils::{self, FREQ_TOL, TIME_TOL};
+use super::uvh5::{
+    fixed_ascii, read_scalar, read_string, write_scalar, Complexh5, MAX_HIST_LENGTH,
+};
+use super::UVData;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CalType {
+    Gain,
+    Delay,
+}
+
+impl FromStr for CalType {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<CalType, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "gain" => Ok(CalType::Gain),
+            "delay" => Ok(CalType::Delay),
+            other => Err(format!("Unknown calibration type: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for CalType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Whether calibrated data are the raw data divided or multiplied by the gains.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GainConvention {
+    Divide,
+    Multiply,
+}
+
+impl FromStr for GainConvention {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<GainConvention, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "divide" => Ok(GainConvention::Divide),
+            "multiply" => Ok(GainConvention::Multiply),
+            other => Err(format!("Unknown gain convention: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for GainConvention {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CalStyle {
+    Sky,
+    Redundant,
+}
+
+impl FromStr for CalStyle {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<CalStyle, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "sky" => Ok(CalStyle::Sky),
+            "redundant" => Ok(CalStyle::Redundant),
+            other => Err(format!("Unknown calibration style: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for CalStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// The Jones elements calibrating each feed of a polarization.
+fn jones_pair(pol: i8) -> Option<(i8, i8)> {
+    match pol {
+        -1 => Some((-1, -1)),
+        -2 => Some((-2, -2)),
+        -3 => Some((-1, -2)),
+        -4 => Some((-2, -1)),
+        -5 => Some((-5, -5)),
+        -6 => Some((-6, -6)),
+        -7 => Some((-5, -6)),
+        -8 => Some((-6, -5)),
+        _ => None,
+    }
+}
+
+fn gain_scale_str(unit: VisUnit) -> Option<&'static str> {
+    match unit {
+        VisUnit::Jansky => Some("Jy"),
+        VisUnit::Kelvinstr => Some("K str"),
+        VisUnit::Uncalib => None,
+    }
+}
+
+/// Read an antenna based array, squeezing the spectral window axis of
+/// older files.
+fn read_squeezed<T: hdf5::H5Type, D: Dimension>(
+    group: &hdf5::Group,
+    name: &str,
+) -> hdf5::Result<Option<Array<T, D>>> {
+    if !group.link_exists(name) {
+        return Ok(None);
+    }
+    let array = group.dataset(name)?.read_dyn::<T>()?;
+    let array: Array<T, IxDyn> = match array.ndim() == D::NDIM.unwrap_or(0) + 1 {
+        true => array.remove_axis(Axis(1)),
+        false => array,
+    };
+    Ok(Some(
+        array
+            .into_dimensionality::<D>()
+            .map_err(|err| err.to_string())?,
+    ))
+}
+
+/// Per-antenna calibration solutions, exchanged as calh5 files.
+///
+/// Gains have shape (Nants_data, Nfreqs, Ntimes, Njones); delays (seconds)
+/// have a single frequency axis entry, (Nants_data, 1, Ntimes, Njones).
+/// Flags and qualities match the shape of the solutions and the optional
+/// total quality drops the antenna axis.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UVCal {
+    pub cal_type: CalType,
+    pub cal_style: CalStyle,
+    pub gain_convention: GainConvention,
+    /// Units of data calibrated by these solutions.
+    pub gain_scale: Option<VisUnit>,
+    pub x_orientation: Orientation,
+    pub telescope_name: String,
+    /// ECEF telescope location in meters.
+    pub telescope_location: [f64; 3],
+    pub history: String,
+    pub antenna_names: Array<String, Ix1>,
+    pub antenna_numbers: Array<u32, Ix1>,
+    pub antenna_positions: Array<f64, Ix2>,
+    pub ant_array: Array<u32, Ix1>,
+    pub freq_array: Array<f64, Ix1>,
+    pub channel_width: Array<f64, Ix1>,
+    pub spw_array: Array<u32, Ix1>,
+    /// Spectral window of each frequency.
+    pub spw_id_array: Array<u32, Ix1>,
+    pub time_array: Array<f64, Ix1>,
+    pub integration_time: Array<f64, Ix1>,
+    pub lst_array: Array<f64, Ix1>,
+    pub jones_array: Array<i8, Ix1>,
+    pub gain_array: Option<Array<Complex<f64>, Ix4>>,
+    pub delay_array: Option<Array<f64, Ix4>>,
+    pub flag_array: Array<bool, Ix4>,
+    pub quality_array: Option<Array<f64, Ix4>>,
+    pub total_quality_array: Option<Array<f64, Ix3>>,
+}
+
+impl UVCal {
+    /// Unit gains for the antennas, frequencies and times of `uvd`, with
+    /// the Jones elements of its polarizations.
+    pub fn from_uvdata<T, S>(uvd: &UVData<T, S>) -> Result<UVCal, String>
+    where
+        T: Float + AbsDiffEq,
+        S: Float + AbsDiffEq,
+    {
+        let arrays = &uvd.meta_arrays;
+        let mut jones = Vec::new();
+        for &pol in arrays.polarization_array.iter() {
+            let (jones1, jones2) = jones_pair(pol)
+                .ok_or_else(|| format!("Polarization {} has no Jones elements.", pol))?;
+            for element in [jones1, jones2] {
+                if !jones.contains(&element) {
+                    jones.push(element);
+                }
+            }
+        }
+        let mut ants: Vec<u32> = arrays
+            .ant_1_array
+            .iter()
+            .chain(arrays.ant_2_array.iter())
+            .copied()
+            .collect();
+        ants.sort_unstable();
+        ants.dedup();
+        let mut times: Vec<usize> = (0..arrays.time_array.len()).collect();
+        times.sort_by(|&blt1, &blt2| arrays.time_array[blt1].total_cmp(&arrays.time_array[blt2]));
+        times.dedup_by(|blt2, blt1| {
+            (arrays.time_array[*blt1] - arrays.time_array[*blt2]).abs() < TIME_TOL
+        });
+
+        let shape = (
+            ants.len(),
+            arrays.freq_array.len(),
+            times.len(),
+            jones.len(),
+        );
+        Ok(UVCal {
+            cal_type: CalType::Gain,
+            cal_style: CalStyle::Sky,
+            gain_convention: GainConvention::Divide,
+            gain_scale: None,
+            x_orientation: uvd.meta.x_orientation,
+            telescope_name: uvd.meta.telescope_name.clone(),
+            telescope_location: uvd.meta.telescope_location,
+            history: String::new(),
+            antenna_names: arrays.antenna_names.clone(),
+            antenna_numbers: arrays.antenna_numbers.clone(),
+            antenna_positions: arrays.antenna_positions.clone(),
+            ant_array: Array::from(ants),
+            freq_array: arrays.freq_array.clone(),
+            channel_width: arrays.channel_width.clone(),
+            spw_array: arrays.spw_array.clone(),
+            spw_id_array: arrays.spw_id_array.clone(),
+            time_array: times.iter().map(|&blt| arrays.time_array[blt]).collect(),
+            integration_time: times
+                .iter()
+                .map(|&blt| arrays.integration_time[blt])
+                .collect(),
+            lst_array: times.iter().map(|&blt| arrays.lst_array[blt]).collect(),
+            jones_array: Array::from(jones),
+            gain_array: Some(Array::from_elem(shape, Complex::new(1.0, 0.0))),
+            delay_array: None,
+            flag_array: Array::from_elem(shape, false),
+            quality_array: None,
+            total_quality_array: None,
+        })
+    }
+
+    pub fn nants(&self) -> usize {
+        self.ant_array.len()
+    }
+
+    /// Complex gains at `freqs`, shape (Nants_data, Nfreqs, Ntimes, Njones).
+    ///
+    /// Gain solutions must be at the same frequencies; delays become
+    /// exp(-2 pi i delay freq).
+    pub fn gains_at(&self, freqs: &Array<f64, Ix1>) -> Result<Array<Complex<f64>, Ix4>, String> {
+        match self.cal_type {
+            CalType::Gain => {
+                let gains = self
+                    .gain_array
+                    .as_ref()
+                    .ok_or("Gain solutions are missing their gain_array.")?;
+                let matches = freqs.len() == self.freq_array.len()
+                    && freqs
+                        .iter()
+                        .zip(self.freq_array.iter())
+                        .all(|(freq1, freq2)| (freq1 - freq2).abs() < FREQ_TOL);
+                match matches {
+                    true => Ok(gains.clone()),
+                    false => Err("The calibration and data frequencies do not match.".to_string()),
+                }
+            }
+            CalType::Delay => {
+                let delays = self
+                    .delay_array
+                    .as_ref()
+                    .ok_or("Delay solutions are missing their delay_array.")?;
+                let (nants, _, ntimes, njones) = delays.dim();
+                Ok(Array::from_shape_fn(
+                    (nants, freqs.len(), ntimes, njones),
+                    |(ant, freq, time, jones)| {
+                        let phase = -2.0 * PI * delays[[ant, 0, time, jones]] * freqs[freq];
+                        Complex::from_polar(1.0, phase)
+                    },
+                ))
+            }
+        }
+    }
+
+    /// Index of the solution time covering `time`.
+    ///
+    /// A single solution applies to every time.
+    pub(crate) fn time_index(&self, time: f64) -> Option<usize> {
+        if self.time_array.len() == 1 {
+            return Some(0);
+        }
+        self.time_array
+            .iter()
+            .zip(self.integration_time.iter())
+            .enumerate()
+            .map(|(ind, (&cal_time, &int_time))| {
+                let tol = (int_time / 2.0 / 86400.0).max(TIME_TOL);
+                (ind, (cal_time - time).abs(), tol)
+            })
+            .filter(|(_, diff, tol)| diff < tol)
+            .min_by(|(_, diff1, _), (_, diff2, _)| diff1.total_cmp(diff2))
+            .map(|(ind, _, _)| ind)
+    }
+
+    /// Read a calh5 file.
+    pub fn read_calh5<P: AsRef<Path>>(fname: P) -> hdf5::Result<UVCal> {
+        let h5file = hdf5::File::open(fname)?;
+        let header = h5file.group("/Header")?;
+        let cal_type =
+            CalType::from_str(&read_string(&header, "cal_type")?.ok_or("Missing cal_type.")?)?;
+        let cal_style = match read_string(&header, "cal_style")? {
+            Some(style) => CalStyle::from_str(&style)?,
+            None => CalStyle::Sky,
+        };
+        let gain_convention = GainConvention::from_str(
+            &read_string(&header, "gain_convention")?.ok_or("Missing gain_convention.")?,
+        )?;
+        let gain_scale = match read_string(&header, "gain_scale")? {
+            Some(scale) => Some(VisUnit::from_str(&scale)?),
+            None => None,
+        };
+        let x_orientation = match read_string(&header, "x_orientation")? {
+            Some(orientation) => Orientation::from_str(&orientation)?,
+            None => Orientation::Unknown,
+        };
+        let telescope_location = utils::xyz_from_latlonalt(
+            header.dataset("latitude")?.read_scalar::<f64>()?,
+            header.dataset("longitude")?.read_scalar::<f64>()?,
+            header.dataset("altitude")?.read_scalar::<f64>()?,
+        );
+        let freq_array = match header.link_exists("freq_array") {
+            true => {
+                let dset = header.dataset("freq_array")?;
+                match dset.ndim() {
+                    2 => dset.read::<f64, Ix2>()?.remove_axis(Axis(0)),
+                    _ => dset.read::<f64, Ix1>()?,
+                }
+            }
+            false => Array::zeros(0),
+        };
+        let channel_width = match header.link_exists("channel_width") {
+            true => {
+                let dset = header.dataset("channel_width")?;
+                match dset.ndim() {
+                    0 => Array::from_elem(freq_array.len(), dset.read_scalar::<f64>()?),
+                    _ => dset.read::<f64, Ix1>()?,
+                }
+            }
+            false => Array::zeros(freq_array.len()),
+        };
+        let spw_array = match header.link_exists("spw_array") {
+            true => header.dataset("spw_array")?.read::<u32, Ix1>()?,
+            false => Array::zeros(1),
+        };
+        let spw_id_array = match header.link_exists("flex_spw_id_array") {
+            true => header.dataset("flex_spw_id_array")?.read::<u32, Ix1>()?,
+            false => Array::from_elem(freq_array.len(), spw_array[0]),
+        };
+        let time_array = header.dataset("time_array")?.read::<f64, Ix1>()?;
+        let integration_time = match header.dataset("integration_time")?.ndim() {
+            0 => Array::from_elem(
+                time_array.len(),
+                header.dataset("integration_time")?.read_scalar::<f64>()?,
+            ),
+            _ => header.dataset("integration_time")?.read::<f64, Ix1>()?,
+        };
+        let lst_array = match header.link_exists("lst_array") {
+            true => header.dataset("lst_array")?.read::<f64, Ix1>()?,
+            false => Array::zeros(time_array.len()),
+        };
+
+        let data = h5file.group("/Data")?;
+        let gain_array = read_squeezed::<Complexh5, Ix4>(&data, "gains")?
+            .map(|gains| gains.mapv(|gain| gain.into()));
+        let uvcal = UVCal {
+            cal_type,
+            cal_style,
+            gain_convention,
+            gain_scale,
+            x_orientation,
+            telescope_name: read_string(&header, "telescope_name")?.unwrap_or_default(),
+            telescope_location,
+            history: read_scalar::<FixedAscii<MAX_HIST_LENGTH>>(&header, "history")?
+                .map(String::from)
+                .unwrap_or_default(),
+            antenna_names: header
+                .dataset("antenna_names")?
+                .read::<FixedAscii<50>, Ix1>()?
+                .mapv(|name| name.into()),
+            antenna_numbers: header.dataset("antenna_numbers")?.read::<u32, Ix1>()?,
+            antenna_positions: header.dataset("antenna_positions")?.read::<f64, Ix2>()?,
+            ant_array: header.dataset("ant_array")?.read::<u32, Ix1>()?,
+            freq_array,
+            channel_width,
+            spw_array,
+            spw_id_array,
+            time_array,
+            integration_time,
+            lst_array,
+            jones_array: header.dataset("jones_array")?.read::<i8, Ix1>()?,
+            gain_array,
+            delay_array: read_squeezed::<f64, Ix4>(&data, "delays")?,
+            flag_array: read_squeezed::<bool, Ix4>(&data, "flags")?.ok_or("Missing flags.")?,
+            quality_array: read_squeezed::<f64, Ix4>(&data, "qualities")?,
+            total_quality_array: read_squeezed::<f64, Ix3>(&data, "total_quality")?,
+        };
+        h5file.close()?;
+        Ok(uvcal)
+    }
+
+    /// Write a calh5 file.
+    pub fn write_calh5<P: AsRef<Path>>(&self, fname: P, overwrite: bool) -> hdf5::Result<()> {
+        let h5file: hdf5::File = match overwrite {
+            true => hdf5::File::create(fname)?,
+            false => hdf5::File::create_excl(fname)?,
+        };
+        let header = h5file.create_group("/Header")?;
+        let strings = [
+            ("cal_type", self.cal_type.to_string()),
+            ("cal_style", self.cal_style.to_string()),
+            ("gain_convention", self.gain_convention.to_string()),
+        ];
+        for (name, value) in strings {
+            write_scalar(&header, name, &fixed_ascii::<20>(&value.to_lowercase())?)?;
+        }
+        if let Some(scale) = self.gain_scale.and_then(gain_scale_str) {
+            write_scalar(&header, "gain_scale", &fixed_ascii::<20>(scale)?)?;
+        }
+        if self.x_orientation != Orientation::Unknown {
+            write_scalar(
+                &header,
+                "x_orientation",
+                &fixed_ascii::<20>(&self.x_orientation.to_string().to_lowercase())?,
+            )?;
+        }
+        write_scalar(
+            &header,
+            "telescope_name",
+            &fixed_ascii::<200>(&self.telescope_name)?,
+        )?;
+        let (latitude, longitude, altitude) = utils::latlonalt_from_xyz(self.telescope_location);
+        write_scalar(&header, "latitude", &latitude.to_degrees())?;
+        write_scalar(&header, "longitude", &longitude.to_degrees())?;
+        write_scalar(&header, "altitude", &altitude)?;
+        write_scalar(
+            &header,
+            "history",
+            &fixed_ascii::<MAX_HIST_LENGTH>(&self.history)?,
+        )?;
+
+        let counts = [
+            ("Nants_data", self.nants()),
+            ("Nants_telescope", self.antenna_numbers.len()),
+            ("Nfreqs", self.freq_array.len()),
+            ("Ntimes", self.time_array.len()),
+            ("Njones", self.jones_array.len()),
+            ("Nspws", self.spw_array.len()),
+        ];
+        for (name, count) in counts {
+            write_scalar(&header, name, &(count as u32))?;
+        }
+        write_scalar(&header, "wide_band", &(self.cal_type == CalType::Delay))?;
+        write_scalar(&header, "flex_spw", &(self.spw_array.len() > 1))?;
+        header
+            .new_dataset_builder()
+            .with_data(&self.spw_array)
+            .create("spw_array")?;
+        header
+            .new_dataset_builder()
+            .with_data(&self.spw_id_array)
+            .create("flex_spw_id_array")?;
+        let datasets_f64 = [
+            ("freq_array", &self.freq_array),
+            ("channel_width", &self.channel_width),
+            ("time_array", &self.time_array),
+            ("integration_time", &self.integration_time),
+            ("lst_array", &self.lst_array),
+        ];
+        for (name, array) in datasets_f64 {
+            header.new_dataset_builder().with_data(array).create(name)?;
+        }
+        if self.cal_type == CalType::Delay {
+            let freq_range = match (
+                self.freq_array.iter().copied().reduce(f64::min),
+                self.freq_array.iter().copied().reduce(f64::max),
+            ) {
+                (Some(min), Some(max)) => vec![min, max],
+                _ => vec![0.0, 0.0],
+            };
+            header
+                .new_dataset_builder()
+                .with_data(&Array::from_shape_vec((1, 2), freq_range).unwrap())
+                .create("freq_range")?;
+        }
+        header
+            .new_dataset_builder()
+            .with_data(&self.jones_array)
+            .create("jones_array")?;
+        header
+            .new_dataset_builder()
+            .with_data(&self.ant_array)
+            .create("ant_array")?;
+        header
+            .new_dataset_builder()
+            .with_data(&self.antenna_numbers)
+            .create("antenna_numbers")?;
+        let names = self
+            .antenna_names
+            .iter()
+            .map(|name| fixed_ascii::<50>(name))
+            .collect::<hdf5::Result<Vec<_>>>()?;
+        header
+            .new_dataset_builder()
+            .with_data(&Array::from(names))
+            .create("antenna_names")?;
+        header
+            .new_dataset_builder()
+            .with_data(&self.antenna_positions)
+            .create("antenna_positions")?;
+
+        let data = h5file.create_group("/Data")?;
+        if let Some(gains) = &self.gain_array {
+            let gains: Array<Complexh5, Ix4> = gains.mapv(|gain| gain.into());
+            data.new_dataset_builder()
+                .with_data(&gains)
+                .create("gains")?;
+        }
+        if let Some(delays) = &self.delay_array {
+            data.new_dataset_builder()
+                .with_data(delays)
+                .create("delays")?;
+        }
+        data.new_dataset_builder()
+            .with_data(&self.flag_array)
+            .lzf()
+            .create("flags")?;
+        if let Some(qualities) = &self.quality_array {
+            data.new_dataset_builder()
+                .with_data(qualities)
+                .create("qualities")?;
+        }
+        if let Some(total_quality) = &self.total_quality_array {
+            data.new_dataset_builder()
+                .with_data(total_quality)
+                .create("total_quality")?;
+        }
+        h5file.close()?;
+        Ok(())
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// Apply (`forward`) or remove the solutions of `uvcal`.
+    fn apply_calibration(&mut self, uvcal: &UVCal, forward: bool) -> Result<(), String> {
+        let arrays = &self.meta_arrays;
+        let gains = uvcal.gains_at(&arrays.freq_array)?;
+        let ant_index: HashMap<u32, usize> = uvcal
+            .ant_array
+            .iter()
+            .enumerate()
+            .map(|(ind, &ant)| (ant, ind))
+            .collect();
+        let index_of = |ant: u32| {
+            ant_index
+                .get(&ant)
+                .copied()
+                .ok_or_else(|| format!("Antenna {} has no calibration solutions.", ant))
+        };
+        let blt_inds = (0..arrays.time_array.len())
+            .map(|blt| {
+                let time = uvcal.time_index(arrays.time_array[blt]).ok_or_else(|| {
+                    format!(
+                        "Time {} has no calibration solutions.",
+                        arrays.time_array[blt]
+                    )
+                })?;
+                Ok((
+                    index_of(arrays.ant_1_array[blt])?,
+                    index_of(arrays.ant_2_array[blt])?,
+                    time,
+                ))
+            })
+            .collect::<Result<Vec<(usize, usize, usize)>, String>>()?;
+        let jones_index = |jones: i8| {
+            uvcal
+                .jones_array
+                .iter()
+                .position(|&other| other == jones)
+                .ok_or_else(|| format!("Jones element {} has no calibration solutions.", jones))
+        };
+        let pol_inds = arrays
+            .polarization_array
+            .iter()
+            .map(|&pol| {
+                let (jones1, jones2) = jones_pair(pol)
+                    .ok_or_else(|| format!("Polarization {} cannot be calibrated.", pol))?;
+                Ok((jones_index(jones1)?, jones_index(jones2)?))
+            })
+            .collect::<Result<Vec<(usize, usize)>, String>>()?;
+
+        let shape = (
+            self.meta.nblts as usize,
+            self.meta.nfreqs as usize,
+            self.meta.npols as usize,
+        );
+        let data = self
+            .data_array
+            .as_mut()
+            .ok_or("Data must be loaded to calibrate.")?;
+        let flags = self
+            .flag_array
+            .get_or_insert_with(|| Array::from_elem(shape, false));
+        let divide = (uvcal.gain_convention == GainConvention::Divide) == forward;
+        // delays have a single flag for all frequencies
+        let flag_freq = |freq: usize| match uvcal.cal_type {
+            CalType::Gain => freq,
+            CalType::Delay => 0,
+        };
+        for (((blt, freq, pol), vis), flag) in data.indexed_iter_mut().zip(flags.iter_mut()) {
+            let (ind1, ind2, time) = blt_inds[blt];
+            let (jones1, jones2) = pol_inds[pol];
+            let gain = gains[[ind1, freq, time, jones1]] * gains[[ind2, freq, time, jones2]].conj();
+            let gain = Complex::new(T::from(gain.re).unwrap(), T::from(gain.im).unwrap());
+            let cal_flag = uvcal.flag_array[[ind1, flag_freq(freq), time, jones1]]
+                || uvcal.flag_array[[ind2, flag_freq(freq), time, jones2]];
+            *flag |= cal_flag;
+            if gain.norm_sqr() == T::zero() {
+                *flag = true;
+                continue;
+            }
+            *vis = match divide {
+                true => *vis / gain,
+                false => *vis * gain,
+            };
+        }
+        Ok(())
+    }
+
+    /// Calibrate the data with the solutions of `uvcal`.
+    ///
+    /// Every visibility is divided (or multiplied, following the gain
+    /// convention) by g_1 g_2^* of its antennas, time and Jones elements.
+    /// Flags of either antenna's solutions and zero gains are flagged, and
+    /// `vis_units` becomes the solutions' gain scale if they have one.
+    pub fn calibrate(&mut self, uvcal: &UVCal) -> Result<(), String> {
+        self.apply_calibration(uvcal, true)?;
+        if let Some(scale) = uvcal.gain_scale {
+            self.meta.vis_units = scale;
+        }
+        Ok(())
+    }
+
+    /// Undo `calibrate`, setting `vis_units` to uncalibrated.
+    pub fn uncalibrate(&mut self, uvcal: &UVCal) -> Result<(), String> {
+        self.apply_calibration(uvcal, false)?;
+        self.meta.vis_units = VisUnit::Uncalib;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CalStyle, CalType, GainConvention, UVCal};
+    use crate::base::{Orientation, VisUnit};
+    use crate::test_data::read_unflagged;
+    use crate::utils;
+    use ndarray::{s, Array};
+    use num_complex::Complex;
+    use std::{f64::consts::PI, path::Path, str::FromStr};
+    use tempdir::TempDir;
+
+    #[test]
+    fn enums_from_str() {
+        assert_eq!(CalType::from_str("Delay").unwrap(), CalType::Delay);
+        assert_eq!(
+            GainConvention::from_str("multiply ").unwrap(),
+            GainConvention::Multiply
+        );
+        assert_eq!(
+            CalStyle::from_str("redundant").unwrap(),
+            CalStyle::Redundant
+        );
+        assert!(CalType::from_str("bandpass").is_err());
+    }
+
+    #[test]
+    fn from_uvdata() {
+        let uvd = read_unflagged();
+        let uvcal = UVCal::from_uvdata(&uvd).expect("Cannot make UVCal.");
+        assert_eq!(uvcal.ant_array.to_vec(), vec![0, 1, 2, 11]);
+        assert_eq!(uvcal.jones_array.to_vec(), vec![-5, -6]);
+        assert_eq!(uvcal.gain_array.unwrap().shape(), &[4, 4, 20, 2]);
+    }
+
+    #[test]
+    fn calh5_roundtrip() {
+        let uvd = read_unflagged();
+        let tmp_dir = TempDir::new("uvcal").unwrap();
+        let mut uvcal = UVCal::from_uvdata(&uvd).unwrap();
+        uvcal.gain_scale = Some(VisUnit::Jansky);
+        uvcal.history = "calibrated".to_string();
+        uvcal.gain_array.as_mut().unwrap()[[1, 2, 3, 0]] = Complex::new(0.5, -2.0);
+        uvcal.flag_array[[2, 1, 0, 1]] = true;
+        uvcal.total_quality_array = Some(Array::ones((4, 20, 2)));
+        let path = tmp_dir.path().join("gains.calh5");
+        uvcal.write_calh5(&path, false).expect("Cannot write.");
+        assert!(uvcal.write_calh5(&path, false).is_err());
+        let mut read = UVCal::read_calh5(&path).expect("Cannot read.");
+        for (x1, x2) in read
+            .telescope_location
+            .iter()
+            .zip(uvcal.telescope_location.iter())
+        {
+            assert_abs_diff_eq!(x1, x2, epsilon = 1e-6);
+        }
+        read.telescope_location = uvcal.telescope_location;
+        assert_eq!(read, uvcal);
+
+        let mut delays = uvcal.clone();
+        delays.cal_type = CalType::Delay;
+        delays.gain_array = None;
+        delays.delay_array = Some(Array::from_elem((4, 1, 20, 2), 1e-9));
+        delays.flag_array = Array::from_elem((4, 1, 20, 2), false);
+        delays.total_quality_array = None;
+        delays.write_calh5(&path, true).expect("Cannot write.");
+        let mut read = UVCal::read_calh5(&path).expect("Cannot read.");
+        read.telescope_location = delays.telescope_location;
+        assert_eq!(read, delays);
+    }
+
+    #[test]
+    fn hand_built_calh5() {
+        // laid out by hand after pyuvdata's format by examples/h5_fixtures.rs
+        let data_file = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/test_gains.calh5");
+        let uvcal = UVCal::read_calh5(data_file).expect("Cannot read.");
+        assert_eq!(uvcal.cal_type, CalType::Gain);
+        assert_eq!(uvcal.cal_style, CalStyle::Redundant);
+        assert_eq!(uvcal.gain_convention, GainConvention::Divide);
+        assert_eq!(uvcal.gain_scale, Some(VisUnit::Jansky));
+        assert_eq!(uvcal.x_orientation, Orientation::East);
+        assert_eq!(uvcal.history, "Calibrated with pyuvdata.");
+        let (lat, _, alt) = utils::latlonalt_from_xyz(uvcal.telescope_location);
+        assert_abs_diff_eq!(lat.to_degrees(), -30.721_526_120_689_57, epsilon = 1e-9);
+        assert_abs_diff_eq!(alt, 1051.69, epsilon = 1e-6);
+        assert_eq!(
+            uvcal.antenna_names.to_vec(),
+            vec!["HH0", "HH1", "HH2", "HH11"]
+        );
+        assert_eq!(uvcal.ant_array.to_vec(), vec![0, 1, 11]);
+        assert_eq!(uvcal.jones_array.to_vec(), vec![-5, -6]);
+        assert_eq!(uvcal.spw_id_array.to_vec(), vec![0; 3]);
+        assert_eq!(uvcal.integration_time.to_vec(), vec![10.737_418_24; 2]);
+
+        let gains = uvcal.gain_array.unwrap();
+        assert_eq!(gains.shape(), &[3, 3, 2, 2]);
+        let gain = gains[[2, 1, 1, 0]];
+        assert_abs_diff_eq!(gain.norm(), 1.21, epsilon = 1e-12);
+        assert_abs_diff_eq!(gain.arg(), 0.6, epsilon = 1e-12);
+        assert!(uvcal.flag_array[[2, 1, 0, 1]]);
+        assert_eq!(uvcal.flag_array.iter().filter(|&&flag| flag).count(), 1);
+        assert_eq!(uvcal.quality_array.unwrap()[[1, 2, 0, 0]], 2.0);
+        assert_eq!(uvcal.total_quality_array.unwrap().shape(), &[3, 2, 2]);
+    }
+
+    #[test]
+    fn calibrate_and_uncalibrate() {
+        let mut uvd = read_unflagged();
+        let original = uvd.clone();
+        let mut uvcal = UVCal::from_uvdata(&uvd).unwrap();
+        uvcal.gain_scale = Some(VisUnit::Jansky);
+        let gains = uvcal.gain_array.as_mut().unwrap();
+        gains
+            .slice_mut(s![0, .., .., ..])
+            .fill(Complex::new(0.0, 2.0));
+        gains
+            .slice_mut(s![1, .., .., ..])
+            .fill(Complex::new(3.0, 0.0));
+        uvcal.flag_array[[2, 1, 0, 1]] = true;
+
+        uvd.calibrate(&uvcal).expect("Cannot calibrate.");
+        assert_eq!(uvd.meta.vis_units, VisUnit::Jansky);
+        let arrays = &uvd.meta_arrays;
+        let data = uvd.data_array.as_ref().unwrap();
+        let raw = original.data_array.as_ref().unwrap();
+        let flags = uvd.flag_array.as_ref().unwrap();
+        let first_time = uvcal.time_array[0];
+        for blt in 0..arrays.time_array.len() {
+            let ants = (arrays.ant_1_array[blt], arrays.ant_2_array[blt]);
+            // (0, 1): g_0 g_1^* = 6i
+            if ants == (0, 1) {
+                let expected = raw[[blt, 3, 0]] / Complex::new(0.0, 6.0);
+                assert_abs_diff_eq!(data[[blt, 3, 0]].re, expected.re, epsilon = 1e-9);
+                assert_abs_diff_eq!(data[[blt, 3, 0]].im, expected.im, epsilon = 1e-9);
+            }
+            let has_ant = ants.0 == 2 || ants.1 == 2;
+            let expected = has_ant && (arrays.time_array[blt] - first_time).abs() < 1e-9;
+            assert_eq!(flags[[blt, 1, 1]], expected);
+        }
+
+        uvd.uncalibrate(&uvcal).expect("Cannot uncalibrate.");
+        assert_eq!(uvd.meta.vis_units, VisUnit::Uncalib);
+        for (vis1, vis2) in uvd
+            .data_array
+            .as_ref()
+            .unwrap()
+            .iter()
+            .zip(original.data_array.as_ref().unwrap().iter())
+        {
+            assert_abs_diff_eq!(vis1.re, vis2.re, epsilon = 1e-9);
+            assert_abs_diff_eq!(vis1.im, vis2.im, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn delay_calibration() {
+        let mut uvd = read_unflagged();
+        let original = uvd.clone();
+        let mut uvcal = UVCal::from_uvdata(&uvd).unwrap();
+        uvcal.cal_type = CalType::Delay;
+        uvcal.gain_array = None;
+        uvcal.gain_convention = GainConvention::Multiply;
+        let mut delays = Array::zeros((4, 1, 20, 2));
+        delays.slice_mut(s![1, .., .., ..]).fill(2e-9);
+        uvcal.delay_array = Some(delays);
+        uvcal.flag_array = Array::from_elem((4, 1, 20, 2), false);
+        uvd.calibrate(&uvcal).expect("Cannot calibrate.");
+
+        let arrays = &uvd.meta_arrays;
+        let blt = (0..arrays.time_array.len())
+            .find(|&blt| (arrays.ant_1_array[blt], arrays.ant_2_array[blt]) == (0, 1))
+            .unwrap();
+        // g_0 g_1^* = exp(2 pi i tau freq)
+        let freq = arrays.freq_array[2];
+        let expected = original.data_array.as_ref().unwrap()[[blt, 2, 1]]
+            * Complex::from_polar(1.0, 2.0 * PI * 2e-9 * freq);
+        let vis = uvd.data_array.as_ref().unwrap()[[blt, 2, 1]];
+        assert_abs_diff_eq!(vis.re, expected.re, epsilon = 1e-9);
+        assert_abs_diff_eq!(vis.im, expected.im, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn calibration_errors() {
+        let mut uvd = read_unflagged();
+        let mut uvcal = UVCal::from_uvdata(&uvd).unwrap();
+        uvcal.ant_array[3] = 12;
+        assert!(uvd.calibrate(&uvcal).unwrap_err().contains("Antenna 11"));
+
+        let mut uvcal = UVCal::from_uvdata(&uvd).unwrap();
+        uvcal.jones_array[1] = -2;
+        assert!(uvd.calibrate(&uvcal).is_err());
+
+        let mut uvcal = UVCal::from_uvdata(&uvd).unwrap();
+        uvcal.freq_array += 1e6;
+        assert!(uvd.calibrate(&uvcal).is_err());
+    }
+}
@@ -0,0 +1,1033 @@
+use approx::AbsDiffEq;
+use hdf5::types::FixedAscii;
+use ndarray::{azip, s, Array, ArrayView2, Axis, Ix1, Ix2, Ix3, Ix4, IxDyn};
+use num_traits::Float;
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use super::base::Orientation;
+use super::utils::{self, TIME_TOL};
+use super::uvh5::{fixed_ascii, read_scalar, read_string, write_scalar, MAX_HIST_LENGTH};
+use super::UVData;
+
+/// Whether a `UVFlag` holds boolean flags or a floating point metric.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UVFlagMode {
+    Flag,
+    Metric,
+}
+
+impl FromStr for UVFlagMode {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<UVFlagMode, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "flag" => Ok(UVFlagMode::Flag),
+            "metric" => Ok(UVFlagMode::Metric),
+            other => Err(format!("Unknown UVFlag mode: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for UVFlagMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// The axes of a `UVFlag`'s arrays.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UVFlagType {
+    /// Shape (Nblts, Nfreqs, Npols).
+    Baseline,
+    /// Shape (Nants, Nfreqs, Ntimes, Npols).
+    Antenna,
+    /// Shape (Ntimes, Nfreqs, Npols).
+    Waterfall,
+}
+
+impl FromStr for UVFlagType {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<UVFlagType, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "baseline" => Ok(UVFlagType::Baseline),
+            "antenna" => Ok(UVFlagType::Antenna),
+            "waterfall" => Ok(UVFlagType::Waterfall),
+            other => Err(format!("Unknown UVFlag type: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for UVFlagType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// How samples are combined when collapsing to a waterfall.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FlagCollapse {
+    /// Flagged if any sample is flagged.
+    Or,
+    /// Flagged if every sample is flagged.
+    And,
+    /// The weighted mean metric, or the flagged fraction of flags.
+    Mean,
+}
+
+impl FromStr for FlagCollapse {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<FlagCollapse, Self::Err> {
+        match input
+            .trim_matches(char::is_whitespace)
+            .to_lowercase()
+            .as_str()
+        {
+            "or" => Ok(FlagCollapse::Or),
+            "and" => Ok(FlagCollapse::And),
+            "mean" => Ok(FlagCollapse::Mean),
+            other => Err(format!("Unknown collapse method: {}.", other)),
+        }
+    }
+}
+impl std::fmt::Display for FlagCollapse {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Index of the entry of the sorted `times` matching `time`.
+fn time_index(times: &[f64], time: f64) -> Option<usize> {
+    let ind = times.partition_point(|&other| other < time - TIME_TOL);
+    match ind < times.len() && (times[ind] - time).abs() < TIME_TOL {
+        true => Some(ind),
+        false => None,
+    }
+}
+
+/// Flags or metrics of visibilities, exchanged in pyuvdata's UVFlag HDF5 layout.
+///
+/// Baseline objects have one time per blt with `ant_1_array` and
+/// `ant_2_array`; antenna and waterfall objects have one time per
+/// integration, and antenna objects list their antennas in `ant_array`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UVFlag {
+    pub mode: UVFlagMode,
+    pub flag_type: UVFlagType,
+    pub label: String,
+    pub history: String,
+    pub x_orientation: Orientation,
+    pub telescope_name: String,
+    /// ECEF telescope location in meters.
+    pub telescope_location: [f64; 3],
+    pub antenna_names: Array<String, Ix1>,
+    pub antenna_numbers: Array<u32, Ix1>,
+    pub antenna_positions: Array<f64, Ix2>,
+    pub time_array: Array<f64, Ix1>,
+    pub lst_array: Array<f64, Ix1>,
+    pub freq_array: Array<f64, Ix1>,
+    pub channel_width: Array<f64, Ix1>,
+    pub polarization_array: Array<i8, Ix1>,
+    pub ant_1_array: Option<Array<u32, Ix1>>,
+    pub ant_2_array: Option<Array<u32, Ix1>>,
+    pub ant_array: Option<Array<u32, Ix1>>,
+    pub flag_array: Option<Array<bool, IxDyn>>,
+    pub metric_array: Option<Array<f64, IxDyn>>,
+    pub weights_array: Option<Array<f64, IxDyn>>,
+}
+
+impl UVFlag {
+    /// A baseline or waterfall object matching the metadata of `uvd`.
+    ///
+    /// Flag objects start from the flags of `uvd` (ORed over baselines for
+    /// waterfalls), metric objects from zero metrics with unit weights.
+    pub fn from_uvdata<T, S>(
+        uvd: &UVData<T, S>,
+        mode: UVFlagMode,
+        flag_type: UVFlagType,
+    ) -> Result<UVFlag, String>
+    where
+        T: Float + AbsDiffEq,
+        S: Float + AbsDiffEq,
+    {
+        let meta = &uvd.meta;
+        let arrays = &uvd.meta_arrays;
+        let shape = (
+            meta.nblts as usize,
+            meta.nfreqs as usize,
+            meta.npols as usize,
+        );
+        let (flag_array, metric_array, weights_array) = match mode {
+            UVFlagMode::Flag => {
+                let flags = uvd
+                    .flag_array
+                    .clone()
+                    .unwrap_or_else(|| Array::from_elem(shape, false));
+                (Some(flags.into_dyn()), None, None)
+            }
+            UVFlagMode::Metric => (
+                None,
+                Some(Array::<f64, Ix3>::zeros(shape).into_dyn()),
+                Some(Array::<f64, Ix3>::ones(shape).into_dyn()),
+            ),
+        };
+        let mut uvf = UVFlag {
+            mode,
+            flag_type: UVFlagType::Baseline,
+            label: String::new(),
+            history: meta.history.clone(),
+            x_orientation: meta.x_orientation,
+            telescope_name: meta.telescope_name.clone(),
+            telescope_location: meta.telescope_location,
+            antenna_names: arrays.antenna_names.clone(),
+            antenna_numbers: arrays.antenna_numbers.clone(),
+            antenna_positions: arrays.antenna_positions.clone(),
+            time_array: arrays.time_array.clone(),
+            lst_array: arrays.lst_array.clone(),
+            freq_array: arrays.freq_array.clone(),
+            channel_width: arrays.channel_width.clone(),
+            polarization_array: arrays.polarization_array.clone(),
+            ant_1_array: Some(arrays.ant_1_array.clone()),
+            ant_2_array: Some(arrays.ant_2_array.clone()),
+            ant_array: None,
+            flag_array,
+            metric_array,
+            weights_array,
+        };
+        match flag_type {
+            UVFlagType::Baseline => {}
+            UVFlagType::Waterfall => uvf.to_waterfall(match mode {
+                UVFlagMode::Flag => FlagCollapse::Or,
+                UVFlagMode::Metric => FlagCollapse::Mean,
+            })?,
+            UVFlagType::Antenna => {
+                return Err("Antenna UVFlag objects cannot be made from UVData.".to_string())
+            }
+        }
+        Ok(uvf)
+    }
+
+    pub fn nfreqs(&self) -> usize {
+        self.freq_array.len()
+    }
+
+    pub fn npols(&self) -> usize {
+        self.polarization_array.len()
+    }
+
+    /// Unique times, sorted, with their lsts.
+    fn unique_times(&self) -> (Vec<f64>, Vec<f64>) {
+        let mut order: Vec<usize> = (0..self.time_array.len()).collect();
+        order.sort_by(|&ind1, &ind2| self.time_array[ind1].total_cmp(&self.time_array[ind2]));
+        order.dedup_by(|ind2, ind1| {
+            (self.time_array[*ind1] - self.time_array[*ind2]).abs() < TIME_TOL
+        });
+        (
+            order.iter().map(|&ind| self.time_array[ind]).collect(),
+            order.iter().map(|&ind| self.lst_array[ind]).collect(),
+        )
+    }
+
+    /// The (values, weights) of the flags or metrics.
+    fn values_and_weights(&self) -> (Array<f64, IxDyn>, Array<f64, IxDyn>) {
+        match self.mode {
+            UVFlagMode::Flag => {
+                let flags = self.flag_array.as_ref().unwrap();
+                (
+                    flags.mapv(|flag| if flag { 1.0 } else { 0.0 }),
+                    Array::ones(flags.raw_dim()),
+                )
+            }
+            UVFlagMode::Metric => (
+                self.metric_array.clone().unwrap(),
+                self.weights_array.clone().unwrap(),
+            ),
+        }
+    }
+
+    /// Collapse a baseline or antenna object to a waterfall.
+    ///
+    /// Flags can be combined with OR or AND, or averaged into a metric of
+    /// the fraction of samples flagged, each counting equally. Metrics can
+    /// only be averaged, weighted by `weights_array`.
+    pub fn to_waterfall(&mut self, method: FlagCollapse) -> Result<(), String> {
+        if self.flag_type == UVFlagType::Waterfall {
+            return Ok(());
+        }
+        if self.mode == UVFlagMode::Metric && method != FlagCollapse::Mean {
+            return Err(format!("Metrics cannot be collapsed with {}.", method));
+        }
+        let (times, lsts) = self.unique_times();
+        let (values, weights) = self.values_and_weights();
+        // (time index, (Nfreqs, Npols) values, weights) of every sample
+        let samples: Vec<(usize, ArrayView2<f64>, ArrayView2<f64>)> = match self.flag_type {
+            UVFlagType::Waterfall => unreachable!(),
+            UVFlagType::Baseline => {
+                let values = values.view().into_dimensionality::<Ix3>().unwrap();
+                let weights = weights.view().into_dimensionality::<Ix3>().unwrap();
+                self.time_array
+                    .iter()
+                    .enumerate()
+                    .map(|(blt, &time)| {
+                        (
+                            time_index(&times, time).unwrap(),
+                            values.index_axis_move(Axis(0), blt),
+                            weights.index_axis_move(Axis(0), blt),
+                        )
+                    })
+                    .collect()
+            }
+            UVFlagType::Antenna => {
+                let values = values.view().into_dimensionality::<Ix4>().unwrap();
+                let weights = weights.view().into_dimensionality::<Ix4>().unwrap();
+                let nants = values.len_of(Axis(0));
+                (0..nants)
+                    .flat_map(|ant| (0..times.len()).map(move |time| (ant, time)))
+                    .map(|(ant, time)| {
+                        (
+                            time,
+                            values.slice_move(s![ant, .., time, ..]),
+                            weights.slice_move(s![ant, .., time, ..]),
+                        )
+                    })
+                    .collect()
+            }
+        };
+
+        let shape = (times.len(), self.nfreqs(), self.npols());
+        let mut nsamples = Array::<f64, Ix3>::zeros(shape);
+        let mut weight_sum = Array::<f64, Ix3>::zeros(shape);
+        let mut weighted_values = Array::<f64, Ix3>::zeros(shape);
+        let mut nflagged = Array::<f64, Ix3>::zeros(shape);
+        for (time, values, weights) in samples {
+            let slice = s![time, .., ..];
+            nsamples.slice_mut(slice).map_inplace(|count| *count += 1.0);
+            nflagged
+                .slice_mut(slice)
+                .zip_mut_with(&values, |sum, &value| *sum += value);
+            weight_sum
+                .slice_mut(slice)
+                .zip_mut_with(&weights, |sum, &value| *sum += value);
+            weighted_values
+                .slice_mut(slice)
+                .zip_mut_with(&(&values * &weights), |sum, &value| *sum += value);
+        }
+
+        match (self.mode, method) {
+            (UVFlagMode::Flag, FlagCollapse::Or) => {
+                self.flag_array = Some(nflagged.mapv(|count| count > 0.0).into_dyn());
+            }
+            (UVFlagMode::Flag, FlagCollapse::And) => {
+                let mut flags = Array::from_elem(shape, false);
+                azip!((flag in &mut flags, &count in &nflagged, &total in &nsamples) *flag = total > 0.0 && count == total);
+                self.flag_array = Some(flags.into_dyn());
+            }
+            (_, FlagCollapse::Mean) => {
+                let mut metric = Array::<f64, Ix3>::zeros(shape);
+                azip!((metric in &mut metric, &sum in &weighted_values, &weight in &weight_sum) if weight > 0.0 {
+                    *metric = sum / weight
+                });
+                self.mode = UVFlagMode::Metric;
+                self.flag_array = None;
+                self.metric_array = Some(metric.into_dyn());
+                self.weights_array = Some(weight_sum.into_dyn());
+            }
+            _ => unreachable!(),
+        }
+        self.time_array = Array::from(times);
+        self.lst_array = Array::from(lsts);
+        self.ant_1_array = None;
+        self.ant_2_array = None;
+        self.ant_array = None;
+        self.flag_type = UVFlagType::Waterfall;
+        Ok(())
+    }
+
+    /// Expand to the baseline-times of `uvd`.
+    ///
+    /// Waterfalls are broadcast to every baseline of each time. Antenna
+    /// flags apply to every baseline containing the antenna; antenna
+    /// metrics cannot be expanded.
+    pub fn to_baseline<T, S>(&mut self, uvd: &UVData<T, S>) -> Result<(), String>
+    where
+        T: Float + AbsDiffEq,
+        S: Float + AbsDiffEq,
+    {
+        if self.flag_type == UVFlagType::Baseline {
+            return Ok(());
+        }
+        if self.nfreqs() != uvd.meta.nfreqs as usize {
+            return Err("The UVFlag and UVData frequencies do not match.".to_string());
+        }
+        let arrays = &uvd.meta_arrays;
+        let (times, _) = self.unique_times();
+        let blt_times = arrays
+            .time_array
+            .iter()
+            .map(|&time| {
+                time_index(&times, time)
+                    .ok_or_else(|| format!("Time {} is not in the UVFlag object.", time))
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+        let shape = (arrays.time_array.len(), self.nfreqs(), self.npols());
+
+        match self.flag_type {
+            UVFlagType::Waterfall => {
+                let broadcast = |array: &Array<f64, IxDyn>| {
+                    let array = array.view().into_dimensionality::<Ix3>().unwrap();
+                    Array::from_shape_fn(shape, |(blt, freq, pol)| {
+                        array[[blt_times[blt], freq, pol]]
+                    })
+                    .into_dyn()
+                };
+                self.flag_array = self.flag_array.as_ref().map(|flags| {
+                    let flags = flags.view().into_dimensionality::<Ix3>().unwrap();
+                    Array::from_shape_fn(shape, |(blt, freq, pol)| {
+                        flags[[blt_times[blt], freq, pol]]
+                    })
+                    .into_dyn()
+                });
+                self.metric_array = self.metric_array.as_ref().map(broadcast);
+                self.weights_array = self.weights_array.as_ref().map(broadcast);
+            }
+            UVFlagType::Antenna => {
+                if self.mode == UVFlagMode::Metric {
+                    return Err("Antenna metrics cannot be expanded to baselines.".to_string());
+                }
+                let ant_index: HashMap<u32, usize> = self
+                    .ant_array
+                    .as_ref()
+                    .unwrap()
+                    .iter()
+                    .enumerate()
+                    .map(|(ind, &ant)| (ant, ind))
+                    .collect();
+                let index_of = |ant: u32| {
+                    ant_index
+                        .get(&ant)
+                        .copied()
+                        .ok_or_else(|| format!("Antenna {} is not in the UVFlag object.", ant))
+                };
+                let blt_ants = (0..shape.0)
+                    .map(|blt| {
+                        Ok((
+                            index_of(arrays.ant_1_array[blt])?,
+                            index_of(arrays.ant_2_array[blt])?,
+                        ))
+                    })
+                    .collect::<Result<Vec<(usize, usize)>, String>>()?;
+                let flags = self
+                    .flag_array
+                    .as_ref()
+                    .unwrap()
+                    .view()
+                    .into_dimensionality::<Ix4>()
+                    .unwrap();
+                let new_flags = Array::from_shape_fn(shape, |(blt, freq, pol)| {
+                    let (ind1, ind2) = blt_ants[blt];
+                    let time = blt_times[blt];
+                    flags[[ind1, freq, time, pol]] || flags[[ind2, freq, time, pol]]
+                });
+                self.flag_array = Some(new_flags.into_dyn());
+                self.ant_array = None;
+            }
+            UVFlagType::Baseline => unreachable!(),
+        }
+        self.time_array = arrays.time_array.clone();
+        self.lst_array = arrays.lst_array.clone();
+        self.ant_1_array = Some(arrays.ant_1_array.clone());
+        self.ant_2_array = Some(arrays.ant_2_array.clone());
+        self.flag_type = UVFlagType::Baseline;
+        Ok(())
+    }
+
+    /// Broadcast a waterfall to every antenna in `antenna_numbers`.
+    pub fn to_antenna(&mut self, antenna_numbers: &[u32]) -> Result<(), String> {
+        match self.flag_type {
+            UVFlagType::Antenna => return Ok(()),
+            UVFlagType::Baseline => {
+                return Err("Baseline objects cannot be converted to antennas.".to_string())
+            }
+            UVFlagType::Waterfall => {}
+        }
+        let nants = antenna_numbers.len();
+        let (ntimes, nfreqs, npols) = (self.time_array.len(), self.nfreqs(), self.npols());
+        let shape = (nants, nfreqs, ntimes, npols);
+        self.flag_array = self.flag_array.as_ref().map(|flags| {
+            Array::from_shape_fn(shape, |(_, freq, time, pol)| flags[[time, freq, pol]]).into_dyn()
+        });
+        let broadcast = |array: &Array<f64, IxDyn>| {
+            Array::from_shape_fn(shape, |(_, freq, time, pol)| array[[time, freq, pol]]).into_dyn()
+        };
+        self.metric_array = self.metric_array.as_ref().map(broadcast);
+        self.weights_array = self.weights_array.as_ref().map(broadcast);
+        self.ant_array = Some(Array::from(antenna_numbers.to_vec()));
+        self.flag_type = UVFlagType::Antenna;
+        Ok(())
+    }
+
+    /// Flag metrics whose magnitude reaches `threshold` or with no weight.
+    pub fn to_flag(&mut self, threshold: f64) {
+        if self.mode == UVFlagMode::Flag {
+            return;
+        }
+        let metric = self.metric_array.take().unwrap();
+        let weights = self.weights_array.take().unwrap();
+        let mut flags = Array::from_elem(metric.raw_dim(), false);
+        azip!((flag in &mut flags, &metric in &metric, &weight in &weights) *flag = metric.abs() >= threshold || weight <= 0.0);
+        self.flag_array = Some(flags);
+        self.mode = UVFlagMode::Flag;
+    }
+
+    /// Convert flags into a metric of ones and zeros with unit weights.
+    pub fn to_metric(&mut self) {
+        if self.mode == UVFlagMode::Metric {
+            return;
+        }
+        let (values, weights) = self.values_and_weights();
+        self.flag_array = None;
+        self.metric_array = Some(values);
+        self.weights_array = Some(weights);
+        self.mode = UVFlagMode::Metric;
+    }
+
+    /// Read a pyuvdata UVFlag HDF5 file.
+    ///
+    /// Arrays with the spectral window axis of older files are squeezed.
+    pub fn from_file<P: AsRef<Path>>(fname: P) -> hdf5::Result<UVFlag> {
+        let h5file = hdf5::File::open(fname)?;
+        let header = h5file.group("/Header")?;
+        let mode = UVFlagMode::from_str(&read_string(&header, "mode")?.ok_or("Missing mode.")?)?;
+        let flag_type =
+            UVFlagType::from_str(&read_string(&header, "type")?.ok_or("Missing type.")?)?;
+        let x_orientation = match read_string(&header, "x_orientation")? {
+            Some(orientation) => Orientation::from_str(&orientation)?,
+            None => Orientation::Unknown,
+        };
+        let telescope_location = match header.link_exists("latitude") {
+            true => utils::xyz_from_latlonalt(
+                header.dataset("latitude")?.read_scalar::<f64>()?,
+                header.dataset("longitude")?.read_scalar::<f64>()?,
+                header.dataset("altitude")?.read_scalar::<f64>()?,
+            ),
+            false => match header.link_exists("telescope_location") {
+                true => {
+                    let location = header.dataset("telescope_location")?.read_raw::<f64>()?;
+                    [location[0], location[1], location[2]]
+                }
+                false => [0.0; 3],
+            },
+        };
+        let read_1d = |name: &str| -> hdf5::Result<Option<Array<u32, Ix1>>> {
+            match header.link_exists(name) {
+                true => Ok(Some(header.dataset(name)?.read::<u32, Ix1>()?)),
+                false => Ok(None),
+            }
+        };
+        let freq_dset = header.dataset("freq_array")?;
+        let freq_array = match freq_dset.ndim() {
+            2 => freq_dset.read::<f64, Ix2>()?.remove_axis(Axis(0)),
+            _ => freq_dset.read::<f64, Ix1>()?,
+        };
+        let channel_width = match header.link_exists("channel_width") {
+            true => {
+                let dset = header.dataset("channel_width")?;
+                match dset.ndim() {
+                    0 => Array::from_elem(freq_array.len(), dset.read_scalar::<f64>()?),
+                    _ => dset.read::<f64, Ix1>()?,
+                }
+            }
+            false => Array::zeros(freq_array.len()),
+        };
+        let antenna_names = match header.link_exists("antenna_names") {
+            true => header
+                .dataset("antenna_names")?
+                .read::<FixedAscii<50>, Ix1>()?
+                .mapv(|name| name.into()),
+            false => Array::from(Vec::<String>::new()),
+        };
+        let antenna_positions = match header.link_exists("antenna_positions") {
+            true => header.dataset("antenna_positions")?.read::<f64, Ix2>()?,
+            false => Array::zeros((0, 3)),
+        };
+
+        // arrays of older files have a length one spectral window axis
+        let future_ndim = match flag_type {
+            UVFlagType::Antenna => 4,
+            _ => 3,
+        };
+        let data = h5file.group("/Data")?;
+        let read_dyn = |name: &str| -> hdf5::Result<Option<Array<f64, IxDyn>>> {
+            if !data.link_exists(name) {
+                return Ok(None);
+            }
+            let array = data.dataset(name)?.read_dyn::<f64>()?;
+            Ok(Some(match array.ndim() == future_ndim {
+                true => array,
+                false => array.remove_axis(Axis(1)),
+            }))
+        };
+        let flag_array = match data.link_exists("flag_array") {
+            true => {
+                let flags = data.dataset("flag_array")?.read_dyn::<bool>()?;
+                Some(match flags.ndim() == future_ndim {
+                    true => flags,
+                    false => flags.remove_axis(Axis(1)),
+                })
+            }
+            false => None,
+        };
+
+        let uvf = UVFlag {
+            mode,
+            flag_type,
+            label: read_string(&header, "label")?.unwrap_or_default(),
+            history: read_scalar::<FixedAscii<MAX_HIST_LENGTH>>(&header, "history")?
+                .map(String::from)
+                .unwrap_or_default(),
+            x_orientation,
+            telescope_name: read_string(&header, "telescope_name")?.unwrap_or_default(),
+            telescope_location,
+            antenna_names,
+            antenna_numbers: read_1d("antenna_numbers")?.unwrap_or_else(|| Array::zeros(0)),
+            antenna_positions,
+            time_array: header.dataset("time_array")?.read::<f64, Ix1>()?,
+            lst_array: header.dataset("lst_array")?.read::<f64, Ix1>()?,
+            freq_array,
+            channel_width,
+            polarization_array: header.dataset("polarization_array")?.read::<i8, Ix1>()?,
+            ant_1_array: read_1d("ant_1_array")?,
+            ant_2_array: read_1d("ant_2_array")?,
+            ant_array: read_1d("ant_array")?,
+            flag_array,
+            metric_array: read_dyn("metric_array")?,
+            weights_array: read_dyn("weights_array")?,
+        };
+        h5file.close()?;
+        Ok(uvf)
+    }
+
+    /// Write a UVFlag HDF5 file readable by pyuvdata.
+    pub fn to_file<P: AsRef<Path>>(&self, fname: P, overwrite: bool) -> hdf5::Result<()> {
+        let h5file: hdf5::File = match overwrite {
+            true => hdf5::File::create(fname)?,
+            false => hdf5::File::create_excl(fname)?,
+        };
+        let header = h5file.create_group("/Header")?;
+        write_scalar(
+            &header,
+            "type",
+            &fixed_ascii::<20>(&self.flag_type.to_string().to_lowercase())?,
+        )?;
+        write_scalar(
+            &header,
+            "mode",
+            &fixed_ascii::<20>(&self.mode.to_string().to_lowercase())?,
+        )?;
+        if self.x_orientation != Orientation::Unknown {
+            write_scalar(
+                &header,
+                "x_orientation",
+                &fixed_ascii::<20>(&self.x_orientation.to_string().to_lowercase())?,
+            )?;
+        }
+        write_scalar(&header, "label", &fixed_ascii::<200>(&self.label)?)?;
+        write_scalar(
+            &header,
+            "history",
+            &fixed_ascii::<MAX_HIST_LENGTH>(&self.history)?,
+        )?;
+        write_scalar(
+            &header,
+            "telescope_name",
+            &fixed_ascii::<200>(&self.telescope_name)?,
+        )?;
+        let (latitude, longitude, altitude) = utils::latlonalt_from_xyz(self.telescope_location);
+        write_scalar(&header, "latitude", &latitude.to_degrees())?;
+        write_scalar(&header, "longitude", &longitude.to_degrees())?;
+        write_scalar(&header, "altitude", &altitude)?;
+
+        let ntimes = match self.flag_type {
+            UVFlagType::Baseline => self.unique_times().0.len(),
+            _ => self.time_array.len(),
+        };
+        write_scalar(&header, "Ntimes", &(ntimes as u32))?;
+        write_scalar(&header, "Nfreqs", &(self.nfreqs() as u32))?;
+        write_scalar(&header, "Npols", &(self.npols() as u32))?;
+        write_scalar(&header, "Nspws", &1u32)?;
+        write_scalar(
+            &header,
+            "Nants_telescope",
+            &(self.antenna_numbers.len() as u32),
+        )?;
+        header
+            .new_dataset_builder()
+            .with_data(&Array::from(vec![0u32]))
+            .create("spw_array")?;
+
+        let datasets_f64 = [
+            ("time_array", &self.time_array),
+            ("lst_array", &self.lst_array),
+            ("freq_array", &self.freq_array),
+            ("channel_width", &self.channel_width),
+        ];
+        for (name, array) in datasets_f64 {
+            header.new_dataset_builder().with_data(array).create(name)?;
+        }
+        header
+            .new_dataset_builder()
+            .with_data(&self.polarization_array)
+            .create("polarization_array")?;
+        header
+            .new_dataset_builder()
+            .with_data(&self.antenna_numbers)
+            .create("antenna_numbers")?;
+        let names = self
+            .antenna_names
+            .iter()
+            .map(|name| fixed_ascii::<50>(name))
+            .collect::<hdf5::Result<Vec<_>>>()?;
+        header
+            .new_dataset_builder()
+            .with_data(&Array::from(names))
+            .create("antenna_names")?;
+        header
+            .new_dataset_builder()
+            .with_data(&self.antenna_positions)
+            .create("antenna_positions")?;
+
+        if let (Some(ant_1_array), Some(ant_2_array)) = (&self.ant_1_array, &self.ant_2_array) {
+            let baseline_array = utils::antnums_to_baseline(ant_1_array, ant_2_array, false);
+            let mut baselines = baseline_array.to_vec();
+            baselines.sort_unstable();
+            baselines.dedup();
+            let mut ants: Vec<u32> = ant_1_array
+                .iter()
+                .chain(ant_2_array.iter())
+                .copied()
+                .collect();
+            ants.sort_unstable();
+            ants.dedup();
+            write_scalar(&header, "Nblts", &(ant_1_array.len() as u32))?;
+            write_scalar(&header, "Nbls", &(baselines.len() as u32))?;
+            write_scalar(&header, "Nants_data", &(ants.len() as u32))?;
+            header
+                .new_dataset_builder()
+                .with_data(ant_1_array)
+                .create("ant_1_array")?;
+            header
+                .new_dataset_builder()
+                .with_data(ant_2_array)
+                .create("ant_2_array")?;
+            header
+                .new_dataset_builder()
+                .with_data(&baseline_array)
+                .create("baseline_array")?;
+        }
+        if let Some(ant_array) = &self.ant_array {
+            write_scalar(&header, "Nants_data", &(ant_array.len() as u32))?;
+            header
+                .new_dataset_builder()
+                .with_data(ant_array)
+                .create("ant_array")?;
+        }
+
+        let data = h5file.create_group("/Data")?;
+        if let Some(flags) = &self.flag_array {
+            data.new_dataset_builder()
+                .with_data(flags)
+                .lzf()
+                .create("flag_array")?;
+        }
+        if let Some(metric) = &self.metric_array {
+            data.new_dataset_builder()
+                .with_data(metric)
+                .create("metric_array")?;
+        }
+        if let Some(weights) = &self.weights_array {
+            data.new_dataset_builder()
+                .with_data(weights)
+                .create("weights_array")?;
+        }
+        h5file.close()?;
+        Ok(())
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// OR the flags of a flag mode `UVFlag` into `flag_array`.
+    ///
+    /// Waterfall and antenna objects are first expanded to this object's
+    /// baselines. Baseline-times and polarizations missing from `uvf` are
+    /// flagged, except that a single polarization applies to all of them.
+    /// Existing flags are cleared first if `unflag_first` is set.
+    pub fn apply_uvflag(&mut self, uvf: &UVFlag, unflag_first: bool) -> Result<(), String> {
+        if uvf.mode != UVFlagMode::Flag {
+            return Err("Only flag mode UVFlag objects can be applied.".to_string());
+        }
+        let freqs_match = uvf.nfreqs() == self.meta.nfreqs as usize
+            && uvf
+                .freq_array
+                .iter()
+                .zip(self.meta_arrays.freq_array.iter())
+                .all(|(freq1, freq2)| (freq1 - freq2).abs() < 1.0);
+        if !freqs_match {
+            return Err("The UVFlag and UVData frequencies do not match.".to_string());
+        }
+        let mut uvf = uvf.clone();
+        uvf.to_baseline(self)?;
+        let uvf_flags = uvf
+            .flag_array
+            .as_ref()
+            .unwrap()
+            .view()
+            .into_dimensionality::<Ix3>()
+            .unwrap();
+
+        let uvf_ant_1 = uvf.ant_1_array.as_ref().unwrap();
+        let uvf_ant_2 = uvf.ant_2_array.as_ref().unwrap();
+        let mut uvf_blts: HashMap<(u32, u32), Vec<(f64, usize)>> = HashMap::new();
+        for (blt, &time) in uvf.time_array.iter().enumerate() {
+            uvf_blts
+                .entry((uvf_ant_1[blt], uvf_ant_2[blt]))
+                .or_default()
+                .push((time, blt));
+        }
+        let pol_map: Vec<Option<usize>> = self
+            .meta_arrays
+            .polarization_array
+            .iter()
+            .map(|pol| match uvf.npols() {
+                1 => Some(0),
+                _ => uvf.polarization_array.iter().position(|other| other == pol),
+            })
+            .collect();
+
+        let shape = (
+            self.meta.nblts as usize,
+            self.meta.nfreqs as usize,
+            self.meta.npols as usize,
+        );
+        let arrays = &self.meta_arrays;
+        let flags = self
+            .flag_array
+            .get_or_insert_with(|| Array::from_elem(shape, false));
+        if unflag_first {
+            flags.fill(false);
+        }
+        for (blt, mut blt_flags) in flags.outer_iter_mut().enumerate() {
+            let time = arrays.time_array[blt];
+            let uvf_blt = uvf_blts
+                .get(&(arrays.ant_1_array[blt], arrays.ant_2_array[blt]))
+                .and_then(|blts| {
+                    blts.iter()
+                        .find(|(other, _)| (other - time).abs() < TIME_TOL)
+                        .map(|&(_, uvf_blt)| uvf_blt)
+                });
+            for ((freq, pol), flag) in blt_flags.indexed_iter_mut() {
+                *flag |= match (uvf_blt, pol_map[pol]) {
+                    (Some(uvf_blt), Some(uvf_pol)) => uvf_flags[[uvf_blt, freq, uvf_pol]],
+                    _ => true,
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FlagCollapse, UVFlag, UVFlagMode, UVFlagType};
+    use crate::test_data::read_unflagged;
+    use crate::{utils, Orientation};
+    use ndarray::{s, Ix3, Ix4};
+    use std::{path::Path, str::FromStr};
+    use tempdir::TempDir;
+
+    #[test]
+    fn enums_from_str() {
+        assert_eq!(UVFlagMode::from_str("Metric").unwrap(), UVFlagMode::Metric);
+        assert_eq!(
+            UVFlagType::from_str(" waterfall").unwrap(),
+            UVFlagType::Waterfall
+        );
+        assert_eq!(FlagCollapse::from_str("AND").unwrap(), FlagCollapse::And);
+        assert!(FlagCollapse::from_str("xor").is_err());
+    }
+
+    #[test]
+    fn collapse_to_waterfall() {
+        let mut uvd = read_unflagged();
+        let time = uvd.meta_arrays.time_array[0];
+        let blts: Vec<usize> = (0..uvd.meta.nblts as usize)
+            .filter(|&blt| uvd.meta_arrays.time_array[blt] == time)
+            .collect();
+        let flags = uvd.flag_array.as_mut().unwrap();
+        flags.slice_mut(s![blts[0], 1, ..]).fill(true);
+        for &blt in blts.iter() {
+            flags[[blt, 2, 0]] = true;
+        }
+
+        let uvf = UVFlag::from_uvdata(&uvd, UVFlagMode::Flag, UVFlagType::Baseline)
+            .expect("Cannot make UVFlag.");
+        let mut or = uvf.clone();
+        or.to_waterfall(FlagCollapse::Or).expect("Cannot collapse.");
+        assert_eq!(or.time_array.len(), uvd.meta.ntimes as usize);
+        let or_flags = or.flag_array.unwrap().into_dimensionality::<Ix3>().unwrap();
+        assert!(or_flags[[0, 1, 1]] && or_flags[[0, 2, 0]]);
+        assert_eq!(or_flags.iter().filter(|&&flag| flag).count(), 3);
+
+        let mut and = uvf.clone();
+        and.to_waterfall(FlagCollapse::And)
+            .expect("Cannot collapse.");
+        let and_flags = and
+            .flag_array
+            .unwrap()
+            .into_dimensionality::<Ix3>()
+            .unwrap();
+        assert!(!and_flags[[0, 1, 1]] && and_flags[[0, 2, 0]]);
+
+        let mut mean = uvf;
+        mean.to_waterfall(FlagCollapse::Mean)
+            .expect("Cannot collapse.");
+        assert_eq!(mean.mode, UVFlagMode::Metric);
+        let metric = mean.metric_array.as_ref().unwrap();
+        assert_abs_diff_eq!(metric[[0, 1, 0]], 1.0 / blts.len() as f64);
+        assert_abs_diff_eq!(
+            mean.weights_array.as_ref().unwrap()[[0, 1, 0]],
+            blts.len() as f64
+        );
+        assert!(mean.to_waterfall(FlagCollapse::Or).is_ok());
+        let mut metric_bl =
+            UVFlag::from_uvdata(&uvd, UVFlagMode::Metric, UVFlagType::Baseline).unwrap();
+        assert!(metric_bl.to_waterfall(FlagCollapse::And).is_err());
+    }
+
+    #[test]
+    fn file_roundtrip() {
+        let uvd = read_unflagged();
+        let tmp_dir = TempDir::new("uvflag").unwrap();
+
+        let mut uvf = UVFlag::from_uvdata(&uvd, UVFlagMode::Flag, UVFlagType::Baseline).unwrap();
+        uvf.label = "xrfi".to_string();
+        uvf.flag_array.as_mut().unwrap()[[4, 1, 0]] = true;
+        let path = tmp_dir.path().join("baseline.h5");
+        uvf.to_file(&path, false).expect("Cannot write.");
+        assert!(uvf.to_file(&path, false).is_err());
+        let read = UVFlag::from_file(&path).expect("Cannot read.");
+        assert_eq!(read.flag_array, uvf.flag_array);
+        assert_eq!(read.ant_2_array, uvf.ant_2_array);
+        assert_eq!(read.label, "xrfi");
+        assert_eq!(read.antenna_names, uvf.antenna_names);
+        for (x1, x2) in read
+            .telescope_location
+            .iter()
+            .zip(uvf.telescope_location.iter())
+        {
+            assert_abs_diff_eq!(x1, x2, epsilon = 1e-6);
+        }
+
+        let mut uvf = UVFlag::from_uvdata(&uvd, UVFlagMode::Metric, UVFlagType::Waterfall).unwrap();
+        uvf.to_antenna(&[0, 1, 2, 11]).expect("Cannot broadcast.");
+        let path = tmp_dir.path().join("antenna.h5");
+        uvf.to_file(&path, true).expect("Cannot write.");
+        let read = UVFlag::from_file(&path).expect("Cannot read.");
+        assert_eq!(read.flag_type, UVFlagType::Antenna);
+        assert_eq!(read.metric_array.as_ref().unwrap().shape(), &[4, 4, 20, 2]);
+        assert_eq!(read.weights_array, uvf.weights_array);
+        assert_eq!(read.ant_array, uvf.ant_array);
+    }
+
+    #[test]
+    fn hand_built_file() {
+        // laid out by hand after pyuvdata's format by examples/h5_fixtures.rs
+        let data_file = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/test_flags.h5");
+        let uvf = UVFlag::from_file(data_file).expect("Cannot read.");
+        assert_eq!(uvf.mode, UVFlagMode::Flag);
+        assert_eq!(uvf.flag_type, UVFlagType::Baseline);
+        assert_eq!(uvf.label, "xrfi");
+        assert_eq!(uvf.history, "Flagged with pyuvdata.");
+        assert_eq!(uvf.x_orientation, Orientation::East);
+        assert_eq!(uvf.telescope_name, "HERA");
+        let (lat, lon, _) = utils::latlonalt_from_xyz(uvf.telescope_location);
+        assert_abs_diff_eq!(lat.to_degrees(), -30.721_526_120_689_57, epsilon = 1e-9);
+        assert_abs_diff_eq!(lon.to_degrees(), 21.428_303_826_863_015, epsilon = 1e-9);
+        assert_eq!(
+            uvf.antenna_names.to_vec(),
+            vec!["HH0", "HH1", "HH2", "HH11"]
+        );
+        assert_eq!(uvf.antenna_numbers.to_vec(), vec![0, 1, 2, 11]);
+        assert_eq!(
+            uvf.ant_1_array.as_ref().unwrap().to_vec(),
+            vec![0, 0, 1, 0, 0, 1]
+        );
+        assert_eq!(
+            uvf.ant_2_array.as_ref().unwrap().to_vec(),
+            vec![1, 2, 2, 1, 2, 2]
+        );
+        assert_eq!(uvf.polarization_array.to_vec(), vec![-5, -6]);
+        assert_eq!(uvf.channel_width.to_vec(), vec![0.1e6; 3]);
+        assert_eq!(uvf.unique_times().0.len(), 2);
+        let flags = uvf.flag_array.unwrap();
+        assert_eq!(flags.shape(), &[6, 3, 2]);
+        for ((blt, freq, pol), &flag) in flags.into_dimensionality::<Ix3>().unwrap().indexed_iter()
+        {
+            assert_eq!(flag, (blt + 2 * freq + pol) % 5 == 0);
+        }
+        assert!(uvf.metric_array.is_none());
+    }
+
+    #[test]
+    fn apply_to_uvdata() {
+        let mut uvd = read_unflagged();
+        let arrays = uvd.meta_arrays.clone();
+
+        // antenna 2 flagged in the second channel of the first time
+        let mut uvf = UVFlag::from_uvdata(&uvd, UVFlagMode::Flag, UVFlagType::Waterfall).unwrap();
+        uvf.to_antenna(&[0, 1, 2, 11]).unwrap();
+        let mut flags = uvf
+            .flag_array
+            .take()
+            .unwrap()
+            .into_dimensionality::<Ix4>()
+            .unwrap();
+        flags[[2, 1, 0, 1]] = true;
+        uvf.flag_array = Some(flags.into_dyn());
+
+        uvd.apply_uvflag(&uvf, false).expect("Cannot apply.");
+        let first_time = uvf.time_array[0];
+        let flags = uvd.flag_array.as_ref().unwrap();
+        for blt in 0..arrays.time_array.len() {
+            let has_ant = arrays.ant_1_array[blt] == 2 || arrays.ant_2_array[blt] == 2;
+            let expected = has_ant && (arrays.time_array[blt] - first_time).abs() < 1e-9;
+            assert_eq!(flags[[blt, 1, 1]], expected);
+            assert!(!flags[[blt, 1, 0]]);
+        }
+
+        // baselines missing from the UVFlag object are flagged
+        let mut subset = uvd.clone();
+        subset.select_blts(&[0, 1, 2]).expect("Cannot select.");
+        let uvf = UVFlag::from_uvdata(&subset, UVFlagMode::Flag, UVFlagType::Baseline).unwrap();
+        uvd.apply_uvflag(&uvf, true).expect("Cannot apply.");
+        let flags = uvd.flag_array.as_ref().unwrap();
+        assert!(flags.slice(s![3.., .., ..]).iter().all(|&flag| flag));
+        assert_eq!(
+            flags.slice(s![..3, .., ..]),
+            subset.flag_array.as_ref().unwrap()
+        );
+
+        let metric = UVFlag::from_uvdata(&uvd, UVFlagMode::Metric, UVFlagType::Baseline).unwrap();
+        assert!(uvd.apply_uvflag(&metric, false).is_err());
+    }
+}
@@ -20,12 +20,12 @@ fn print_version_str() -> String {
 
 #[derive(H5Type, Clone, PartialEq, Debug)]
 #[repr(C)]
-struct Complexh5 {
+pub(crate) struct Complexh5 {
     r: f64,
     i: f64,
 }
 
-const MAX_HIST_LENGTH: usize = 20_000;
+pub(crate) const MAX_HIST_LENGTH: usize = 20_000;
 
 impl<T: Float + AsPrimitive<f64>> From<Complex<T>> for Complexh5 {
     fn from(comp: Complex<T>) -> Self {
@@ -44,17 +44,32 @@ impl<T: Float + FromPrimitive> From<Complexh5> for Complex<T> {
     }
 }
 
-fn read_scalar<T: hdf5::H5Type>(header: &hdf5::Group, param: &str) -> hdf5::Result<Option<T>> {
+pub(crate) fn read_scalar<T: hdf5::H5Type>(
+    header: &hdf5::Group,
+    param: &str,
+) -> hdf5::Result<Option<T>> {
     match header.link_exists(param) {
         true => Ok(Some(header.dataset(param)?.read_scalar::<T>()?)),
         false => Ok(None),
     }
 }
 
-fn write_scalar<T: hdf5::H5Type>(group: &hdf5::Group, param: &str, val: &T) -> hdf5::Result<()> {
+pub(crate) fn write_scalar<T: hdf5::H5Type>(
+    group: &hdf5::Group,
+    param: &str,
+    val: &T,
+) -> hdf5::Result<()> {
     group.new_dataset::<T>().create(param)?.write_scalar(val)
 }
 
+pub(crate) fn fixed_ascii<const N: usize>(text: &str) -> hdf5::Result<FixedAscii<N>> {
+    FixedAscii::<N>::from_ascii(text).map_err(|err| err.to_string().into())
+}
+
+pub(crate) fn read_string(group: &hdf5::Group, param: &str) -> hdf5::Result<Option<String>> {
+    Ok(read_scalar::<FixedAscii<200>>(group, param)?.map(String::from))
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct UVH5<T, S>
 where
@@ -326,27 +341,26 @@ where
                 (cat, Array::<u32, Ix1>::zeros(meta.nblts as usize))
             }
         };
-        let meta_arrays = ArrayMetaData {
-            spw_array,
-            uvw_array,
-            time_array,
-            lst_array,
-            ant_1_array,
-            ant_2_array,
-            baseline_array,
-            freq_array,
-            spw_id_array,
-            polarization_array,
-            integration_time,
-            channel_width,
-            antenna_numbers,
-            antenna_names,
-            antenna_positions,
-            eq_coeffs,
-            antenna_diameters,
-            phase_center_catalog,
-            phase_center_id_array,
-        };
+        let mut meta_arrays = ArrayMetaData::new(&meta);
+        meta_arrays.spw_array = spw_array;
+        meta_arrays.uvw_array = uvw_array;
+        meta_arrays.time_array = time_array;
+        meta_arrays.lst_array = lst_array;
+        meta_arrays.ant_1_array = ant_1_array;
+        meta_arrays.ant_2_array = ant_2_array;
+        meta_arrays.baseline_array = baseline_array;
+        meta_arrays.freq_array = freq_array;
+        meta_arrays.spw_id_array = spw_id_array;
+        meta_arrays.polarization_array = polarization_array;
+        meta_arrays.integration_time = integration_time;
+        meta_arrays.channel_width = channel_width;
+        meta_arrays.antenna_numbers = antenna_numbers;
+        meta_arrays.antenna_names = antenna_names;
+        meta_arrays.antenna_positions = antenna_positions;
+        meta_arrays.eq_coeffs = eq_coeffs;
+        meta_arrays.antenna_diameters = antenna_diameters;
+        meta_arrays.phase_center_catalog = phase_center_catalog;
+        meta_arrays.phase_center_id_array = phase_center_id_array;
         // optional data read
         let (data_array, nsample_array, flag_array) = match read_data {
             true => {
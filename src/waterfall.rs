@@ -0,0 +1,278 @@
+use approx::AbsDiffEq;
+use ndarray::{s, Array, Axis, CowArray, Ix1, Ix2, Ix3, Slice};
+use num_complex::Complex;
+use num_traits::Float;
+use std::collections::BTreeSet;
+
+use super::base::{IntoPolNum, Polarization};
+use super::UVData;
+
+// the blt indices as a slice if they are evenly spaced and increasing
+fn strided_slice(inds: &[usize]) -> Option<Slice> {
+    let step = match inds.len() {
+        0 => return None,
+        1 => 1,
+        _ => inds[1] as isize - inds[0] as isize,
+    };
+    match step > 0
+        && inds
+            .windows(2)
+            .all(|pair| pair[1] as isize - pair[0] as isize == step)
+    {
+        true => Some(Slice::new(
+            inds[0] as isize,
+            Some(inds[inds.len() - 1] as isize + 1),
+            step,
+        )),
+        false => None,
+    }
+}
+
+fn select_waterfall<'a, A: Clone>(
+    array: &'a Array<A, Ix3>,
+    inds: &[usize],
+    pol_ind: usize,
+) -> CowArray<'a, A, Ix2> {
+    let pol_view = array.index_axis(Axis(2), pol_ind);
+    match strided_slice(inds) {
+        Some(slice) => CowArray::from(pol_view.slice_move(s![slice, ..])),
+        None => CowArray::from(pol_view.select(Axis(0), inds)),
+    }
+}
+
+fn select_rows<'a, A: Clone>(array: &'a Array<A, Ix1>, inds: &[usize]) -> CowArray<'a, A, Ix1> {
+    match strided_slice(inds) {
+        Some(slice) => CowArray::from(array.slice(s![slice])),
+        None => CowArray::from(array.select(Axis(0), inds)),
+    }
+}
+
+// cross polarizations swap when the antenna order is reversed
+fn reversed_pol(pol: i8) -> i8 {
+    match pol {
+        -3 => -4,
+        -4 => -3,
+        -7 => -8,
+        -8 => -7,
+        other => other,
+    }
+}
+
+impl<T, S> UVData<T, S>
+where
+    T: Float + AbsDiffEq,
+    S: Float + AbsDiffEq,
+{
+    /// The time ordered blts of the antenna pair, and whether the pair is
+    /// stored in the reverse order.
+    fn oriented_antpair_blts(&self, ant1: u32, ant2: u32) -> Result<(Vec<usize>, bool), String> {
+        let inds = self.antpair_blts(ant1, ant2);
+        if !inds.is_empty() {
+            return Ok((inds, false));
+        }
+        let inds = self.antpair_blts(ant2, ant1);
+        match inds.is_empty() {
+            true => Err(format!(
+                "Antenna pair ({}, {}) is not in the data.",
+                ant1, ant2
+            )),
+            false => Ok((inds, true)),
+        }
+    }
+
+    /// The blts and polarization index of a waterfall, and whether it must
+    /// be conjugated.
+    fn waterfall_inds<P: IntoPolNum>(
+        &self,
+        ant1: u32,
+        ant2: u32,
+        pol: P,
+    ) -> Result<(Vec<usize>, usize, bool), String> {
+        let (inds, reversed) = self.oriented_antpair_blts(ant1, ant2)?;
+        let mut num = pol.pol_num(self.meta.x_orientation)?;
+        if reversed {
+            num = reversed_pol(num);
+        }
+        let pol_ind = self
+            .meta_arrays
+            .polarization_array
+            .iter()
+            .position(|&x| x == num)
+            .ok_or(format!("Polarization {} is not in the data.", num))?;
+        Ok((inds, pol_ind, reversed))
+    }
+
+    /// The (Ntimes, Nfreqs) visibilities of an antenna pair and polarization.
+    ///
+    /// A view into `data_array` is returned when possible. If only the
+    /// reversed pair is stored, its conjugate is returned as an owned array.
+    pub fn get_data<P: IntoPolNum>(
+        &self,
+        ant1: u32,
+        ant2: u32,
+        pol: P,
+    ) -> Result<CowArray<'_, Complex<T>, Ix2>, String> {
+        let data = self
+            .data_array
+            .as_ref()
+            .ok_or("The data array is not loaded.")?;
+        let (inds, pol_ind, reversed) = self.waterfall_inds(ant1, ant2, pol)?;
+        let waterfall = select_waterfall(data, &inds, pol_ind);
+        match reversed {
+            true => Ok(CowArray::from(waterfall.mapv(|vis| vis.conj()))),
+            false => Ok(waterfall),
+        }
+    }
+
+    /// The (Ntimes, Nfreqs) flags of an antenna pair and polarization.
+    pub fn get_flags<P: IntoPolNum>(
+        &self,
+        ant1: u32,
+        ant2: u32,
+        pol: P,
+    ) -> Result<CowArray<'_, bool, Ix2>, String> {
+        let flags = self
+            .flag_array
+            .as_ref()
+            .ok_or("The flag array is not loaded.")?;
+        let (inds, pol_ind, _) = self.waterfall_inds(ant1, ant2, pol)?;
+        Ok(select_waterfall(flags, &inds, pol_ind))
+    }
+
+    /// The (Ntimes, Nfreqs) nsamples of an antenna pair and polarization.
+    pub fn get_nsamples<P: IntoPolNum>(
+        &self,
+        ant1: u32,
+        ant2: u32,
+        pol: P,
+    ) -> Result<CowArray<'_, S, Ix2>, String> {
+        let nsamples = self
+            .nsample_array
+            .as_ref()
+            .ok_or("The nsample array is not loaded.")?;
+        let (inds, pol_ind, _) = self.waterfall_inds(ant1, ant2, pol)?;
+        Ok(select_waterfall(nsamples, &inds, pol_ind))
+    }
+
+    /// The times (JD) of an antenna pair, in either order.
+    pub fn get_times(&self, ant1: u32, ant2: u32) -> Result<CowArray<'_, f64, Ix1>, String> {
+        let (inds, _) = self.oriented_antpair_blts(ant1, ant2)?;
+        Ok(select_rows(&self.meta_arrays.time_array, &inds))
+    }
+
+    /// The lsts (radians) of an antenna pair, in either order.
+    pub fn get_lsts(&self, ant1: u32, ant2: u32) -> Result<CowArray<'_, f64, Ix1>, String> {
+        let (inds, _) = self.oriented_antpair_blts(ant1, ant2)?;
+        Ok(select_rows(&self.meta_arrays.lst_array, &inds))
+    }
+
+    /// The unique antenna pairs in the data, sorted.
+    pub fn antpairs(&self) -> impl Iterator<Item = (u32, u32)> {
+        self.meta_arrays
+            .ant_1_array
+            .iter()
+            .copied()
+            .zip(self.meta_arrays.ant_2_array.iter().copied())
+            .collect::<BTreeSet<(u32, u32)>>()
+            .into_iter()
+    }
+
+    /// The unique baseline numbers in the data, sorted.
+    pub fn baselines(&self) -> impl Iterator<Item = u32> {
+        self.meta_arrays
+            .baseline_array
+            .iter()
+            .copied()
+            .collect::<BTreeSet<u32>>()
+            .into_iter()
+    }
+
+    /// The polarizations in the data, in the order of `polarization_array`.
+    pub fn pols(&self) -> impl Iterator<Item = Polarization> {
+        self.meta_arrays
+            .polarization_array
+            .iter()
+            .filter_map(|&num| Polarization::from_num(num).ok())
+            .collect::<Vec<Polarization>>()
+            .into_iter()
+    }
+
+    /// Every (ant1, ant2, pol) combination in the data.
+    pub fn antpairpols(&self) -> impl Iterator<Item = (u32, u32, Polarization)> {
+        let pols: Vec<Polarization> = self.pols().collect();
+        self.antpairs()
+            .flat_map(move |(ant1, ant2)| {
+                pols.iter()
+                    .map(|&pol| (ant1, ant2, pol))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::strided_slice;
+    use crate::base::Polarization;
+    use crate::test_data::read_drift;
+    use ndarray::{s, Slice};
+
+    #[test]
+    fn strided_inds() {
+        assert_eq!(strided_slice(&[3, 5, 7]), Some(Slice::new(3, Some(8), 2)));
+        assert_eq!(strided_slice(&[4]), Some(Slice::new(4, Some(5), 1)));
+        assert_eq!(strided_slice(&[1, 2, 4]), None);
+        assert_eq!(strided_slice(&[5, 3]), None);
+    }
+
+    #[test]
+    fn waterfall_is_view() {
+        let uvd = read_drift();
+        let waterfall = uvd.get_data(0, 1, "nn").expect("Cannot get data.");
+        assert!(waterfall.is_view());
+        assert_eq!(waterfall.shape(), &[20, 4]);
+
+        let blts: Vec<usize> = (0..200)
+            .filter(|&blt| {
+                uvd.meta_arrays.ant_1_array[blt] == 0 && uvd.meta_arrays.ant_2_array[blt] == 1
+            })
+            .collect();
+        let data = uvd.data_array.as_ref().unwrap();
+        for (row, &blt) in blts.iter().enumerate() {
+            assert_eq!(waterfall.row(row), data.slice(s![blt, .., 0]));
+        }
+        let times = uvd.get_times(1, 0).expect("Cannot get times.");
+        assert_eq!(times.len(), 20);
+        assert_eq!(times[3], uvd.meta_arrays.time_array[blts[3]]);
+    }
+
+    #[test]
+    fn reversed_pair_is_conjugated() {
+        let uvd = read_drift();
+        let data = uvd.get_data(0, 1, -5i8).expect("Cannot get data.");
+        let reversed = uvd.get_data(1, 0, -5i8).expect("Cannot get data.");
+        assert!(!reversed.is_view());
+        assert_eq!(reversed.to_owned(), data.mapv(|vis| vis.conj()));
+        assert_eq!(
+            uvd.get_flags(1, 0, Polarization::XX).unwrap(),
+            uvd.get_flags(0, 1, Polarization::XX).unwrap()
+        );
+        assert!(uvd.get_data(0, 12, -5i8).is_err());
+        assert!(uvd.get_nsamples(0, 1, "pI").is_err());
+    }
+
+    #[test]
+    fn iterators() {
+        let uvd = read_drift();
+        assert_eq!(uvd.antpairs().count(), 10);
+        assert_eq!(uvd.baselines().count(), 10);
+        assert_eq!(
+            uvd.pols().collect::<Vec<_>>(),
+            vec![Polarization::XX, Polarization::YY]
+        );
+        let antpairpols: Vec<_> = uvd.antpairpols().collect();
+        assert_eq!(antpairpols.len(), 20);
+        assert!(antpairpols.contains(&(2, 1, Polarization::YY)));
+    }
+}
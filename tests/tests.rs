@@ -42,18 +42,18 @@ fn init_metadata_false() {
     };
     let test_data = Array3::<Complex<f64>>::zeros((
         meta.nblts as usize,
-        meta.ntimes as usize,
+        meta.nfreqs as usize,
         meta.npols as usize,
     ));
     let test_nsample = Array3::<f32>::zeros((
         meta.nblts as usize,
-        meta.ntimes as usize,
+        meta.nfreqs as usize,
         meta.npols as usize,
     ));
     let test_flag = Array3::<bool>::from_elem(
         (
             meta.nblts as usize,
-            meta.ntimes as usize,
+            meta.nfreqs as usize,
             meta.npols as usize,
         ),
         false,
@@ -98,18 +98,18 @@ fn from_uvmeta_bool() {
     };
     let test_data = Array3::<Complex<f64>>::zeros((
         meta.nblts as usize,
-        meta.ntimes as usize,
+        meta.nfreqs as usize,
         meta.npols as usize,
     ));
     let test_nsample = Array3::<f32>::zeros((
         meta.nblts as usize,
-        meta.ntimes as usize,
+        meta.nfreqs as usize,
         meta.npols as usize,
     ));
     let test_flag = Array3::<bool>::from_elem(
         (
             meta.nblts as usize,
-            meta.ntimes as usize,
+            meta.nfreqs as usize,
             meta.npols as usize,
         ),
         false,
@@ -193,7 +193,7 @@ fn init_metadata_false_f32() {
     let test_data = Array3::<Complex<f32>>::from_elem(
         (
             meta.nblts as usize,
-            meta.ntimes as usize,
+            meta.nfreqs as usize,
             meta.npols as usize,
         ),
         Complex { re: 2.0, im: -3.2 },
@@ -201,7 +201,7 @@ fn init_metadata_false_f32() {
     let test_nsample = Array3::<f32>::from_elem(
         (
             meta.nblts as usize,
-            meta.ntimes as usize,
+            meta.nfreqs as usize,
             meta.npols as usize,
         ),
         3.1415,
@@ -209,7 +209,7 @@ fn init_metadata_false_f32() {
     let test_flag = Array3::<bool>::from_elem(
         (
             meta.nblts as usize,
-            meta.ntimes as usize,
+            meta.nfreqs as usize,
             meta.npols as usize,
         ),
         false,
@@ -344,3 +344,26 @@ fn test_latlonalt_fn() {
         assert_abs_diff_eq!(x1, x2, epsilon = 1e-3)
     }
 }
+
+#[test]
+fn test_roundtrip_bda() {
+    let outdir = TempDir::new("bda_test").expect("Unable to create temporary test directory");
+    let data_file = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/test_drift.uvh5");
+    let mut uvd = UVData::<f64, f32>::read_uvh5(data_file, true).expect("Unable to read file");
+    uvd.apply_bda(1e-4, 30.0, true)
+        .expect("Unable to apply bda");
+    let outpath = outdir.path().join("bda.uvh5");
+    uvd.clone()
+        .write_uvh5(&outpath, true)
+        .expect("Unable to write bda file");
+    let mut uvd2 = UVData::<f64, f32>::read_uvh5(&outpath, true).expect("Unable to read bda file");
+    uvd2.meta.history = uvd.meta.history.clone();
+    assert_eq!(
+        uvd2.meta.blt_order,
+        BltOrder {
+            major: BltOrders::Bda,
+            minor: BltOrders::Bda
+        }
+    );
+    assert_eq!(uvd, uvd2);
+}